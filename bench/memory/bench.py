@@ -6,9 +6,13 @@ import os
 chrome_options = Options()
 chrome_options.add_argument("--headless")
 
+# root/vendor/webpl-enum is built from the last commit before the heap was
+# packed into 64-bit cells (e58dd96~1), to show how much memory that saves
 ENGINES = [
     ("WebPL", "/webpl.html"),
     ("WebPL (GC)", "/webpl-gc.html"),
+    ("WebPL (enum cells)", "/webpl-enum.html"),
+    ("WebPL (enum cells, GC)", "/webpl-enum-gc.html"),
     ("SWI-Prolog", "/swipl.html"),
     ("Trealla Prolog", "/trealla.html"),
     ("Tau Prolog", "/tau.html"),
@@ -1,44 +1,269 @@
-use crate::{Error, ErrorLocation};
+use crate::flags::{DoubleQuotes, Flags};
+use crate::{Error, ErrorKind, ErrorLocation};
 
 use lalrpop_util::lexer::Token;
+use serde::{Deserialize, Serialize};
+use std::num::IntErrorKind;
 
-#[derive(Debug)]
-pub struct Program(pub Vec<Clause>);
+// Clauses, lines, directives and the flags in effect at the end of the program
+#[derive(Clone, Debug)]
+pub struct Program(pub Vec<Clause>, pub Lines, pub Vec<Directive>, pub Flags);
 
-pub struct Query(pub Vec<Term>);
+// Goals and the lines of the query
+pub struct Query(pub Vec<Term>, pub Lines);
 
-#[derive(Debug)]
-pub struct Clause(pub Term, pub Vec<Term>);
+// Head, body and the span of the whole clause
+#[derive(Clone, Debug)]
+pub struct Clause(pub Term, pub Vec<Term>, pub Span);
 
-#[derive(Debug)]
+// Goals, span, and the number of clauses before it
+#[derive(Clone, Debug)]
+pub struct Directive(pub Vec<Term>, pub Span, pub usize);
+
+pub enum Item {
+    Clause(Clause),
+    Directive(Vec<Term>, Span),
+}
+
+// An error found by a grammar action, such as an undefined escape sequence
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UserError(pub Span, pub &'static str);
+
+// Start and end byte offsets in the source
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span(pub usize, pub usize);
+
+// Offsets at which each line after the first starts, and the offset and
+// length in bytes of each character outside ASCII, to find the line and
+// column of a span once the source is gone
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Lines(Vec<usize>, Vec<(usize, u8)>);
+
+// A term read from a stream and the offset after its full stop, or just the
+// offset after a term with a syntax error
+pub type Read = Result<(Term, usize), usize>;
+
+// Each term ends with its span in the source. Terms that aren't written there,
+// like those of translated grammar rules, have the span of what they stand for.
+#[derive(Clone, Debug)]
 pub enum Term {
-    Atom(Atom),
-    Variable(String),
-    Compound(String, Vec<Term>),
-    Cut,
-    Lambda(String, Vec<String>),
+    Atom(Atom, Span),
+    Variable(String, Span),
+    Compound(String, Vec<Term>, Span),
+    Cut(Span),
+    Lambda(String, Vec<String>, Span),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Atom {
     String(String),
     Integer(i64),
     Float(f64),
+    Text(String),
+}
+
+impl Program {
+    // Lines are filled in by the caller, which has the whole input
+    pub fn new(items: Vec<Item>, flags: Flags) -> Self {
+        let mut clauses = Vec::new();
+        let mut directives = Vec::new();
+
+        for item in items {
+            match item {
+                Item::Clause(clause) => clauses.push(clause),
+                Item::Directive(goals, span) => {
+                    directives.push(Directive(goals, span, clauses.len()))
+                }
+            }
+        }
+
+        Program(clauses, Lines::default(), directives, flags)
+    }
+
+    // Predicates declared with `:- dynamic(Name/Arity)` or a list of them,
+    // which have no clauses rather than being unknown
+    pub fn dynamic(&self) -> Vec<(&str, usize)> {
+        self.2.iter().flat_map(Directive::dynamic).collect()
+    }
+}
+
+impl Directive {
+    // The predicates this directive declares dynamic
+    pub fn dynamic(&self) -> Vec<(&str, usize)> {
+        let mut predicates = Vec::new();
+
+        for goal in &self.0 {
+            if let Term::Compound(name, args, _) = goal {
+                if name == "dynamic" && args.len() == 1 {
+                    predicate_indicators(&args[0], &mut predicates);
+                }
+            }
+        }
+
+        predicates
+    }
+}
+
+// Flags that change how the rest of the program reads take effect as soon as
+// their directive is parsed
+pub fn set_flags(goals: &[Term], flags: &std::cell::Cell<Flags>) {
+    for goal in goals {
+        if let Term::Compound(name, args, _) = goal {
+            if let [Term::Atom(Atom::String(flag), _), Term::Atom(Atom::String(value), _)] =
+                &args[..]
+            {
+                if name == "set_prolog_flag" && flag == "double_quotes" {
+                    if let Some(double_quotes) = DoubleQuotes::parse(value) {
+                        flags.set(Flags {
+                            double_quotes,
+                            ..flags.get()
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn predicate_indicators<'a>(term: &'a Term, predicates: &mut Vec<(&'a str, usize)>) {
+    match term {
+        Term::Compound(slash, args, _) if slash == "/" && args.len() == 2 => {
+            if let [Term::Atom(Atom::String(name), _), Term::Atom(Atom::Integer(arity), _)] =
+                &args[..]
+            {
+                if let Ok(arity) = usize::try_from(*arity) {
+                    predicates.push((name, arity));
+                }
+            }
+        }
+        Term::Compound(dot, args, _) if dot == "." && args.len() == 2 => {
+            predicate_indicators(&args[0], predicates);
+            predicate_indicators(&args[1], predicates);
+        }
+        _ => {}
+    }
+}
+
+impl Lines {
+    pub fn new(input: &str) -> Self {
+        Lines(
+            input.match_indices('\n').map(|(i, _)| i + 1).collect(),
+            input
+                .char_indices()
+                .filter(|(_, c)| !c.is_ascii())
+                .map(|(i, c)| (i, c.len_utf8() as u8))
+                .collect(),
+        )
+    }
+
+    // The line and column (counted in characters) of a byte offset, starting at 1
+    pub fn locate(&self, offset: usize) -> (usize, usize) {
+        let line = self.0.partition_point(|start| *start <= offset);
+        let start = line.checked_sub(1).map_or(0, |i| self.0[i]);
+        let extra: usize = self.wide(start, offset).map(|len| len - 1).sum();
+        (line + 1, offset - start - extra + 1)
+    }
+
+    // A byte offset in UTF-16 code units, as JavaScript strings count them
+    pub fn utf16(&self, offset: usize) -> usize {
+        // Characters of four bytes take two code units, and others one
+        let extra: usize = self
+            .wide(0, offset)
+            .map(|len| if len == 4 { len - 2 } else { len - 1 })
+            .sum();
+        offset - extra
+    }
+
+    // The lengths of the characters outside ASCII between two byte offsets
+    fn wide(&self, start: usize, end: usize) -> impl Iterator<Item = usize> + '_ {
+        let first = self.1.partition_point(|(i, _)| *i < start);
+        self.1[first..]
+            .iter()
+            .take_while(move |(i, _)| *i < end)
+            .map(|(_, len)| *len as usize)
+    }
 }
 
 impl Term {
-    // Support syntactic sugar for lists.
-    pub fn list(terms: Vec<Term>, tail: Option<Term>) -> Term {
-        let mut term = tail.unwrap_or(Term::Atom(Atom::String("[]".to_string())));
+    pub fn span(&self) -> Span {
+        match self {
+            Term::Atom(_, span)
+            | Term::Variable(_, span)
+            | Term::Compound(_, _, span)
+            | Term::Cut(span)
+            | Term::Lambda(_, _, span) => *span,
+        }
+    }
 
-        for t in terms.into_iter().rev() {
-            term = Term::Compound(".".to_string(), vec![t, term]);
+    // Support syntactic sugar for lists. The list spans all of it, and the
+    // cells after the first from their element to its end.
+    pub fn list(terms: Vec<Term>, tail: Option<Term>, span: Span) -> Term {
+        let mut term = tail.unwrap_or(Term::Atom(Atom::String("[]".to_string()), span));
+
+        for (i, t) in terms.into_iter().enumerate().rev() {
+            let start = if i == 0 { span.0 } else { t.span().0 };
+            term = Term::Compound(".".to_string(), vec![t, term], Span(start, span.1));
         }
 
         term
     }
 
-    pub fn parse_lambda(js_str: &str) -> Result<Term, &'static str> {
+    // Goals in braces, which read as `'{}'/1` applied to their conjunction
+    pub fn braces(goals: Vec<Term>, span: Span) -> Term {
+        let conjunction = goals.into_iter().rev().reduce(|rest, goal| {
+            let span = Span(goal.span().0, rest.span().1);
+            Term::Compound(",".to_string(), vec![goal, rest], span)
+        });
+
+        match conjunction {
+            Some(goals) => Term::Compound("{}".to_string(), vec![goals], span),
+            None => Term::Atom(Atom::String("{}".to_string()), span),
+        }
+    }
+
+    // The goals of a conjunction, in order
+    pub fn conjuncts(self) -> Vec<Term> {
+        let mut goals = Vec::new();
+        let mut term = self;
+
+        loop {
+            match term {
+                Term::Compound(name, mut args, _) if name == "," && args.len() == 2 => {
+                    let rest = args.pop().unwrap();
+                    goals.push(args.pop().unwrap());
+                    term = rest;
+                }
+                goal => {
+                    goals.push(goal);
+                    return goals;
+                }
+            }
+        }
+    }
+
+    // Text in double quotes, which reads as codes, chars, an atom or a string
+    pub fn double_quoted(text: String, double_quotes: DoubleQuotes, span: Span) -> Term {
+        match double_quotes {
+            DoubleQuotes::Codes => Term::list(
+                text.chars()
+                    .map(|c| Term::Atom(Atom::Integer(c as i64), span))
+                    .collect(),
+                None,
+                span,
+            ),
+            DoubleQuotes::Chars => Term::list(
+                text.chars()
+                    .map(|c| Term::Atom(Atom::String(c.to_string()), span))
+                    .collect(),
+                None,
+                span,
+            ),
+            DoubleQuotes::Atom => Term::Atom(Atom::String(text), span),
+            DoubleQuotes::String => Term::Atom(Atom::Text(text), span),
+        }
+    }
+
+    pub fn parse_lambda(js_str: &str, span: Span) -> Result<Term, &'static str> {
         let js = js_str.as_bytes();
 
         let mut vars = Vec::new();
@@ -113,75 +338,256 @@ impl Term {
             "return ".to_string() + js_str[i..js_str.len() - 2].trim()
         };
 
-        Ok(Term::Lambda(js, vars))
+        Ok(Term::Lambda(js, vars, span))
     }
 }
 
+// The text of a quoted atom or string, given with its quotes, with escape
+// sequences replaced and doubled quotes standing for one quote
+pub fn unquote(literal: &str) -> Result<String, &'static str> {
+    let quote = literal.chars().next().unwrap_or('\'');
+    let mut chars = literal[1..literal.len() - 1].chars().peekable();
+    let mut text = String::with_capacity(literal.len());
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(escape(&mut chars)?),
+            c if c == quote => {
+                // The lexer only accepts quotes inside when they are doubled
+                chars.next();
+                text.push(c);
+            }
+            c => text.push(c),
+        }
+    }
+
+    Ok(text)
+}
+
+// Reads a number token, which may have digit groups, an exponent, a `0x`,
+// `0o` or `0b` prefix, or be written as `Radix'Digits`
+pub fn number(literal: &str) -> Result<Atom, &'static str> {
+    let (digits, radix) = if let Some(digits) = literal.strip_prefix("0x") {
+        (digits, 16)
+    } else if let Some(digits) = literal.strip_prefix("0o") {
+        (digits, 8)
+    } else if let Some(digits) = literal.strip_prefix("0b") {
+        (digits, 2)
+    } else if let Some((radix, digits)) = literal.split_once('\'') {
+        (digits, radix.parse().map_err(|_| "Invalid radix")?)
+    } else if literal.contains(['.', 'e', 'E']) {
+        let float: f64 = literal.parse().map_err(|_| "Invalid float")?;
+        return match float.is_finite() {
+            true => Ok(Atom::Float(float)),
+            false => Err("Float overflow"),
+        };
+    } else {
+        (literal, 10)
+    };
+
+    let digits = digits.replace(['_', ' '], "");
+    i64::from_str_radix(&digits, radix)
+        .map(Atom::Integer)
+        .map_err(|e| match e.kind() {
+            IntErrorKind::PosOverflow => "Integer overflow",
+            _ => "Invalid digit for the radix",
+        })
+}
+
+// The code of the character in `0'c`
+pub fn char_code(literal: &str) -> Result<i64, &'static str> {
+    let mut chars = literal[2..].chars().peekable();
+
+    let c = match chars.next() {
+        Some('\\') => escape(&mut chars)?.ok_or("Expected a character after `0'`")?,
+        Some(c) => c,
+        None => return Err("Expected a character after `0'`"),
+    };
+
+    Ok(c as i64)
+}
+
+// Reads an escape sequence after its backslash. A backslash before a newline
+// continues the text on the next line, so reads no character.
+fn escape(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Option<char>, &'static str> {
+    let c = match chars.next().ok_or("Undefined escape sequence")? {
+        'a' => '\x07',
+        'b' => '\x08',
+        'f' => '\x0c',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        'v' => '\x0b',
+        'e' => '\x1b',
+        's' => ' ',
+        '\n' => return Ok(None),
+        c @ ('\\' | '\'' | '"' | '`') => c,
+        c @ ('x' | '0'..='7') => {
+            let radix = if c == 'x' { 16 } else { 8 };
+            let mut digits = String::new();
+            if c != 'x' {
+                digits.push(c);
+            }
+            for c in chars.by_ref() {
+                if c == '\\' {
+                    break;
+                }
+                digits.push(c);
+            }
+
+            u32::from_str_radix(&digits, radix)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or("Invalid character code")?
+        }
+        _ => return Err("Undefined escape sequence"),
+    };
+
+    Ok(Some(c))
+}
+
 pub fn parse_error(
     input: &str,
     query: bool,
-    error: lalrpop_util::ParseError<usize, Token<'_>, &str>,
+    error: lalrpop_util::ParseError<usize, Token<'_>, UserError>,
 ) -> Error {
-    match error {
+    let (message, culprit, expected, start, end) = match error {
         lalrpop_util::ParseError::InvalidToken { location } => {
-            with_location("Invalid token".into(), input, query, location)
-        }
-        lalrpop_util::ParseError::UnrecognizedEof {
-            location,
-            expected: _,
-        } => with_location(
-            "Unexpected end of file, did you forget a '.'?".into(),
-            input,
-            query,
-            location,
-        ),
-        lalrpop_util::ParseError::UnrecognizedToken { token, expected: _ } => with_location(
-            format!("Unexpected token `{}`", &input[token.0..token.2]),
-            input,
-            query,
-            token.0,
-        ),
-        lalrpop_util::ParseError::ExtraToken { token } => with_location(
-            format!("Extra token `{}`", &input[token.0..token.2]),
-            input,
-            query,
-            token.0,
-        ),
-        lalrpop_util::ParseError::User { error } => Error {
-            location: None,
-            error: error.to_string(),
-        },
-    }
-}
+            let end = next_char(input, location);
+            let token = &input[location..end];
+            ("Invalid token".into(), Some(token), None, location, end)
+        }
+        lalrpop_util::ParseError::UnrecognizedEof { location, expected } => {
+            let expected = describe_expected(&expected);
+            let message = match &expected {
+                Some(expected) if !expected.contains("`.`") => {
+                    format!("Unexpected end of file, expected {}", expected)
+                }
+                _ => "Unexpected end of file, did you forget a '.'?".into(),
+            };
+            (message, None, expected, location, location)
+        }
+        lalrpop_util::ParseError::UnrecognizedToken { token, expected } => {
+            let culprit = &input[token.0..token.2];
+            let expected = describe_expected(&expected);
+            let message = match &expected {
+                Some(expected) => format!("Unexpected token `{}`, expected {}", culprit, expected),
+                None => format!("Unexpected token `{}`", culprit),
+            };
+            (message, Some(culprit), expected, token.0, token.2)
+        }
+        lalrpop_util::ParseError::ExtraToken { token } => {
+            let culprit = &input[token.0..token.2];
+            let message = format!("Extra token `{}`", culprit);
+            (message, Some(culprit), None, token.0, token.2)
+        }
+        lalrpop_util::ParseError::User {
+            error: UserError(span, message),
+        } => (message.to_string(), None, None, span.0, span.1),
+    };
 
-fn with_location(error: String, input: &str, query: bool, offset: usize) -> Error {
-    Error {
-        location: Some(get_location(input, query, offset)),
-        error,
-    }
+    let mut error = with_location(message, input, query, start, end);
+    let location = error.location.as_ref().unwrap();
+    let context = crate::Term::compound(
+        ":",
+        vec![
+            crate::Term::Number(location.line as f64),
+            crate::Term::Number(location.column as f64),
+        ],
+    );
+    let formal = crate::Term::compound("syntax_error", vec![crate::Term::atom(&error.error)]);
+
+    error = error.with_term(formal, context);
+    error.culprit = culprit.map(str::to_string);
+    error.expected = expected;
+    error
 }
 
-fn get_location(input: &str, query: bool, offset: usize) -> ErrorLocation {
-    let mut line = 1;
-    let mut column = 1;
+// Lists the tokens LALRPOP expected, e.g. "`,`, `.` or `:-`". Where any term
+// could start, its first tokens are summarised as "a term".
+fn describe_expected(expected: &[String]) -> Option<String> {
+    let term = expected
+        .iter()
+        .any(|token| token_name(token) == "a variable");
 
-    for (i, c) in input.chars().enumerate() {
-        if i == offset {
-            break;
+    let mut names: Vec<String> = Vec::new();
+    for token in expected {
+        let mut name = token_name(token);
+        if term && starts_term(&name) {
+            name = "a term".into();
         }
-
-        if c == '\n' {
-            line += 1;
-            column = 1;
-        } else {
-            column += 1;
+        if !names.contains(&name) {
+            names.push(name);
         }
     }
 
-    ErrorLocation {
-        query,
-        offset,
-        line,
-        column,
+    names.sort_by_key(|name| !name.starts_with('`'));
+
+    let (last, rest) = names.split_last()?;
+    if rest.is_empty() {
+        Some(last.clone())
+    } else {
+        Some(format!("{} or {}", rest.join(", "), last))
+    }
+}
+
+// Literal tokens are quoted, and regular expressions are named after the
+// terminals of the grammar that use them
+fn token_name(token: &str) -> String {
+    let Some(regex) = token.strip_prefix("r#\"") else {
+        return format!("`{}`", token.trim_matches('"'));
+    };
+
+    let name = if regex.starts_with(r"[\\p{Lu}") {
+        "a variable"
+    } else if regex.starts_with("[0-9]") || regex.starts_with(['0', '(']) {
+        "a number"
+    } else if regex.starts_with(['\'', '[', '#']) {
+        "an atom"
+    } else if regex.starts_with('"') {
+        "a string"
+    } else if regex.starts_with("<\\{") {
+        "a lambda"
+    } else {
+        "an operator"
+    };
+
+    name.into()
+}
+
+fn starts_term(name: &str) -> bool {
+    matches!(
+        name,
+        "`(`"
+            | "`[`"
+            | "`[]`"
+            | "`{`"
+            | "`;`"
+            | "`!`"
+            | r"`\\+`"
+            | "an atom"
+            | "a variable"
+            | "a number"
+            | "a string"
+            | "a lambda"
+            | "an operator"
+    )
+}
+
+fn next_char(input: &str, offset: usize) -> usize {
+    input[offset..]
+        .chars()
+        .next()
+        .map_or(offset, |c| offset + c.len_utf8())
+}
+
+fn with_location(error: String, input: &str, query: bool, start: usize, end: usize) -> Error {
+    Error {
+        location: Some(Box::new(ErrorLocation::new(
+            &Lines::new(input),
+            Span(start, end),
+            query,
+        ))),
+        ..Error::new(ErrorKind::Syntax, error)
     }
 }
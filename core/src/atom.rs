@@ -1,11 +1,15 @@
 use crate::stringmap::StringMap;
 use crate::{ast, StringId};
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum Atom {
     String(StringId),
     Integer(i64),
     Float(f64),
+    // A string, as double quotes read when the `double_quotes` flag is `string`
+    Text(StringId),
 }
 
 impl Atom {
@@ -14,14 +18,17 @@ impl Atom {
             ast::Atom::String(s) => Atom::String(string_map.alloc(s)),
             ast::Atom::Integer(n) => Atom::Integer(*n),
             ast::Atom::Float(n) => Atom::Float(*n),
+            ast::Atom::Text(s) => Atom::Text(string_map.alloc(s)),
         }
     }
 
+    // Atoms are quoted and strings are double quoted, so they read back the same
     pub fn to_string(self, string_map: &StringMap) -> String {
         match self {
-            Atom::String(id) => string_map.get(id).unwrap().to_string(),
+            Atom::String(id) => quote(string_map.get(id).unwrap()),
             Atom::Integer(integer) => integer.to_string(),
-            Atom::Float(float) => float.to_string(),
+            Atom::Float(float) => format_float(float),
+            Atom::Text(id) => quote_string(string_map.get(id).unwrap()),
         }
     }
 
@@ -29,3 +36,68 @@ impl Atom {
         matches!(self, Atom::String(crate::stringmap::str::NIL))
     }
 }
+
+// Writes an atom in single quotes unless it is a name, a run of symbol
+// characters or a solo atom like `[]`
+pub fn quote(name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = match chars.next() {
+        // Letters without case start atoms too, as in `λ` or `日本`
+        Some(c) if c.is_alphabetic() && !c.is_uppercase() => {
+            chars.all(|c| c.is_alphanumeric() || c == '_')
+        }
+        Some(_) if matches!(name, "[]" | "!" | ";" | "{}") => true,
+        // Longer runs can't end in a sign or a backslash, which would read apart
+        Some(_) => {
+            name.chars().all(|c| SYMBOL_CHARS.contains(c))
+                && (name.len() == 1 || !name.ends_with(['+', '-', '\\']))
+        }
+        None => false,
+    };
+
+    if plain {
+        name.to_string()
+    } else {
+        quote_with(name, '\'')
+    }
+}
+
+// Floats always have a fraction, so `1.0` and `1.0e20` don't read back as integers
+pub fn format_float(float: f64) -> String {
+    let text = format!("{:?}", float);
+
+    match text.split_once('e') {
+        Some((mantissa, exponent)) if !mantissa.contains('.') => {
+            format!("{}.0e{}", mantissa, exponent)
+        }
+        _ => text,
+    }
+}
+
+pub fn quote_string(text: &str) -> String {
+    quote_with(text, '"')
+}
+
+const SYMBOL_CHARS: &str = "+-*/\\^<>=~:.?@#&$";
+
+fn quote_with(text: &str, quote: char) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push(quote);
+
+    for c in text.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            c if c == quote => {
+                result.push('\\');
+                result.push(c);
+            }
+            c if c.is_control() => result.push_str(&format!("\\x{:x}\\", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result.push(quote);
+    result
+}
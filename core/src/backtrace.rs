@@ -0,0 +1,63 @@
+use crate::goal::Caller;
+use crate::{Frame, Solver};
+
+// Deeper recursion is left out of backtraces, keeping the innermost frames
+const MAX_FRAMES: usize = 64;
+// How deeply the arguments of goals are printed
+const MAX_DEPTH: usize = 4;
+
+impl Solver {
+    // Reconstructs the goals that are running from the continuation. Each goal
+    // keeps the goal that called its clause, so the callers of the goals still
+    // to run are the ancestors of the current one. A clause whose last goal is
+    // running has no goals left, so the goal that called it doesn't appear
+    // unless tracing keeps an exit marker for it. Neither does a caller that
+    // the garbage collector has reclaimed.
+    pub(crate) fn backtrace(&self) -> Vec<Frame> {
+        let Some(current) = self.goals.current else {
+            return Vec::new();
+        };
+        let goals = &self.goals.goals;
+
+        let mut frames = vec![Frame {
+            goal: self
+                .heap
+                .serialize_bounded(goals[current].term(), MAX_DEPTH),
+            location: self.goal_location(),
+        }];
+
+        let mut last = None;
+        let mut next = Some(current);
+
+        while let Some(ptr) = next {
+            if frames.len() == MAX_FRAMES {
+                break;
+            }
+
+            let goal = goals[ptr];
+            next = goal.prev_ptr();
+
+            let Some(Caller(term, origin)) = goal.caller() else {
+                continue;
+            };
+            if last == goal.caller() {
+                continue;
+            }
+            last = goal.caller();
+
+            frames.push(Frame {
+                goal: self.heap.serialize_bounded(term, MAX_DEPTH),
+                location: origin.and_then(|origin| self.origin_location(origin)),
+            });
+        }
+
+        frames
+    }
+
+    // Attaches the backtrace to an error that stops the query. Errors that
+    // are only reported as warnings don't need one.
+    pub(crate) fn raise(&self, mut error: crate::Error) -> crate::Error {
+        error.backtrace = self.backtrace();
+        error
+    }
+}
@@ -4,21 +4,38 @@ use crate::{Atom, HeapTerm, HeapTermPtr, Solver};
 
 use std::ops::{Add, Div, Mul, Rem, Shl, Shr, Sub};
 
+// Expressions nested this deep are checked for cycles
+const CYCLE_CHECK_DEPTH: usize = 256;
+
 pub fn eval(solver: &mut Solver, term: HeapTermPtr) -> Result<Atom, BuiltinError> {
+    eval_inner(solver, term, 0)
+}
+
+fn eval_inner(solver: &mut Solver, term: HeapTermPtr, depth: usize) -> Result<Atom, BuiltinError> {
     let term_ptr = solver.heap.get_ptr(term);
     match solver.heap.get(term_ptr) {
         HeapTerm::Atom(atom) => {
             if matches!(atom, Atom::Integer(_) | Atom::Float(_)) {
-                Ok(*atom)
+                Ok(atom)
             } else {
                 Err(BuiltinError::NotANumber(term))
             }
         }
-        HeapTerm::Var(_, _, _, _) => Err(BuiltinError::InsufficientlyInstantiated(term)),
-        HeapTerm::Compound(f, arity) if *arity == 2 => {
-            let f = *f;
-            let a = eval(solver, term_ptr + 1)?;
-            let b = eval(solver, term_ptr + 2)?;
+        HeapTerm::Var(_) => Err(BuiltinError::InsufficientlyInstantiated(term)),
+        HeapTerm::Compound(f, 2) => {
+            if depth == CYCLE_CHECK_DEPTH && solver.heap.is_cyclic(term_ptr) {
+                return Err(BuiltinError::CyclicTerm(term));
+            }
+
+            let a = eval_inner(solver, term_ptr + 1, depth + 1)?;
+            let b = eval_inner(solver, term_ptr + 2, depth + 1)?;
+
+            // Integer division by zero would otherwise panic
+            if matches!(f, str::DIV | str::INTDIV | str::MOD)
+                && matches!((a, b), (Atom::Integer(_), Atom::Integer(0)))
+            {
+                return Err(BuiltinError::ZeroDivisor);
+            }
 
             match f {
                 str::ADD => add(&a, &b),
@@ -31,7 +48,7 @@ pub fn eval(solver: &mut Solver, term: HeapTermPtr) -> Result<Atom, BuiltinError
                 str::LSHIFT => shl(&a, &b),
                 _ => Err(()),
             }
-            .map_err(|_| BuiltinError::UnsupportedOperation(f))
+            .map_err(|_| BuiltinError::UnsupportedOperation(f, 2))
         }
         _ => Err(BuiltinError::NotANumber(term)),
     }
@@ -10,11 +10,8 @@ impl Builtin<2> for DelayBuiltin {
         let var = solver.heap.get_ptr(args);
         let goal = solver.heap.get_ptr(args + 1);
 
-        match &mut solver.heap.data[var] {
-            HeapTerm::Var(_, _, attributed, attribute) => {
-                *attributed = true;
-                *attribute = goal;
-            }
+        match solver.heap.term(var) {
+            HeapTerm::Var(_) => solver.heap.set_attribute(var, goal),
             _ => solver.goals.push_pending(goal),
         }
 
@@ -27,11 +24,10 @@ impl Builtin<2> for FreezeBuiltin {
         let var = solver.heap.get_ptr(args);
         let goal = solver.heap.get_ptr(args + 1);
 
-        match &mut solver.heap.data[var] {
-            HeapTerm::Var(_, _, attributed, attribute) => {
-                *attributed = true;
-                *attribute = solver.goals.current().unwrap()
-            }
+        match solver.heap.term(var) {
+            HeapTerm::Var(_) => solver
+                .heap
+                .set_attribute(var, solver.goals.current().unwrap()),
             _ => solver.goals.push_pending(goal),
         }
 
@@ -1,5 +1,7 @@
+use crate::builtins::text::codes;
 use crate::builtins::{Builtin, BuiltinError};
-use crate::{HeapTermPtr, Solver};
+use crate::stringmap::str;
+use crate::{Atom, ChoicePointIdx, HeapTerm, HeapTermPtr, Solver, StringId};
 
 pub struct CallBuiltin;
 
@@ -9,3 +11,197 @@ impl Builtin<1> for CallBuiltin {
         Ok(true)
     }
 }
+
+// call/N calls its first argument with the others added to it
+pub fn call_with_args(
+    solver: &mut Solver,
+    args: HeapTermPtr,
+    extra: usize,
+) -> Result<bool, BuiltinError> {
+    let extra: Vec<_> = (1..=extra).map(|i| args + i).collect();
+    let goal = add_args(solver, args, &extra)?;
+    solver.goals.push_pending(goal);
+    Ok(true)
+}
+
+pub struct PhraseBuiltin;
+
+impl Builtin<2> for PhraseBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        let rest = solver.heap.alloc(HeapTerm::Atom(Atom::String(str::NIL)));
+        phrase(solver, args, args + 1, rest)
+    }
+}
+
+impl Builtin<3> for PhraseBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        phrase(solver, args, args + 1, args + 2)
+    }
+}
+
+// Calls a grammar body with the list and what remains of it, translated like
+// the body of a grammar rule. A cut in the body is local to phrase/2,3.
+fn phrase(
+    solver: &mut Solver,
+    body: HeapTermPtr,
+    list: HeapTermPtr,
+    rest: HeapTermPtr,
+) -> Result<bool, BuiltinError> {
+    let cut = solver.choice_points.len();
+    let mut body = solver.heap.get_ptr(body);
+
+    // A disjunction runs its first branch, and its second when it's retried
+    if let Some(branches) = control(solver, body, ";") {
+        if solver.clause == 0 {
+            let choice_point = solver.enter();
+            solver.choice_point_age = choice_point.heap_checkpoint;
+            solver.push_choice_point(choice_point);
+            body = branches;
+        } else {
+            body = branches + 1;
+        }
+    }
+
+    let mut goals = Vec::new();
+    translate(solver, body, list, rest, cut, &mut goals)?;
+    for goal in goals {
+        solver.goals.push_pending(goal);
+    }
+
+    Ok(true)
+}
+
+// The goals of a grammar body, as `dcg_rule` translates it
+fn translate(
+    solver: &mut Solver,
+    body: HeapTermPtr,
+    s0: HeapTermPtr,
+    s: HeapTermPtr,
+    cut: ChoicePointIdx,
+    goals: &mut Vec<HeapTermPtr>,
+) -> Result<(), BuiltinError> {
+    let body = solver.heap.get_ptr(body);
+
+    if let Some(args) = control(solver, body, ",") {
+        let s1 = solver.heap.alloc_new_var();
+        translate(solver, args, s0, s1, cut, goals)?;
+        return translate(solver, args + 1, s1, s, cut, goals);
+    }
+
+    // Disjunctions are run by phrase/3, which chooses a branch
+    if control(solver, body, ";").is_some() {
+        goals.push(goal(solver, str::PHRASE, &[body, s0, s]));
+        return Ok(());
+    }
+
+    match solver.heap.get(body) {
+        HeapTerm::Atom(Atom::String(str::NIL)) | HeapTerm::Compound(str::DOT, 2) => {
+            let terminals = append(solver, body, s)?;
+            goals.push(goal(solver, str::EQ, &[s0, terminals]));
+        }
+        HeapTerm::Atom(Atom::Text(id)) => {
+            let text = solver.heap.get_atom(id).to_string();
+            let codes = codes(solver, &text);
+            let terminals = append(solver, codes, s)?;
+            goals.push(goal(solver, str::EQ, &[s0, terminals]));
+        }
+        HeapTerm::Atom(Atom::String(str::EXCL)) | HeapTerm::Cut(_) => {
+            goals.push(solver.heap.alloc(HeapTerm::Cut(cut)));
+            goals.push(goal(solver, str::EQ, &[s0, s]));
+        }
+        HeapTerm::Atom(Atom::String(name)) if solver.heap.get_atom(name) == "{}" => {
+            goals.push(goal(solver, str::EQ, &[s0, s]));
+        }
+        HeapTerm::Compound(name, 1) if solver.heap.get_atom(name) == "{}" => {
+            let mut conjunction = solver.heap.get_ptr(body + 1);
+            while let Some(args) = control(solver, conjunction, ",") {
+                goals.push(solver.heap.get_ptr(args));
+                conjunction = solver.heap.get_ptr(args + 1);
+            }
+            goals.push(conjunction);
+            goals.push(goal(solver, str::EQ, &[s0, s]));
+        }
+        _ => goals.push(add_args(solver, body, &[s0, s])?),
+    }
+
+    Ok(())
+}
+
+// The arguments of a conjunction or disjunction
+fn control(solver: &Solver, term: HeapTermPtr, name: &str) -> Option<HeapTermPtr> {
+    let term = solver.heap.get_ptr(term);
+    match solver.heap.get(term) {
+        HeapTerm::Compound(functor, 2) if solver.heap.get_atom(functor) == name => Some(term + 1),
+        _ => None,
+    }
+}
+
+fn goal(solver: &mut Solver, functor: StringId, args: &[HeapTermPtr]) -> HeapTermPtr {
+    let goal = solver.heap.alloc(HeapTerm::Compound(functor, args.len()));
+    for arg in args {
+        solver.heap.alloc(HeapTerm::Var(*arg));
+    }
+    goal
+}
+
+// Builds a goal with arguments added after those it already has
+fn add_args(
+    solver: &mut Solver,
+    goal: HeapTermPtr,
+    extra: &[HeapTermPtr],
+) -> Result<HeapTermPtr, BuiltinError> {
+    let goal = solver.heap.get_ptr(goal);
+    let (functor, arity) = match solver.heap.get(goal) {
+        HeapTerm::Atom(Atom::String(name)) => (name, 0),
+        HeapTerm::Compound(functor, arity) => (functor, arity),
+        HeapTerm::Var(_) => return Err(BuiltinError::InsufficientlyInstantiated(goal)),
+        _ => return Err(BuiltinError::Type("callable", goal)),
+    };
+
+    let new_goal = solver
+        .heap
+        .alloc(HeapTerm::Compound(functor, arity + extra.len()));
+    for i in 1..=arity {
+        solver.heap.alloc(HeapTerm::Var(goal + i));
+    }
+    for arg in extra {
+        solver.heap.alloc(HeapTerm::Var(*arg));
+    }
+
+    Ok(new_goal)
+}
+
+// A copy of a list of terminals that ends in `rest`
+fn append(
+    solver: &mut Solver,
+    list: HeapTermPtr,
+    rest: HeapTermPtr,
+) -> Result<HeapTermPtr, BuiltinError> {
+    let mut terminals = Vec::new();
+    let mut tail = list;
+
+    // A list can't be longer than the heap, so a longer one is cyclic
+    for _ in 0..solver.heap.len() {
+        let ptr = solver.heap.get_ptr(tail);
+        match solver.heap.get(ptr) {
+            HeapTerm::Atom(atom) if atom.is_nil() => {
+                let mut result = rest;
+                for head in terminals.into_iter().rev() {
+                    let cons = solver.heap.alloc(HeapTerm::Compound(str::DOT, 2));
+                    solver.heap.alloc(HeapTerm::Var(head));
+                    solver.heap.alloc(HeapTerm::Var(result));
+                    result = cons;
+                }
+                return Ok(result);
+            }
+            HeapTerm::Compound(str::DOT, 2) => {
+                terminals.push(ptr + 1);
+                tail = ptr + 2;
+            }
+            HeapTerm::Var(_) => return Err(BuiltinError::InsufficientlyInstantiated(list)),
+            _ => return Err(BuiltinError::Type("list", list)),
+        }
+    }
+
+    Err(BuiltinError::CyclicTerm(list))
+}
@@ -1,6 +1,9 @@
 use crate::builtins::{arithmetic, Builtin, BuiltinError};
+use crate::cyclic::VISITED_THRESHOLD;
 use crate::{Atom, HeapTerm, HeapTermPtr, Solver};
 
+use std::collections::HashSet;
+
 pub struct EquivBuiltin;
 
 impl Builtin<2> for EquivBuiltin {
@@ -41,19 +44,43 @@ impl_arithmetic_cmp!(LtBuiltin, lt);
 impl_arithmetic_cmp!(LteBuiltin, le);
 
 fn equiv(solver: &Solver, a: HeapTermPtr, b: HeapTermPtr) -> bool {
-    let a_root = solver.heap.get_ptr(a);
-    let b_root = solver.heap.get_ptr(b);
-
-    match (solver.heap.get(a_root), solver.heap.get(b_root)) {
-        (HeapTerm::Atom(a), HeapTerm::Atom(b)) => a == b,
-        (HeapTerm::Var(a, _, _, _), HeapTerm::Var(b, _, _, _)) => a == b,
-        (HeapTerm::Compound(f, a), HeapTerm::Compound(g, b)) => {
-            f == g && a == b && (1..=*a).all(|i| equiv(solver, a_root + i, b_root + i))
+    let mut stack = vec![(a, b)];
+
+    // Pairs of structures that are assumed to be equivalent, so that cyclic terms terminate
+    let mut visited: Option<HashSet<_>> = None;
+    let mut structures = 0;
+
+    while let Some((a, b)) = stack.pop() {
+        let a_root = solver.heap.get_ptr(a);
+        let b_root = solver.heap.get_ptr(b);
+
+        let arity = match (solver.heap.get(a_root), solver.heap.get(b_root)) {
+            (HeapTerm::Atom(a), HeapTerm::Atom(b)) if a == b => 0,
+            (HeapTerm::Var(a), HeapTerm::Var(b)) if a == b => 0,
+            (HeapTerm::Compound(f, a), HeapTerm::Compound(g, b)) if f == g && a == b => a,
+            (HeapTerm::Cut(_), HeapTerm::Cut(_)) => 0,
+            (HeapTerm::Lambda(js1, a1), HeapTerm::Lambda(js2, a2)) if js1 == js2 && a1 == a2 => a1,
+            _ => return false,
+        };
+
+        if arity == 0 {
+            continue;
         }
-        (HeapTerm::Cut(_), HeapTerm::Cut(_)) => true,
-        (HeapTerm::Lambda(js1, a1), HeapTerm::Lambda(js2, a2)) => {
-            js1 == js2 && a1 == a2 && (1..=*a1).all(|i| equiv(solver, a_root + i, b_root + i))
+
+        structures += 1;
+        if a_root == b_root
+            || structures > VISITED_THRESHOLD
+                && !visited
+                    .get_or_insert_with(HashSet::new)
+                    .insert((a_root, b_root))
+        {
+            continue;
+        }
+
+        for i in (1..=arity).rev() {
+            stack.push((a_root + i, b_root + i));
         }
-        _ => false,
     }
+
+    true
 }
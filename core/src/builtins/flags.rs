@@ -0,0 +1,55 @@
+use crate::builtins::{Builtin, BuiltinError};
+use crate::flags::{DoubleQuotes, Unknown};
+use crate::{Atom, HeapTerm, HeapTermPtr, Solver};
+
+pub struct SetPrologFlagBuiltin;
+
+pub struct CurrentPrologFlagBuiltin;
+
+impl Builtin<2> for SetPrologFlagBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        let flag = solver.heap.get_ptr(args);
+        let value = solver.heap.get_ptr(args + 1);
+
+        let name = match solver.heap.get(value) {
+            HeapTerm::Atom(Atom::String(id)) => Some(solver.heap.get_atom(id)),
+            HeapTerm::Var(_) => return Err(BuiltinError::InsufficientlyInstantiated(value)),
+            _ => None,
+        };
+        let invalid = BuiltinError::InvalidFlagValue(flag, value);
+
+        match flag_name(solver, flag)? {
+            "unknown" => solver.flags.unknown = name.and_then(Unknown::parse).ok_or(invalid)?,
+            "double_quotes" => {
+                solver.flags.double_quotes = name.and_then(DoubleQuotes::parse).ok_or(invalid)?
+            }
+            _ => return Err(BuiltinError::UnknownFlag(flag)),
+        }
+
+        Ok(true)
+    }
+}
+
+impl Builtin<2> for CurrentPrologFlagBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        let flag = solver.heap.get_ptr(args);
+
+        let value = match flag_name(solver, flag)? {
+            "unknown" => solver.flags.unknown.name(),
+            "double_quotes" => solver.flags.double_quotes.name(),
+            _ => return Err(BuiltinError::UnknownFlag(flag)),
+        };
+
+        let value = solver.heap.string_map.alloc(value);
+        let value = solver.heap.alloc(HeapTerm::Atom(Atom::String(value)));
+        Ok(solver.unify(args + 1, value))
+    }
+}
+
+fn flag_name(solver: &Solver, flag: HeapTermPtr) -> Result<&str, BuiltinError> {
+    match solver.heap.get(flag) {
+        HeapTerm::Atom(Atom::String(id)) => Ok(solver.heap.get_atom(id)),
+        HeapTerm::Var(_) => Err(BuiltinError::InsufficientlyInstantiated(flag)),
+        _ => Err(BuiltinError::UnknownFlag(flag)),
+    }
+}
@@ -0,0 +1,12 @@
+use crate::builtins::{Builtin, BuiltinError};
+use crate::{HeapTermPtr, Solver};
+
+pub struct GarbageCollectBuiltin;
+
+// The collection can't run in the middle of a step, so it happens before the next one
+impl Builtin<0> for GarbageCollectBuiltin {
+    fn eval(solver: &mut Solver, _: HeapTermPtr) -> Result<bool, BuiltinError> {
+        solver.gc.force();
+        Ok(true)
+    }
+}
@@ -2,45 +2,122 @@ mod arithmetic;
 mod attributes;
 mod call;
 mod cmp;
+mod flags;
+mod gc;
 mod is;
+mod port;
+mod read;
 mod statistics;
+mod text;
+mod trace;
 mod types;
 mod unify;
 
 use crate::stringmap::str;
-use crate::{Error, HeapTerm, HeapTermPtr, Solver, StringId};
+use crate::{Atom, Error, ErrorKind, HeapTerm, HeapTermPtr, Solver, StringId, Term};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum BuiltinError {
     NotANumber(HeapTermPtr),
+    Type(&'static str, HeapTermPtr),
+    Representation(&'static str, HeapTermPtr),
+    IllegalNumber(String),
+    Syntax(&'static str, String),
+    Domain(&'static str, HeapTermPtr),
     InsufficientlyInstantiated(HeapTermPtr),
-    UnsupportedOperation(StringId),
+    UnsupportedOperation(StringId, usize),
+    ZeroDivisor,
+    NotAPredicateIndicator(HeapTermPtr),
+    CyclicTerm(HeapTermPtr),
     UnsupportedPlatform,
+    UnknownProcedure(StringId, usize),
+    UnknownFlag(HeapTermPtr),
+    InvalidFlagValue(HeapTermPtr, HeapTermPtr),
     JavaScriptError(String),
+    InferenceLimit(usize),
 }
 
 pub trait Builtin<const ARITY: usize> {
     fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError>;
 }
 
+// The predicates handled by `eval`, which clauses of a program can't redefine
+pub const PREDICATES: &[(StringId, usize)] = &[
+    (str::TRACE, 0),
+    (str::NOTRACE, 0),
+    (str::GARBAGE_COLLECT, 0),
+    (str::TRUE, 0),
+    (str::FAIL, 0),
+    (str::FALSE, 0),
+    (str::INTEGER, 1),
+    (str::FLOAT, 1),
+    (str::ATOM, 1),
+    (str::STRING, 1),
+    (str::COMPOUND, 1),
+    (str::NUMBER, 1),
+    (str::VAR, 1),
+    (str::NONVAR, 1),
+    (str::CALL, 1),
+    (str::CALL, 2),
+    (str::CALL, 3),
+    (str::CALL, 4),
+    (str::CALL, 5),
+    (str::CALL, 6),
+    (str::CALL, 7),
+    (str::CALL, 8),
+    (str::SPY, 1),
+    (str::NOSPY, 1),
+    (str::CYCLIC_TERM, 1),
+    (str::ACYCLIC_TERM, 1),
+    (str::EQ, 2),
+    (str::IS, 2),
+    (str::GT, 2),
+    (str::GE, 2),
+    (str::LT, 2),
+    (str::LE, 2),
+    (str::ANE, 2),
+    (str::AEQ, 2),
+    (str::STAT, 2),
+    (str::EQUIV, 2),
+    (str::DELAY, 2),
+    (str::FREEZE, 2),
+    (str::SET_PROLOG_FLAG, 2),
+    (str::CURRENT_PROLOG_FLAG, 2),
+    (str::NUMBER_CODES, 2),
+    (str::PHRASE, 2),
+    (str::PHRASE, 3),
+    (str::READ_TERM, 2),
+    (str::READ_TERM, 3),
+    (str::TERM_TO_ATOM, 2),
+    (str::TERM_STRING, 2),
+    (str::EXIT, 3),
+];
+
 pub fn eval(solver: &mut Solver, goal: HeapTermPtr) -> Option<Result<bool, BuiltinError>> {
     let goal_ptr = solver.heap.get_ptr(goal);
     match solver.heap.get(goal_ptr) {
         HeapTerm::Compound(functor, arity) => {
-            if *arity == 1 {
-                match *functor {
+            if functor == str::CALL && (2..=8).contains(&arity) {
+                Some(call::call_with_args(solver, goal_ptr + 1, arity - 1))
+            } else if arity == 1 {
+                match functor {
                     str::INTEGER => Some(types::IsIntegerBuiltin::eval(solver, goal_ptr + 1)),
                     str::FLOAT => Some(types::IsFloatBuiltin::eval(solver, goal_ptr + 1)),
                     str::ATOM => Some(types::IsAtomBuiltin::eval(solver, goal_ptr + 1)),
+                    str::STRING => Some(types::IsStringBuiltin::eval(solver, goal_ptr + 1)),
                     str::COMPOUND => Some(types::IsCompoundBuiltin::eval(solver, goal_ptr + 1)),
                     str::NUMBER => Some(types::IsNumberBuiltin::eval(solver, goal_ptr + 1)),
                     str::VAR => Some(types::IsVarBuiltin::eval(solver, goal_ptr + 1)),
                     str::NONVAR => Some(types::IsNonVarBuiltin::eval(solver, goal_ptr + 1)),
                     str::CALL => Some(call::CallBuiltin::eval(solver, goal_ptr + 1)),
+                    str::SPY => Some(trace::SpyBuiltin::eval(solver, goal_ptr + 1)),
+                    str::NOSPY => Some(trace::NoSpyBuiltin::eval(solver, goal_ptr + 1)),
+                    str::CYCLIC_TERM => Some(types::IsCyclicBuiltin::eval(solver, goal_ptr + 1)),
+                    str::ACYCLIC_TERM => Some(types::IsAcyclicBuiltin::eval(solver, goal_ptr + 1)),
                     _ => None,
                 }
-            } else if *arity == 2 {
-                match *functor {
+            } else if arity == 2 {
+                match functor {
                     str::EQ => Some(unify::UnifyBuiltin::eval(solver, goal_ptr + 1)),
                     str::IS => Some(is::IsBuiltin::eval(solver, goal_ptr + 1)),
                     str::GT => Some(cmp::GtBuiltin::eval(solver, goal_ptr + 1)),
@@ -53,43 +130,246 @@ pub fn eval(solver: &mut Solver, goal: HeapTermPtr) -> Option<Result<bool, Built
                     str::EQUIV => Some(cmp::EquivBuiltin::eval(solver, goal_ptr + 1)),
                     str::DELAY => Some(attributes::DelayBuiltin::eval(solver, goal_ptr + 1)),
                     str::FREEZE => Some(attributes::FreezeBuiltin::eval(solver, goal_ptr + 1)),
+                    str::SET_PROLOG_FLAG => {
+                        Some(flags::SetPrologFlagBuiltin::eval(solver, goal_ptr + 1))
+                    }
+                    str::CURRENT_PROLOG_FLAG => {
+                        Some(flags::CurrentPrologFlagBuiltin::eval(solver, goal_ptr + 1))
+                    }
+                    str::NUMBER_CODES => Some(text::NumberCodesBuiltin::eval(solver, goal_ptr + 1)),
+                    str::PHRASE => Some(<call::PhraseBuiltin as Builtin<2>>::eval(
+                        solver,
+                        goal_ptr + 1,
+                    )),
+                    str::READ_TERM => Some(<read::ReadTermBuiltin as Builtin<2>>::eval(
+                        solver,
+                        goal_ptr + 1,
+                    )),
+                    str::TERM_TO_ATOM => Some(read::TermToAtomBuiltin::eval(solver, goal_ptr + 1)),
+                    str::TERM_STRING => Some(read::TermStringBuiltin::eval(solver, goal_ptr + 1)),
+                    _ => None,
+                }
+            } else if arity == 3 {
+                match functor {
+                    str::EXIT => Some(port::ExitBuiltin::eval(solver, goal_ptr + 1)),
+                    str::PHRASE => Some(<call::PhraseBuiltin as Builtin<3>>::eval(
+                        solver,
+                        goal_ptr + 1,
+                    )),
+                    str::READ_TERM => Some(<read::ReadTermBuiltin as Builtin<3>>::eval(
+                        solver,
+                        goal_ptr + 1,
+                    )),
                     _ => None,
                 }
             } else {
                 None
             }
         }
+        HeapTerm::Atom(Atom::String(atom)) => match atom {
+            str::TRACE => Some(trace::TraceBuiltin::eval(solver, goal_ptr)),
+            str::NOTRACE => Some(trace::NoTraceBuiltin::eval(solver, goal_ptr)),
+            str::GARBAGE_COLLECT => Some(gc::GarbageCollectBuiltin::eval(solver, goal_ptr)),
+            str::TRUE => Some(Ok(true)),
+            str::FAIL | str::FALSE => Some(Ok(false)),
+            _ => None,
+        },
         HeapTerm::Cut(choice_point_idx) => {
-            solver.cut(*choice_point_idx);
+            solver.cut(choice_point_idx);
             Some(Ok(true))
         }
         HeapTerm::Lambda(id, arity) => {
-            let args = (1..=*arity).map(|i| goal_ptr + i).collect();
-            Some(crate::wasm::inline_js::eval(solver, *id, args))
+            let args = (1..=arity).map(|i| goal_ptr + i).collect();
+            Some(crate::wasm::inline_js::eval(solver, id, args))
         }
-        _ => None,
+        HeapTerm::Var(_) => Some(Err(BuiltinError::InsufficientlyInstantiated(goal_ptr))),
+        // Numbers and strings aren't goals
+        HeapTerm::Atom(_) => Some(Err(BuiltinError::Type("callable", goal_ptr))),
     }
 }
 
 pub fn error(solver: &Solver, error: BuiltinError) -> Error {
-    Error {
-        location: None,
-        error: match error {
-            BuiltinError::NotANumber(ptr) => {
-                format!(
-                    "Expected a number, got `{}`",
-                    solver.heap.serialize(&[("Err".to_string(), ptr)])[0].1
-                )
-            }
-            BuiltinError::InsufficientlyInstantiated(ptr) => format!(
+    let heap = &solver.heap;
+    let term = |ptr| Term::from_heap(heap, ptr);
+
+    let (kind, message, formal) = match error {
+        BuiltinError::NotANumber(ptr) => (
+            ErrorKind::Type,
+            format!("Expected a number, got `{}`", heap.serialize_term(ptr)),
+            Term::compound(
+                "type_error",
+                vec![Term::atom("evaluable"), evaluable(solver, ptr)],
+            ),
+        ),
+        BuiltinError::Type(expected, ptr) => (
+            ErrorKind::Type,
+            format!(
+                "Expected {} {}, got `{}`",
+                article(expected),
+                expected.replace('_', " "),
+                heap.serialize_term(ptr)
+            ),
+            Term::compound("type_error", vec![Term::atom(expected), term(ptr)]),
+        ),
+        BuiltinError::Representation(limit, ptr) => (
+            ErrorKind::Representation,
+            format!(
+                "Invalid {} `{}`",
+                limit.replace('_', " "),
+                heap.serialize_term(ptr)
+            ),
+            Term::compound("representation_error", vec![Term::atom(limit)]),
+        ),
+        BuiltinError::IllegalNumber(text) => (
+            ErrorKind::Syntax,
+            format!("Illegal number `{}`", text),
+            Term::compound("syntax_error", vec![Term::atom("illegal_number")]),
+        ),
+        BuiltinError::Syntax(read, message) => (
+            ErrorKind::Syntax,
+            format!("Syntax error in {}: {}", read, message),
+            Term::compound("syntax_error", vec![Term::atom(&message)]),
+        ),
+        BuiltinError::Domain(domain, ptr) => (
+            ErrorKind::Domain,
+            format!(
+                "Expected {} {}, got `{}`",
+                article(domain),
+                domain.replace('_', " "),
+                heap.serialize_term(ptr)
+            ),
+            Term::compound("domain_error", vec![Term::atom(domain), term(ptr)]),
+        ),
+        BuiltinError::InsufficientlyInstantiated(ptr) => (
+            ErrorKind::Instantiation,
+            format!(
                 "Insufficiently instantiated variable `{}`",
-                solver.heap.serialize(&[("Err".to_string(), ptr)])[0].1
+                heap.serialize_term(ptr)
             ),
-            BuiltinError::UnsupportedOperation(s) => {
-                format!("Unsupported operation `{}`", solver.heap.get_atom(s))
-            }
-            BuiltinError::UnsupportedPlatform => "Unsupported platform, requires WASM".to_string(),
-            BuiltinError::JavaScriptError(e) => format!("JS: {}", e),
-        },
+            Term::atom("instantiation_error"),
+        ),
+        BuiltinError::UnsupportedOperation(s, arity) => (
+            ErrorKind::Type,
+            format!("Unsupported operation `{}`", heap.get_atom(s)),
+            Term::compound(
+                "type_error",
+                vec![
+                    Term::atom("evaluable"),
+                    Term::indicator(heap.get_atom(s), arity),
+                ],
+            ),
+        ),
+        BuiltinError::ZeroDivisor => (
+            ErrorKind::Evaluation,
+            "Division by zero".to_string(),
+            Term::compound("evaluation_error", vec![Term::atom("zero_divisor")]),
+        ),
+        BuiltinError::NotAPredicateIndicator(ptr) => (
+            ErrorKind::Type,
+            format!(
+                "Expected a predicate indicator, got `{}`",
+                heap.serialize_term(ptr)
+            ),
+            Term::compound(
+                "type_error",
+                vec![Term::atom("predicate_indicator"), term(ptr)],
+            ),
+        ),
+        BuiltinError::CyclicTerm(ptr) => (
+            ErrorKind::Type,
+            format!(
+                "Expected an acyclic term, got `{}`",
+                heap.serialize_term(ptr)
+            ),
+            // The term itself can't be built without repeating it forever
+            Term::compound(
+                "type_error",
+                vec![
+                    Term::atom("acyclic_term"),
+                    Term::atom(&heap.serialize_term(ptr)),
+                ],
+            ),
+        ),
+        BuiltinError::UnsupportedPlatform => (
+            ErrorKind::JavaScript,
+            "Unsupported platform, requires WASM".to_string(),
+            Term::compound("resource_error", vec![Term::atom("javascript")]),
+        ),
+        BuiltinError::UnknownProcedure(name, arity) => (
+            ErrorKind::Existence,
+            format!("Unknown procedure `{}/{}`", heap.get_atom(name), arity),
+            Term::compound(
+                "existence_error",
+                vec![
+                    Term::atom("procedure"),
+                    Term::indicator(heap.get_atom(name), arity),
+                ],
+            ),
+        ),
+        BuiltinError::UnknownFlag(ptr) => (
+            ErrorKind::Domain,
+            format!("Unknown Prolog flag `{}`", heap.serialize_term(ptr)),
+            Term::compound("domain_error", vec![Term::atom("prolog_flag"), term(ptr)]),
+        ),
+        BuiltinError::InvalidFlagValue(flag, value) => (
+            ErrorKind::Domain,
+            format!(
+                "Invalid value `{}` for Prolog flag `{}`",
+                heap.serialize_term(value),
+                heap.serialize_term(flag)
+            ),
+            Term::compound(
+                "domain_error",
+                vec![
+                    Term::atom("flag_value"),
+                    Term::compound("+", vec![term(flag), term(value)]),
+                ],
+            ),
+        ),
+        BuiltinError::JavaScriptError(e) => (
+            ErrorKind::JavaScript,
+            format!("JS: {}", e),
+            Term::compound("system_error", vec![Term::atom(&e)]),
+        ),
+        BuiltinError::InferenceLimit(limit) => (
+            ErrorKind::Resource,
+            format!("Gave up after {} inferences", limit),
+            Term::compound("resource_error", vec![Term::atom("inferences")]),
+        ),
+    };
+
+    // The context is the predicate that raised the error
+    let context = match solver
+        .goals
+        .current()
+        .map(|goal| heap.get(heap.get_ptr(goal)))
+    {
+        Some(HeapTerm::Atom(Atom::String(name))) => Term::indicator(heap.get_atom(name), 0),
+        Some(HeapTerm::Compound(functor, arity)) => Term::indicator(heap.get_atom(functor), arity),
+        _ => Term::atom("[]"),
+    };
+
+    Error {
+        location: solver.goal_location().map(Box::new),
+        ..Error::new(kind, message)
+    }
+    .with_term(formal, context)
+}
+
+fn article(noun: &str) -> &'static str {
+    if noun.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    }
+}
+
+// Arithmetic expects functors of evaluable functions, such as `+/2`
+fn evaluable(solver: &Solver, ptr: HeapTermPtr) -> Term {
+    let heap = &solver.heap;
+    match heap.get(heap.get_ptr(ptr)) {
+        HeapTerm::Atom(Atom::String(name)) => Term::indicator(heap.get_atom(name), 0),
+        HeapTerm::Compound(functor, arity) => Term::indicator(heap.get_atom(functor), arity),
+        _ => Term::from_heap(heap, ptr),
     }
 }
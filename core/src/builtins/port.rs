@@ -0,0 +1,23 @@
+use crate::builtins::{Builtin, BuiltinError};
+use crate::profile::Port;
+use crate::{Atom, HeapTerm, HeapTermPtr, Solver};
+
+pub struct ExitBuiltin;
+
+impl Builtin<3> for ExitBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        let goal = match solver.heap.term(args) {
+            HeapTerm::Var(goal) => goal,
+            _ => unreachable!(),
+        };
+
+        if let (HeapTerm::Atom(Atom::Integer(depth)), HeapTerm::Atom(Atom::Integer(clause))) =
+            (solver.heap.term(args + 1), solver.heap.term(args + 2))
+        {
+            solver.depth = depth as usize;
+            solver.port(Port::Exit, goal, Some(clause as usize));
+        }
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,326 @@
+use crate::atom::Atom;
+use crate::builtins::text::{list, text};
+use crate::builtins::{Builtin, BuiltinError};
+use crate::stringmap::str;
+use crate::{ast, grammar, lint, HeapTerm, HeapTermPtr, Solver};
+
+use std::cell::Cell;
+use std::sync::LazyLock;
+
+static TERMS_PARSER: LazyLock<grammar::TermsParser> = LazyLock::new(grammar::TermsParser::new);
+static TERM_TEXT_PARSER: LazyLock<grammar::TermTextParser> =
+    LazyLock::new(grammar::TermTextParser::new);
+
+pub struct ReadTermBuiltin;
+
+impl Builtin<2> for ReadTermBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        read_term(solver, args, args + 1)
+    }
+}
+
+impl Builtin<3> for ReadTermBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        // The input added to the solver is the only stream
+        match solver.heap.get(args) {
+            HeapTerm::Atom(Atom::String(id)) if solver.heap.get_atom(id) == "user_input" => {}
+            HeapTerm::Var(_) => return Err(BuiltinError::InsufficientlyInstantiated(args)),
+            _ => return Err(BuiltinError::Domain("stream_or_alias", args)),
+        }
+
+        read_term(solver, args + 1, args + 2)
+    }
+}
+
+pub struct TermToAtomBuiltin;
+
+impl Builtin<2> for TermToAtomBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        match text_of(solver, args + 1, "atom")? {
+            Some(text) => parse(solver, args, &text, "atom"),
+            None if matches!(solver.heap.get(args), HeapTerm::Var(_)) => {
+                Err(BuiltinError::InsufficientlyInstantiated(args))
+            }
+            None => {
+                let text = solver.heap.serialize_term(args);
+                let atom = solver.heap.string_map.alloc(&text);
+                let atom = solver.heap.alloc(HeapTerm::Atom(Atom::String(atom)));
+                Ok(solver.unify(args + 1, atom))
+            }
+        }
+    }
+}
+
+pub struct TermStringBuiltin;
+
+impl Builtin<2> for TermStringBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        match text_of(solver, args + 1, "string")? {
+            Some(text) => parse(solver, args, &text, "string"),
+            None if matches!(solver.heap.get(args), HeapTerm::Var(_)) => {
+                Err(BuiltinError::InsufficientlyInstantiated(args))
+            }
+            None => {
+                let text = solver.heap.serialize_term(args);
+                let string = solver.heap.string_map.alloc(&text);
+                let string = solver.heap.alloc(HeapTerm::Atom(Atom::Text(string)));
+                Ok(solver.unify(args + 1, string))
+            }
+        }
+    }
+}
+
+// What read_term/2 tells about the variables of the term it reads
+#[derive(Clone, Copy)]
+enum ReadOption {
+    VariableNames,
+    Variables,
+    Singletons,
+}
+
+fn read_term(
+    solver: &mut Solver,
+    term: HeapTermPtr,
+    options: HeapTermPtr,
+) -> Result<bool, BuiltinError> {
+    // Options are checked before anything is read
+    let options = read_options(solver, options)?;
+
+    let (ptr, vars, singletons) = match next_term(solver)? {
+        Some(read) => {
+            let singletons: Vec<_> = lint::singletons([&read])
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect();
+            let (ptr, vars) = alloc(solver, &read);
+            (ptr, vars, singletons)
+        }
+        None => {
+            let end_of_file = solver.heap.string_map.alloc("end_of_file");
+            let ptr = solver.heap.alloc(HeapTerm::Atom(Atom::String(end_of_file)));
+            (ptr, Vec::new(), Vec::new())
+        }
+    };
+
+    if !solver.unify(term, ptr) {
+        return Ok(false);
+    }
+
+    for (option, arg) in options {
+        let items: Vec<_> = match option {
+            ReadOption::Variables => vars.iter().map(|(_, var)| *var).collect(),
+            ReadOption::VariableNames => vars
+                .iter()
+                .map(|(name, var)| binding(solver, name, *var))
+                .collect(),
+            ReadOption::Singletons => vars
+                .iter()
+                .filter(|(name, _)| singletons.contains(name))
+                .map(|(name, var)| binding(solver, name, *var))
+                .collect(),
+        };
+
+        let items = list(solver, &items);
+        if !solver.unify(arg, items) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn read_options(
+    solver: &Solver,
+    ptr: HeapTermPtr,
+) -> Result<Vec<(ReadOption, HeapTermPtr)>, BuiltinError> {
+    let heap = &solver.heap;
+    let mut options = Vec::new();
+    let mut list = ptr;
+
+    // A list can't be longer than the heap, so a longer one is cyclic
+    for _ in 0..heap.len() {
+        match heap.get(list) {
+            HeapTerm::Atom(atom) if atom.is_nil() => return Ok(options),
+            HeapTerm::Compound(str::DOT, 2) => {
+                let list_ptr = heap.get_ptr(list);
+                let option_ptr = heap.get_ptr(list_ptr + 1);
+                let option = match heap.get(option_ptr) {
+                    HeapTerm::Compound(name, 1) => match heap.get_atom(name) {
+                        "variable_names" => ReadOption::VariableNames,
+                        "variables" => ReadOption::Variables,
+                        "singletons" => ReadOption::Singletons,
+                        _ => return Err(BuiltinError::Domain("read_option", option_ptr)),
+                    },
+                    HeapTerm::Var(_) => {
+                        return Err(BuiltinError::InsufficientlyInstantiated(option_ptr))
+                    }
+                    _ => return Err(BuiltinError::Domain("read_option", option_ptr)),
+                };
+                options.push((option, option_ptr + 1));
+                list = list_ptr + 2;
+            }
+            HeapTerm::Var(_) => return Err(BuiltinError::InsufficientlyInstantiated(ptr)),
+            _ => return Err(BuiltinError::Type("list", ptr)),
+        }
+    }
+
+    Err(BuiltinError::CyclicTerm(ptr))
+}
+
+// Reads the next term from the input and consumes the text up to its full
+// stop, or returns `None` once only layout is left. A term with a syntax error
+// is consumed too, so the next read continues after it. Only the text up to
+// the full stop is parsed, so later terms don't slow the read down or spoil it.
+fn next_term(solver: &mut Solver) -> Result<Option<ast::Term>, BuiltinError> {
+    let start = solver.input_offset;
+    let end = start + term_end(&solver.input[start..]);
+    solver.input_offset = end;
+
+    let text = &solver.input[start..end];
+    let flags = Cell::new(solver.flags);
+    let mut errors = Vec::new();
+
+    match TERMS_PARSER.parse(&mut errors, &flags, text) {
+        Ok(terms) => match terms.into_iter().next() {
+            Some(Ok((term, _))) => Ok(Some(term)),
+            Some(Err(_)) => Err(syntax_error("read term", text, errors.remove(0).error)),
+            None => Ok(None),
+        },
+        Err(e) => Err(syntax_error("read term", text, e)),
+    }
+}
+
+// The length of the text up to and including the first end token, a full stop
+// followed by layout or the end of the text, or of the whole text without one.
+// Quotes, comments and character codes can't end a term, and neither can a
+// full stop that is part of a symbol atom like `=..`.
+fn term_end(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let at = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => i = find(bytes, i, b"\n"),
+            b'/' if at(i + 1) == b'*' => i = find(bytes, i + 2, b"*/") + 2,
+            b'<' if at(i + 1) == b'{' => i = find(bytes, i + 2, b"}>") + 2,
+            quote @ (b'\'' | b'"') => {
+                i += 1;
+                while i < bytes.len() {
+                    match bytes[i] {
+                        b'\\' => i += 2,
+                        c if c == quote && at(i + 1) == quote => i += 2,
+                        c if c == quote => break,
+                        _ => i += 1,
+                    }
+                }
+                i += 1;
+            }
+            b'0' if at(i + 1) == b'\'' => {
+                i += 2;
+                match at(i) {
+                    b'\\' if at(i + 1) == b'x' || at(i + 1).is_ascii_digit() => {
+                        i = find(bytes, i + 1, b"\\") + 1;
+                    }
+                    b'\\' | b'\'' => i += 2,
+                    _ => i += 1,
+                }
+            }
+            // Names and numbers, including radix numbers like `16'FF`
+            c if c.is_ascii_alphanumeric() || c == b'_' || !c.is_ascii() => {
+                while i < bytes.len()
+                    && (at(i).is_ascii_alphanumeric() || at(i) == b'_' || !at(i).is_ascii())
+                {
+                    i += 1;
+                }
+                if at(i) == b'\'' && c.is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            c if SYMBOL_CHARS.contains(&c) => {
+                let symbol = i;
+                while i < bytes.len() && SYMBOL_CHARS.contains(&bytes[i]) {
+                    i += 1;
+                }
+                let layout = at(i).is_ascii_whitespace() || matches!(at(i), 0 | b'%');
+                if i - symbol == 1 && c == b'.' && layout {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+const SYMBOL_CHARS: &[u8] = b"+-*/\\^<>=~:.?@#&$";
+
+// The offset of a pattern from `start`, or the end of the text without it
+fn find(bytes: &[u8], start: usize, pattern: &[u8]) -> usize {
+    bytes
+        .get(start..)
+        .and_then(|rest| rest.windows(pattern.len()).position(|w| w == pattern))
+        .map_or(bytes.len(), |i| start + i)
+}
+
+// Reads the text of term_to_atom/2 or term_string/2 and unifies the term with it
+fn parse(
+    solver: &mut Solver,
+    term: HeapTermPtr,
+    text: &str,
+    read: &'static str,
+) -> Result<bool, BuiltinError> {
+    let flags = Cell::new(solver.flags);
+    let read = TERM_TEXT_PARSER
+        .parse(&mut Vec::new(), &flags, text)
+        .map_err(|e| syntax_error(read, text, e))?;
+
+    let (ptr, _) = alloc(solver, &read);
+    Ok(solver.unify(term, ptr))
+}
+
+fn alloc(solver: &mut Solver, term: &ast::Term) -> (HeapTermPtr, Vec<(String, HeapTermPtr)>) {
+    let mut var_map = Vec::new();
+    let (ptr, _) = term.alloc(&mut solver.heap, &mut var_map, &mut solver.lambdas);
+
+    let vars = var_map
+        .into_iter()
+        .map(|(id, var)| (solver.heap.get_atom(id).to_string(), var))
+        .collect();
+    (ptr, vars)
+}
+
+// `Name = Var`, as in the lists of variable_names/1 and singletons/1
+fn binding(solver: &mut Solver, name: &str, var: HeapTermPtr) -> HeapTermPtr {
+    let name = solver.heap.string_map.alloc(name);
+    let name = solver.heap.alloc(HeapTerm::Atom(Atom::String(name)));
+    let binding = solver.heap.alloc(HeapTerm::Compound(str::EQ, 2));
+    solver.heap.alloc(HeapTerm::Var(name));
+    solver.heap.alloc(HeapTerm::Var(var));
+    binding
+}
+
+// The text of an atom, a string or a list of codes, or `None` if it isn't known yet
+fn text_of(
+    solver: &Solver,
+    ptr: HeapTermPtr,
+    expected: &'static str,
+) -> Result<Option<String>, BuiltinError> {
+    match solver.heap.get(ptr) {
+        HeapTerm::Atom(Atom::String(id)) => Ok(Some(solver.heap.get_atom(id).to_string())),
+        HeapTerm::Atom(Atom::Text(_)) | HeapTerm::Compound(str::DOT, 2) | HeapTerm::Var(_) => {
+            text(solver, ptr)
+        }
+        _ => Err(BuiltinError::Type(expected, ptr)),
+    }
+}
+
+// An error in the text of a term, which is read as the given kind of text
+fn syntax_error(
+    read: &'static str,
+    text: &str,
+    error: lalrpop_util::ParseError<usize, grammar::Token<'_>, ast::UserError>,
+) -> BuiltinError {
+    BuiltinError::Syntax(read, ast::parse_error(text, false, error).error)
+}
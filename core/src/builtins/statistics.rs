@@ -1,5 +1,6 @@
 use crate::atom::Atom;
 use crate::builtins::{Builtin, BuiltinError};
+use crate::stringmap::str;
 use crate::{HeapTerm, HeapTermPtr, Solver};
 
 pub struct StatisticsBuiltin;
@@ -7,10 +8,43 @@ pub struct StatisticsBuiltin;
 impl Builtin<2> for StatisticsBuiltin {
     fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
         Ok(match solver.heap.get(args) {
-            HeapTerm::Atom(Atom::String(id)) => match solver.heap.get_atom(*id) {
+            HeapTerm::Atom(Atom::String(id)) => match solver.heap.get_atom(id) {
                 "memory" => unify_int(solver, args + 1, solver.heap.size() as i64),
                 "allocated" => unify_int(solver, args + 1, solver.heap.capacity() as i64),
                 "gc" => unify_int(solver, args + 1, solver.gc.runs() as i64),
+                "gc_time" => unify_int(solver, args + 1, solver.gc.time() as i64),
+                "gc_collected" => unify_int(solver, args + 1, solver.gc.collected() as i64),
+                "gc_atoms" => unify_int(solver, args + 1, solver.gc.collected_atoms() as i64),
+                "gc_max_pause" => unify_float(solver, args + 1, solver.gc.max_pause()),
+                "gc_trail" => unify_int(solver, args + 1, solver.gc.collected_trail() as i64),
+                "gc_last" => match solver.gc.last().copied() {
+                    Some(record) => unify_ints(
+                        solver,
+                        args + 1,
+                        &[
+                            record.cells_before as i64,
+                            record.cells_after as i64,
+                            record.trail_removed as i64,
+                            record.choice_points_scanned as i64,
+                            record.time as i64,
+                        ],
+                    ),
+                    None => false,
+                },
+                "inferences" => unify_int(solver, args + 1, solver.stats.inferences as i64),
+                "choicepoints" => unify_int(solver, args + 1, solver.choice_points.len() as i64),
+                "trail" => unify_int(solver, args + 1, solver.trail.vars.len() as i64),
+                "goals" => unify_int(solver, args + 1, solver.goals.goals.len() as i64),
+                "atoms" => unify_int(solver, args + 1, solver.heap.string_map.count() as i64),
+                "cputime" => unify_float(solver, args + 1, solver.stats.cpu_time() / 1000.0),
+                "runtime" => {
+                    let (total, since) = solver.stats.runtime();
+                    unify_ints(solver, args + 1, &[total as i64, since as i64])
+                }
+                "walltime" => {
+                    let (total, since) = solver.stats.walltime();
+                    unify_ints(solver, args + 1, &[total as i64, since as i64])
+                }
 
                 #[cfg(target_family = "wasm")]
                 "wasm_memory" => unify_int(solver, args + 1, crate::wasm::memory() as i64),
@@ -26,3 +60,22 @@ fn unify_int(solver: &mut Solver, a: HeapTermPtr, i: i64) -> bool {
     let atom: HeapTermPtr = solver.heap.alloc(HeapTerm::Atom(Atom::Integer(i)));
     solver.unify(a, atom)
 }
+
+fn unify_float(solver: &mut Solver, a: HeapTermPtr, f: f64) -> bool {
+    let atom: HeapTermPtr = solver.heap.alloc(HeapTerm::Atom(Atom::Float(f)));
+    solver.unify(a, atom)
+}
+
+fn unify_ints(solver: &mut Solver, a: HeapTermPtr, ints: &[i64]) -> bool {
+    let mut list = solver.heap.alloc(HeapTerm::Atom(Atom::String(str::NIL)));
+
+    for i in ints.iter().rev() {
+        let head = solver.heap.alloc(HeapTerm::Atom(Atom::Integer(*i)));
+        let tail = list;
+        list = solver.heap.alloc(HeapTerm::Compound(str::DOT, 2));
+        solver.heap.alloc(HeapTerm::Var(head));
+        solver.heap.alloc(HeapTerm::Var(tail));
+    }
+
+    solver.unify(a, list)
+}
@@ -0,0 +1,113 @@
+use crate::atom::Atom;
+use crate::builtins::{Builtin, BuiltinError};
+use crate::flags::Flags;
+use crate::stringmap::str;
+use crate::{ast, grammar, HeapTerm, HeapTermPtr, Solver};
+
+use std::cell::Cell;
+use std::sync::LazyLock;
+
+static NUMBER_PARSER: LazyLock<grammar::NumberLiteralParser> =
+    LazyLock::new(grammar::NumberLiteralParser::new);
+
+pub struct NumberCodesBuiltin;
+
+impl Builtin<2> for NumberCodesBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        let number = match solver.heap.get(args) {
+            HeapTerm::Atom(atom @ (Atom::Integer(_) | Atom::Float(_))) => Some(atom),
+            HeapTerm::Var(_) => None,
+            _ => return Err(BuiltinError::Type("number", args)),
+        };
+
+        // The codes are read whenever they're complete, even if the number is known
+        match (text(solver, args + 1)?, number) {
+            (Some(text), _) => {
+                let atom = read_number(&text).ok_or(BuiltinError::IllegalNumber(text))?;
+                let atom = solver.heap.alloc(HeapTerm::Atom(atom));
+                Ok(solver.unify(args, atom))
+            }
+            (None, Some(number)) => {
+                let text = number.to_string(&solver.heap.string_map);
+                let codes = codes(solver, &text);
+                Ok(solver.unify(args + 1, codes))
+            }
+            (None, None) => Err(BuiltinError::InsufficientlyInstantiated(args)),
+        }
+    }
+}
+
+// Reads a number with an optional sign, allowing layout before it but not
+// after it or between the sign and the digits, which the tokenizer would skip
+fn read_number(text: &str) -> Option<Atom> {
+    let number = text.trim_start();
+    let unsigned = number.strip_prefix(['-', '+']).unwrap_or(number);
+    if unsigned.starts_with(char::is_whitespace) || number.ends_with(char::is_whitespace) {
+        return None;
+    }
+
+    let flags = Cell::new(Flags::default());
+    match NUMBER_PARSER.parse(&mut Vec::new(), &flags, text) {
+        Ok(ast::Atom::Integer(i)) => Some(Atom::Integer(i)),
+        Ok(ast::Atom::Float(f)) => Some(Atom::Float(f)),
+        _ => None,
+    }
+}
+
+// The text of a list of character codes or a string, or `None` if the list
+// isn't complete yet
+pub(super) fn text(solver: &Solver, ptr: HeapTermPtr) -> Result<Option<String>, BuiltinError> {
+    let heap = &solver.heap;
+    let mut text = String::new();
+    let mut list = ptr;
+
+    // A list can't be longer than the heap, so a longer one is cyclic
+    for _ in 0..heap.len() {
+        match heap.get(list) {
+            HeapTerm::Atom(Atom::Text(id)) if list == ptr => {
+                return Ok(Some(heap.get_atom(id).to_string()));
+            }
+            HeapTerm::Atom(atom) if atom.is_nil() => return Ok(Some(text)),
+            HeapTerm::Compound(str::DOT, 2) => {
+                let list_ptr = heap.get_ptr(list);
+                match heap.get(list_ptr + 1) {
+                    HeapTerm::Atom(Atom::Integer(code)) => {
+                        let c = u32::try_from(code)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or(BuiltinError::Representation("character_code", list_ptr + 1))?;
+                        text.push(c);
+                    }
+                    HeapTerm::Var(_) => return Ok(None),
+                    _ => return Err(BuiltinError::Representation("character_code", list_ptr + 1)),
+                }
+                list = list_ptr + 2;
+            }
+            HeapTerm::Var(_) => return Ok(None),
+            _ => return Err(BuiltinError::Type("list", ptr)),
+        }
+    }
+
+    Err(BuiltinError::CyclicTerm(ptr))
+}
+
+pub(super) fn codes(solver: &mut Solver, text: &str) -> HeapTermPtr {
+    let codes: Vec<_> = text
+        .chars()
+        .map(|c| solver.heap.alloc(HeapTerm::Atom(Atom::Integer(c as i64))))
+        .collect();
+    list(solver, &codes)
+}
+
+pub(super) fn list(solver: &mut Solver, items: &[HeapTermPtr]) -> HeapTermPtr {
+    let mut list = solver.heap.alloc(HeapTerm::Atom(Atom::String(str::NIL)));
+
+    for &head in items.iter().rev() {
+        let tail = list;
+        list = solver.heap.alloc(HeapTerm::Compound(str::DOT, 2));
+        solver.heap.alloc(HeapTerm::Var(head));
+        solver.heap.alloc(HeapTerm::Var(tail));
+    }
+
+    list
+}
@@ -0,0 +1,67 @@
+use crate::builtins::{Builtin, BuiltinError};
+use crate::stringmap::str;
+use crate::{Atom, HeapTerm, HeapTermPtr, Solver, StringId};
+
+pub struct TraceBuiltin;
+
+pub struct NoTraceBuiltin;
+
+pub struct SpyBuiltin;
+
+pub struct NoSpyBuiltin;
+
+impl Builtin<0> for TraceBuiltin {
+    fn eval(solver: &mut Solver, _: HeapTermPtr) -> Result<bool, BuiltinError> {
+        solver.tracer.set_tracing(true);
+        Ok(true)
+    }
+}
+
+impl Builtin<0> for NoTraceBuiltin {
+    fn eval(solver: &mut Solver, _: HeapTermPtr) -> Result<bool, BuiltinError> {
+        solver.tracer.set_tracing(false);
+        Ok(true)
+    }
+}
+
+impl Builtin<1> for SpyBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        let (functor, arity) = predicate_indicator(solver, args)?;
+        solver.tracer.spy(functor, arity);
+        Ok(true)
+    }
+}
+
+impl Builtin<1> for NoSpyBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        let (functor, arity) = predicate_indicator(solver, args)?;
+        solver.tracer.nospy(functor, arity);
+        Ok(true)
+    }
+}
+
+// Accepts either `Name/Arity` or `Name`, which matches all arities.
+fn predicate_indicator(
+    solver: &Solver,
+    args: HeapTermPtr,
+) -> Result<(StringId, Option<usize>), BuiltinError> {
+    let ptr = solver.heap.get_ptr(args);
+
+    match solver.heap.get(ptr) {
+        HeapTerm::Atom(Atom::String(functor)) => Ok((functor, None)),
+        HeapTerm::Compound(str::DIV, 2) => {
+            match (solver.heap.get(ptr + 1), solver.heap.get(ptr + 2)) {
+                (HeapTerm::Atom(Atom::String(functor)), HeapTerm::Atom(Atom::Integer(arity)))
+                    if arity >= 0 =>
+                {
+                    Ok((functor, Some(arity as usize)))
+                }
+                (HeapTerm::Var(_), _) => Err(BuiltinError::InsufficientlyInstantiated(ptr + 1)),
+                (_, HeapTerm::Var(_)) => Err(BuiltinError::InsufficientlyInstantiated(ptr + 2)),
+                _ => Err(BuiltinError::NotAPredicateIndicator(ptr)),
+            }
+        }
+        HeapTerm::Var(_) => Err(BuiltinError::InsufficientlyInstantiated(ptr)),
+        _ => Err(BuiltinError::NotAPredicateIndicator(ptr)),
+    }
+}
@@ -15,14 +15,31 @@ macro_rules! impl_type_check {
 
 impl_type_check!(IsIntegerBuiltin, HeapTerm::Atom(Atom::Integer(_)));
 impl_type_check!(IsFloatBuiltin, HeapTerm::Atom(Atom::Float(_)));
-impl_type_check!(IsAtomBuiltin, HeapTerm::Atom(_));
+impl_type_check!(IsAtomBuiltin, HeapTerm::Atom(Atom::String(_)));
+impl_type_check!(IsStringBuiltin, HeapTerm::Atom(Atom::Text(_)));
 impl_type_check!(
     IsNumberBuiltin,
     HeapTerm::Atom(Atom::Integer(_)) | HeapTerm::Atom(Atom::Float(_))
 );
-impl_type_check!(IsVarBuiltin, HeapTerm::Var(_, _, _, _));
+impl_type_check!(IsVarBuiltin, HeapTerm::Var(_));
 impl_type_check!(IsCompoundBuiltin, HeapTerm::Compound(_, _));
 impl_type_check!(
     IsNonVarBuiltin,
     HeapTerm::Atom(_) | HeapTerm::Compound(_, _) | HeapTerm::Cut(_) | HeapTerm::Lambda(_, _)
 );
+
+pub struct IsCyclicBuiltin;
+
+impl Builtin<1> for IsCyclicBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        Ok(solver.heap.is_cyclic(args))
+    }
+}
+
+pub struct IsAcyclicBuiltin;
+
+impl Builtin<1> for IsAcyclicBuiltin {
+    fn eval(solver: &mut Solver, args: HeapTermPtr) -> Result<bool, BuiltinError> {
+        Ok(!solver.heap.is_cyclic(args))
+    }
+}
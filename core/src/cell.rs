@@ -0,0 +1,200 @@
+use crate::atom::Atom;
+use crate::HeapTerm;
+
+use serde::{Deserialize, Serialize};
+
+// A heap cell is a single 64-bit word: a 4-bit tag and a 60-bit payload.
+// Floats and integers that don't fit in the payload are boxed, taking a header
+// cell with the high 32 bits followed by a `RAW` cell with the low 32 bits.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Cell(u64);
+
+const TAG_BITS: u32 = 4;
+const TAG_MASK: u64 = (1 << TAG_BITS) - 1;
+
+const VAR: u64 = 0; // ptr << 2 | attributed << 1 | shunted
+const ATOM: u64 = 1; // string id
+const INTEGER: u64 = 2; // signed 60-bit integer
+const BIG_INTEGER: u64 = 3; // high 32 bits, boxed
+const FLOAT: u64 = 4; // high 32 bits, boxed
+const RAW: u64 = 5; // low 32 bits of a boxed value
+const COMPOUND: u64 = 6; // functor << ARITY_BITS | arity
+const LAMBDA: u64 = 7; // lambda id << ARITY_BITS | arity
+const CUT: u64 = 8; // choice point index
+const TEXT: u64 = 9; // string id
+
+const SHUNTED: u64 = 1;
+const ATTRIBUTED: u64 = 2;
+const VAR_FLAGS: u32 = 2;
+
+const ARITY_BITS: u32 = 24;
+const ARITY_MASK: u64 = (1 << ARITY_BITS) - 1;
+
+impl Cell {
+    const fn new(tag: u64, payload: u64) -> Self {
+        Self(payload << TAG_BITS | tag)
+    }
+
+    pub const fn var(ptr: usize) -> Self {
+        Self::new(VAR, (ptr as u64) << VAR_FLAGS)
+    }
+
+    // Encodes a term into one cell, or two if it is boxed.
+    #[inline(always)]
+    pub fn encode(term: HeapTerm) -> (Self, Option<Self>) {
+        match term {
+            HeapTerm::Atom(Atom::String(id)) => (Self::new(ATOM, id as u64), None),
+            HeapTerm::Atom(Atom::Integer(i)) if Self::fits(i) => {
+                (Self::new(INTEGER, i as u64 & (u64::MAX >> TAG_BITS)), None)
+            }
+            HeapTerm::Atom(Atom::Integer(i)) => Self::boxed(BIG_INTEGER, i as u64),
+            HeapTerm::Atom(Atom::Float(f)) => Self::boxed(FLOAT, f.to_bits()),
+            HeapTerm::Atom(Atom::Text(id)) => (Self::new(TEXT, id as u64), None),
+            HeapTerm::Var(ptr) => (Self::var(ptr), None),
+            HeapTerm::Compound(functor, arity) => (Self::structure(COMPOUND, functor, arity), None),
+            HeapTerm::Lambda(id, arity) => (Self::structure(LAMBDA, id, arity), None),
+            HeapTerm::Cut(idx) => (Self::new(CUT, idx as u64), None),
+        }
+    }
+
+    // Decodes the cell, given the cell after it in case it is boxed.
+    #[inline(always)]
+    pub fn decode(self, next: impl FnOnce() -> Cell) -> HeapTerm {
+        let payload = self.payload();
+
+        match self.tag() {
+            VAR => HeapTerm::Var(self.ptr()),
+            ATOM => HeapTerm::Atom(Atom::String(payload as usize)),
+            INTEGER => HeapTerm::Atom(Atom::Integer((self.0 as i64) >> TAG_BITS)),
+            BIG_INTEGER => HeapTerm::Atom(Atom::Integer(self.unbox(next()) as i64)),
+            FLOAT => HeapTerm::Atom(Atom::Float(f64::from_bits(self.unbox(next())))),
+            COMPOUND => HeapTerm::Compound(
+                (payload >> ARITY_BITS) as usize,
+                (payload & ARITY_MASK) as usize,
+            ),
+            LAMBDA => HeapTerm::Lambda(
+                (payload >> ARITY_BITS) as usize,
+                (payload & ARITY_MASK) as usize,
+            ),
+            CUT => HeapTerm::Cut(payload as usize),
+            TEXT => HeapTerm::Atom(Atom::Text(payload as usize)),
+            _ => self.invalid(),
+        }
+    }
+
+    #[inline]
+    pub fn is_var(self) -> bool {
+        self.tag() == VAR
+    }
+
+    // The cell is followed by a `RAW` cell
+    #[inline]
+    pub fn is_boxed(self) -> bool {
+        matches!(self.tag(), BIG_INTEGER | FLOAT)
+    }
+
+    #[inline]
+    pub fn is_raw(self) -> bool {
+        self.tag() == RAW
+    }
+
+    // Whether the cell is a valid start of a term, i.e. not the second half of a boxed one
+    pub fn is_valid(self) -> bool {
+        self.tag() <= TEXT && self.tag() != RAW
+    }
+
+    // The variable this one is bound to, or itself if unbound
+    #[inline]
+    pub fn ptr(self) -> usize {
+        (self.payload() >> VAR_FLAGS) as usize
+    }
+
+    #[inline]
+    pub fn set_ptr(&mut self, ptr: usize) {
+        debug_assert!(self.is_var());
+        *self = Self::new(
+            VAR,
+            (ptr as u64) << VAR_FLAGS | self.payload() & (SHUNTED | ATTRIBUTED),
+        );
+    }
+
+    #[inline]
+    pub fn is_shunted(self) -> bool {
+        self.is_var() && self.payload() & SHUNTED != 0
+    }
+
+    #[inline]
+    pub fn is_attributed(self) -> bool {
+        self.is_var() && self.payload() & ATTRIBUTED != 0
+    }
+
+    pub fn set_shunted(&mut self) {
+        debug_assert!(self.is_var());
+        self.0 |= SHUNTED << TAG_BITS;
+    }
+
+    pub fn set_attributed(&mut self, attributed: bool) {
+        debug_assert!(self.is_var());
+        if attributed {
+            self.0 |= ATTRIBUTED << TAG_BITS;
+        } else {
+            self.0 &= !(ATTRIBUTED << TAG_BITS);
+        }
+    }
+
+    #[inline]
+    fn tag(self) -> u64 {
+        self.0 & TAG_MASK
+    }
+
+    #[inline]
+    fn payload(self) -> u64 {
+        self.0 >> TAG_BITS
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn invalid(self) -> ! {
+        unreachable!("Invalid heap cell {:#x}", self.0)
+    }
+
+    fn fits(i: i64) -> bool {
+        (i << TAG_BITS) >> TAG_BITS == i
+    }
+
+    fn structure(tag: u64, id: usize, arity: usize) -> Self {
+        if arity as u64 > ARITY_MASK {
+            Self::too_large(arity);
+        }
+        Self::new(tag, (id as u64) << ARITY_BITS | arity as u64)
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn too_large(arity: usize) -> ! {
+        panic!("Arity {} is too large", arity)
+    }
+
+    fn boxed(tag: u64, bits: u64) -> (Self, Option<Self>) {
+        (
+            Self::new(tag, bits >> 32),
+            Some(Self::new(RAW, bits & u32::MAX as u64)),
+        )
+    }
+
+    fn unbox(self, raw: Cell) -> u64 {
+        debug_assert_eq!(raw.tag(), RAW);
+        self.payload() << 32 | raw.payload()
+    }
+}
+
+impl std::fmt::Debug for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.tag() {
+            RAW => write!(f, "Raw({:#x})", self.payload()),
+            _ if self.is_boxed() => write!(f, "Boxed({:#x})", self.payload()),
+            _ => write!(f, "{:?}", self.decode(|| unreachable!())),
+        }
+    }
+}
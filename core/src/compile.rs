@@ -1,51 +1,642 @@
+use crate::stringmap::StringMap;
+use crate::vm::{Commit, CompiledClause, GoalSource, Instruction, Key, Register};
 use crate::{
-    ast, Atom, ClauseName, Heap, HeapClausePtr, HeapTerm, HeapTermPtr, Index, Lambda, LambdaId,
-    StringId,
+    ast, Atom, ClauseName, Heap, HeapTerm, HeapTermPtr, Index, Lambda, LambdaId, StringId,
 };
 
-pub fn compile(ast_program: ast::Program, heap: &mut Heap, lambdas: &mut Vec<Lambda>) -> Index {
-    let mut index: Vec<(ClauseName, Vec<HeapClausePtr>)> = Vec::new();
+// Adds clauses and the predicates declared dynamic to a program's index, and
+// returns the predicates that changed. The determinism analysis of each clause
+// is kept, as clauses added later change whether earlier ones can commit.
+#[allow(clippy::too_many_arguments)]
+pub fn compile(
+    clauses: impl IntoIterator<Item = ast::Clause>,
+    dynamic: &[(&str, usize)],
+    index: &mut Index,
+    analysis: &mut Analyses,
+    string_map: &mut StringMap,
+    lambdas: &mut Vec<Lambda>,
+    code: &mut Vec<Instruction>,
+    sources: &mut Vec<GoalSource>,
+) -> Vec<usize> {
+    let mut changed = Vec::new();
 
-    let mut var_map = Vec::new();
-    for ast_clause in ast_program.0 {
-        var_map.clear();
+    for (name, arity) in dynamic {
+        let clause_name = ClauseName(string_map.alloc(name), *arity);
+        if !index.iter().any(|(name, _)| *name == clause_name) {
+            index.push((clause_name, Vec::new()));
+            analysis.push(Vec::new());
+        }
+    }
+
+    for ast_clause in clauses {
+        let (clause_name, args) = match &ast_clause.0 {
+            ast::Term::Atom(ast::Atom::String(name), _) => {
+                (ClauseName(string_map.alloc(name), 0), &[][..])
+            }
+            ast::Term::Compound(functor, args, _) => {
+                (ClauseName(string_map.alloc(functor), args.len()), &args[..])
+            }
+            _ => panic!("Invalid clause head"),
+        };
+
+        let key = match args.first() {
+            Some(ast::Term::Atom(atom, _)) => Some(Key::Atom(Atom::new(string_map, atom))),
+            Some(ast::Term::Compound(functor, args, _)) => {
+                Some(Key::Functor(string_map.alloc(functor), args.len()))
+            }
+            _ => None,
+        };
+
+        let group_index = index
+            .iter()
+            .position(|(name, _)| *name == clause_name)
+            .unwrap_or(index.len());
+        let clause_index = index
+            .get(group_index)
+            .map_or(0, |(_, clauses)| clauses.len());
+
+        let first_source = sources.len();
+        sources.extend(ast_clause.1.iter().map(|goal| GoalSource {
+            span: goal.span(),
+            predicate: group_index,
+            clause: clause_index,
+        }));
+
+        let mut compiler = ClauseCompiler {
+            string_map: &mut *string_map,
+            lambdas: &mut *lambdas,
+            code: &mut *code,
+            vars: Vec::new(),
+            registers: args.len(),
+        };
+
+        let head = compiler.code.len();
+        compiler.compile_head(args);
+
+        let body = compiler.code.len();
+        compiler.compile_body(&ast_clause.1, first_source);
+
+        let clause_analysis = Analysis::new(args, &ast_clause.1);
+
+        let clause = CompiledClause {
+            head,
+            body,
+            end: compiler.code.len(),
+            goals: ast_clause.1.len(),
+            registers: compiler.registers,
+            key,
+            guards: clause_analysis.guards.len(),
+            commit: Commit::Never,
+        };
+
+        if group_index < index.len() {
+            index[group_index].1.push(clause);
+            analysis[group_index].push(clause_analysis);
+        } else {
+            index.push((clause_name, vec![clause]));
+            analysis.push(vec![clause_analysis]);
+        }
+
+        changed.push(group_index);
+    }
+
+    changed.sort_unstable();
+    changed.dedup();
+    for &group in &changed {
+        let clauses = &mut index[group].1;
+        for i in 0..clauses.len() {
+            clauses[i].commit = commit(clauses, &analysis[group], i);
+        }
+    }
+
+    changed
+}
+
+// What determinism analysis found about each clause of each predicate
+pub type Analyses = Vec<Vec<Analysis>>;
+
+// Determinism analysis: a clause can commit without leaving a choice point
+// once its guards (leading tests without side effects) succeed if they are
+// followed by a cut, or if no later clause can succeed at the same time.
+pub struct Analysis {
+    guards: Vec<Option<Test>>,
+    cut: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Test {
+    Compare(Cmp, Operand, Operand),
+    Var(usize),
+    NonVar(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+// Guards can only be compared if they test arguments of the head
+#[derive(Clone, Copy, PartialEq)]
+enum Operand {
+    Arg(usize),
+    Integer(i64),
+    Float(f64),
+}
+
+impl Analysis {
+    fn new(args: &[ast::Term], body: &[ast::Term]) -> Self {
+        let guards: Vec<Option<Test>> = body
+            .iter()
+            .take_while(|goal| is_guard(goal))
+            .map(|goal| Test::new(args, goal))
+            .collect();
+
+        let cut = matches!(body.get(guards.len()), Some(ast::Term::Cut(_)));
+
+        Self { guards, cut }
+    }
+}
+
+fn is_guard(goal: &ast::Term) -> bool {
+    match goal {
+        ast::Term::Compound(name, args, _) if args.len() == 2 => {
+            matches!(
+                name.as_str(),
+                "<" | "=<" | ">" | ">=" | "=:=" | "=\\=" | "=="
+            )
+        }
+        ast::Term::Compound(name, args, _) if args.len() == 1 => matches!(
+            name.as_str(),
+            "var" | "nonvar" | "atom" | "integer" | "float" | "number" | "compound"
+        ),
+        _ => false,
+    }
+}
+
+impl Test {
+    fn new(head: &[ast::Term], goal: &ast::Term) -> Option<Self> {
+        let ast::Term::Compound(name, args, _) = goal else {
+            return None;
+        };
+
+        let operand = |term: &ast::Term| match term {
+            ast::Term::Atom(ast::Atom::Integer(i), _) => Some(Operand::Integer(*i)),
+            ast::Term::Atom(ast::Atom::Float(f), _) => Some(Operand::Float(*f)),
+            ast::Term::Variable(var, _) if var != "_" => head
+                .iter()
+                .position(|arg| matches!(arg, ast::Term::Variable(v, _) if v == var))
+                .filter(|arg| *arg < 32)
+                .map(Operand::Arg),
+            _ => None,
+        };
+
+        let cmp = match name.as_str() {
+            "<" => Cmp::Lt,
+            "=<" => Cmp::Le,
+            ">" => Cmp::Gt,
+            ">=" => Cmp::Ge,
+            "=:=" => Cmp::Eq,
+            "=\\=" => Cmp::Ne,
+            "var" | "nonvar" => {
+                return match operand(&args[0])? {
+                    Operand::Arg(arg) if name == "var" => Some(Test::Var(arg)),
+                    Operand::Arg(arg) => Some(Test::NonVar(arg)),
+                    _ => None,
+                }
+            }
+            _ => return None,
+        };
+
+        Some(Test::Compare(cmp, operand(&args[0])?, operand(&args[1])?))
+    }
+
+    // Whether both tests can't succeed for the same arguments
+    fn excludes(&self, other: &Test) -> bool {
+        match (*self, *other) {
+            (Test::Var(a), Test::NonVar(b)) | (Test::NonVar(a), Test::Var(b)) => a == b,
+            (Test::Compare(cmp, a, b), Test::Compare(other, c, d)) => {
+                let other = if (a, b) == (c, d) {
+                    other
+                } else if (a, b) == (d, c) {
+                    other.flip()
+                } else {
+                    return false;
+                };
+
+                matches!(
+                    (cmp, other),
+                    (Cmp::Lt, Cmp::Ge | Cmp::Gt | Cmp::Eq)
+                        | (Cmp::Gt, Cmp::Le | Cmp::Lt | Cmp::Eq)
+                        | (Cmp::Le, Cmp::Gt)
+                        | (Cmp::Ge, Cmp::Lt)
+                        | (Cmp::Eq, Cmp::Ne | Cmp::Lt | Cmp::Gt)
+                        | (Cmp::Ne, Cmp::Eq)
+                )
+            }
+            _ => false,
+        }
+    }
+
+    // Arguments that must be bound before the head is unified, so that
+    // they can't differ between clauses
+    fn args(&self) -> u32 {
+        let bit = |operand: Operand| match operand {
+            Operand::Arg(arg) => 1 << arg,
+            _ => 0,
+        };
+
+        match *self {
+            Test::Compare(_, a, b) => bit(a) | bit(b),
+            Test::Var(arg) | Test::NonVar(arg) => 1 << arg,
+        }
+    }
+}
+
+impl Cmp {
+    fn flip(self) -> Self {
+        match self {
+            Cmp::Lt => Cmp::Gt,
+            Cmp::Le => Cmp::Ge,
+            Cmp::Gt => Cmp::Lt,
+            Cmp::Ge => Cmp::Le,
+            cmp => cmp,
+        }
+    }
+}
+
+fn commit(clauses: &[CompiledClause], analysis: &[Analysis], i: usize) -> Commit {
+    if analysis[i].cut {
+        return Commit::Cut;
+    }
+
+    let mut args = 0;
+    let mut indexed = false;
+
+    for j in i + 1..clauses.len() {
+        if let (Some(a), Some(b)) = (clauses[i].key, clauses[j].key) {
+            if a != b {
+                indexed = true;
+                continue;
+            }
+        }
+
+        // Only the first guard of the later clause is considered, as an earlier one
+        // could raise an error
+        let Some(Some(other)) = analysis[j].guards.first() else {
+            return Commit::Never;
+        };
 
-        let goals = heap.data.len();
-        let goals_length = ast_clause.1.len();
-        for _ in &ast_clause.1 {
-            heap.alloc_new_var();
+        match analysis[i]
+            .guards
+            .iter()
+            .flatten()
+            .find(|test| test.excludes(other))
+        {
+            Some(test) => args |= test.args(),
+            None => return Commit::Never,
         }
+    }
+
+    match analysis[i].guards.is_empty() {
+        true => Commit::Never,
+        false => Commit::Exclusive { args, indexed },
+    }
+}
+
+struct ClauseCompiler<'a, 't> {
+    string_map: &'a mut StringMap,
+    lambdas: &'a mut Vec<Lambda>,
+    code: &'a mut Vec<Instruction>,
+    vars: Vec<(&'t str, Register)>,
+    registers: usize,
+}
+
+impl<'a, 't> ClauseCompiler<'a, 't> {
+    fn compile_head(&mut self, args: &'t [ast::Term]) {
+        let mut deferred = Vec::new();
+
+        for (arg, term) in args.iter().enumerate() {
+            self.get(term, arg, &mut deferred);
+        }
+
+        // Nested structures are unified after their parent (top-down)
+        while let Some((reg, term)) = deferred.pop() {
+            self.get(term, reg, &mut deferred);
+        }
+    }
 
-        let (head, clause_name) = ast_clause.0.alloc(heap, &mut var_map, lambdas);
-        let head_length = heap.data.len() - head;
-        let clause_name = clause_name.unwrap();
+    fn compile_body(&mut self, goals: &'t [ast::Term], first_source: usize) {
+        let goals: Vec<Register> = goals.iter().map(|goal| self.put(goal)).collect();
 
-        let group_index = index.iter().position(|(name, _)| *name == clause_name);
-        let group = if let Some(group_index) = group_index {
-            &mut index[group_index].1
+        for (i, goal) in goals.into_iter().enumerate().rev() {
+            self.code.push(Instruction::Call(goal, first_source + i));
+        }
+    }
+
+    fn get(
+        &mut self,
+        term: &'t ast::Term,
+        reg: Register,
+        deferred: &mut Vec<(Register, &'t ast::Term)>,
+    ) {
+        match term {
+            ast::Term::Atom(atom, _) => {
+                let atom = Atom::new(self.string_map, atom);
+                self.code.push(Instruction::GetConstant(atom, reg));
+            }
+            ast::Term::Variable(var, _) if var == "_" => {}
+            ast::Term::Variable(var, _) => match self.var(var) {
+                (var, true) => self.code.push(Instruction::GetValue(var, reg)),
+                (var, false) => self.code.push(Instruction::GetVariable(var, reg)),
+            },
+            ast::Term::Compound(functor, args, _) => {
+                let functor = self.string_map.alloc(functor);
+                self.code
+                    .push(Instruction::GetStructure(functor, args.len(), reg));
+
+                for arg in args {
+                    self.unify(arg, deferred);
+                }
+            }
+            ast::Term::Lambda(_, _, _) | ast::Term::Cut(_) => {
+                let term = self.put(term);
+                self.code.push(Instruction::GetValue(term, reg));
+            }
+        }
+    }
+
+    fn unify(&mut self, term: &'t ast::Term, deferred: &mut Vec<(Register, &'t ast::Term)>) {
+        match term {
+            ast::Term::Atom(atom, _) => {
+                let atom = Atom::new(self.string_map, atom);
+                self.code.push(Instruction::UnifyConstant(atom));
+            }
+            ast::Term::Variable(var, _) if var == "_" => self.code.push(Instruction::UnifyVoid),
+            ast::Term::Variable(var, _) => match self.var(var) {
+                (var, true) => self.code.push(Instruction::UnifyValue(var)),
+                (var, false) => self.code.push(Instruction::UnifyVariable(var)),
+            },
+            _ => {
+                let reg = self.temp();
+                self.code.push(Instruction::UnifyVariable(reg));
+                deferred.push((reg, term));
+            }
+        }
+    }
+
+    fn put(&mut self, term: &'t ast::Term) -> Register {
+        match term {
+            ast::Term::Atom(atom, _) => {
+                let atom = Atom::new(self.string_map, atom);
+                let reg = self.temp();
+                self.code.push(Instruction::PutConstant(atom, reg));
+                reg
+            }
+            ast::Term::Variable(var, _) if var == "_" => {
+                let reg = self.temp();
+                self.code.push(Instruction::PutVariable(reg));
+                reg
+            }
+            ast::Term::Variable(var, _) => {
+                let (reg, seen) = self.var(var);
+                if !seen {
+                    self.code.push(Instruction::PutVariable(reg));
+                }
+                reg
+            }
+            ast::Term::Compound(functor, args, _) => {
+                // Nested structures are built before their parent (bottom-up)
+                let nested: Vec<Option<Register>> = args
+                    .iter()
+                    .map(|arg| match arg {
+                        ast::Term::Atom(_, _) | ast::Term::Variable(_, _) => None,
+                        _ => Some(self.put(arg)),
+                    })
+                    .collect();
+
+                let functor = self.string_map.alloc(functor);
+                let reg = self.temp();
+                self.code
+                    .push(Instruction::PutStructure(functor, args.len(), reg));
+
+                for (arg, nested) in args.iter().zip(nested) {
+                    match nested {
+                        Some(nested) => self.code.push(Instruction::SetValue(nested)),
+                        None => self.set(arg),
+                    }
+                }
+
+                reg
+            }
+            ast::Term::Lambda(js, args, _) => {
+                let lambda_id: LambdaId = self.lambdas.len();
+                self.lambdas.push(Lambda {
+                    js: js.clone(),
+                    arg_names: args.clone(),
+                });
+
+                let reg = self.temp();
+                self.code
+                    .push(Instruction::PutLambda(lambda_id, args.len(), reg));
+
+                for arg in args {
+                    self.set_var(arg);
+                }
+
+                reg
+            }
+            ast::Term::Cut(_) => {
+                let reg = self.temp();
+                self.code.push(Instruction::PutCut(reg));
+                reg
+            }
+        }
+    }
+
+    fn set(&mut self, term: &'t ast::Term) {
+        match term {
+            ast::Term::Atom(atom, _) => {
+                let atom = Atom::new(self.string_map, atom);
+                self.code.push(Instruction::SetConstant(atom));
+            }
+            ast::Term::Variable(var, _) => self.set_var(var),
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_var(&mut self, var: &'t str) {
+        if var == "_" {
+            self.code.push(Instruction::SetVoid);
         } else {
-            index.push((clause_name, Vec::new()));
-            &mut index.last_mut().unwrap().1
+            match self.var(var) {
+                (var, true) => self.code.push(Instruction::SetValue(var)),
+                (var, false) => self.code.push(Instruction::SetVariable(var)),
+            }
+        }
+    }
+
+    // Returns the register of the given variable, and whether it has been seen before.
+    fn var(&mut self, name: &'t str) -> (Register, bool) {
+        if let Some((_, reg)) = self.vars.iter().find(|(var, _)| *var == name) {
+            (*reg, true)
+        } else {
+            let reg = self.temp();
+            self.vars.push((name, reg));
+            (reg, false)
+        }
+    }
+
+    fn temp(&mut self) -> Register {
+        self.registers += 1;
+        self.registers - 1
+    }
+}
+
+// Translates a grammar rule into a clause. Each nonterminal takes the list
+// before and after the text it describes, so `a --> b, [x].` becomes
+// `a(S0, S) :- b(S0, S1), S1 = [x|S].`
+pub fn dcg_rule(
+    head: ast::Term,
+    pushback: Option<ast::Term>,
+    body: Vec<ast::Term>,
+    span: ast::Span,
+) -> Result<ast::Clause, ast::UserError> {
+    let mut rule = Dcg {
+        vars: 0,
+        goals: Vec::new(),
+    };
+
+    let start = rule.var(span);
+    let end = rule.var(span);
+    let head = nonterminal(head, start.clone(), end.clone())
+        .ok_or(ast::UserError(span, "Invalid grammar rule head"))?;
+
+    // Pushback is put back in front of the rest of the list once the body ends
+    let body_end = match &pushback {
+        Some(pushback) => rule.var(pushback.span()),
+        None => end.clone(),
+    };
+
+    let mut list = start;
+    let last = body.len() - 1;
+    for (i, term) in body.into_iter().enumerate() {
+        let next = if i == last {
+            body_end.clone()
+        } else {
+            rule.var(term.span())
         };
+        rule.body(term, list, next.clone())?;
+        list = next;
+    }
+
+    if let Some(terminals) = pushback {
+        let span = terminals.span();
+        let terminals = terminal_list(terminals, body_end)
+            .ok_or(ast::UserError(span, "Pushback must be a list"))?;
+        rule.goals.push(unify(end, terminals, span));
+    }
 
-        let body = ast_clause.alloc_body(heap, goals, &mut var_map, lambdas);
-        let body_length = heap.data.len() - body;
+    Ok(ast::Clause(head, rule.goals, span))
+}
+
+struct Dcg {
+    vars: usize,
+    goals: Vec<ast::Term>,
+}
 
-        group.push(HeapClausePtr {
-            ptr: goals,
-            head_length,
-            goals_length,
-            body_length,
-        });
+impl Dcg {
+    // Names that can't be written in source, and aren't reported as singletons
+    fn var(&mut self, span: ast::Span) -> ast::Term {
+        self.vars += 1;
+        ast::Term::Variable(format!("_#S{}", self.vars - 1), span)
     }
 
-    heap.code_end = heap.data.len();
+    fn body(&mut self, term: ast::Term, s0: ast::Term, s: ast::Term) -> Result<(), ast::UserError> {
+        let span = term.span();
+        let goal = match term {
+            ast::Term::Compound(functor, mut args, _) if functor == "," && args.len() == 2 => {
+                let s1 = self.var(span);
+                let second = args.pop().unwrap();
+                self.body(args.pop().unwrap(), s0, s1.clone())?;
+                return self.body(second, s1, s);
+            }
+            // Variables and disjunctions are run by phrase/3
+            ast::Term::Variable(_, _) => {
+                ast::Term::Compound("phrase".to_string(), vec![term, s0, s], span)
+            }
+            ast::Term::Compound(ref functor, ref args, _) if functor == ";" && args.len() == 2 => {
+                ast::Term::Compound("phrase".to_string(), vec![term, s0, s], span)
+            }
+            ast::Term::Cut(_) => {
+                self.goals.push(term);
+                unify(s0, s, span)
+            }
+            ast::Term::Compound(functor, mut goals, _) if functor == "{}" && goals.len() == 1 => {
+                self.goals.extend(goals.pop().unwrap().conjuncts());
+                unify(s0, s, span)
+            }
+            ast::Term::Atom(ast::Atom::String(ref name), _) if name == "{}" => unify(s0, s, span),
+            ast::Term::Atom(ast::Atom::Text(text), _) => {
+                let codes = text
+                    .chars()
+                    .map(|c| ast::Term::Atom(ast::Atom::Integer(c as i64), span))
+                    .collect();
+                unify(s0, ast::Term::list(codes, Some(s), span), span)
+            }
+            ast::Term::Atom(ast::Atom::String(ref name), _) if name == "[]" => unify(s0, s, span),
+            ast::Term::Compound(ref functor, ref args, _) if functor == "." && args.len() == 2 => {
+                let terminals = terminal_list(term, s)
+                    .ok_or(ast::UserError(span, "Terminals must be a list"))?;
+                unify(s0, terminals, span)
+            }
+            term => {
+                nonterminal(term, s0, s).ok_or(ast::UserError(span, "Invalid grammar rule body"))?
+            }
+        };
 
-    index
+        self.goals.push(goal);
+        Ok(())
+    }
+}
+
+// A callable term with the two lists added as its last arguments
+fn nonterminal(term: ast::Term, s0: ast::Term, s: ast::Term) -> Option<ast::Term> {
+    match term {
+        ast::Term::Atom(ast::Atom::String(name), span) => {
+            Some(ast::Term::Compound(name, vec![s0, s], span))
+        }
+        ast::Term::Compound(functor, mut args, span) => {
+            args.extend([s0, s]);
+            Some(ast::Term::Compound(functor, args, span))
+        }
+        _ => None,
+    }
+}
+
+// A proper list with its end replaced by `tail`
+fn terminal_list(list: ast::Term, tail: ast::Term) -> Option<ast::Term> {
+    match list {
+        ast::Term::Atom(ast::Atom::String(name), _) if name == "[]" => Some(tail),
+        ast::Term::Compound(functor, args, span) if functor == "." && args.len() == 2 => {
+            let [head, rest] = <[ast::Term; 2]>::try_from(args).ok()?;
+            let rest = terminal_list(rest, tail)?;
+            Some(ast::Term::Compound(functor, vec![head, rest], span))
+        }
+        _ => None,
+    }
+}
+
+fn unify(a: ast::Term, b: ast::Term, span: ast::Span) -> ast::Term {
+    ast::Term::Compound("=".to_string(), vec![a, b], span)
 }
 
 pub fn alloc_query(
-    ast_query: ast::Query,
+    ast_query: &ast::Query,
     heap: &mut Heap,
     lambdas: &mut Vec<Lambda>,
 ) -> (Vec<HeapTermPtr>, Vec<(String, HeapTermPtr)>) {
@@ -72,7 +663,7 @@ impl ast::Term {
         lambdas: &mut Vec<Lambda>,
     ) -> (HeapTermPtr, Option<ClauseName>) {
         match self {
-            Self::Atom(atom) => {
+            Self::Atom(atom, _) => {
                 let atom = Atom::new(&mut heap.string_map, atom);
                 let ptr = heap.alloc(HeapTerm::Atom(atom));
                 if let Atom::String(string_id) = &atom {
@@ -81,19 +672,21 @@ impl ast::Term {
                     (ptr, None)
                 }
             }
-            Self::Variable(var) if var == "_" => (heap.alloc_new_var(), None),
-            Self::Variable(var) => {
+            Self::Variable(var, _) if var == "_" => (heap.alloc_new_var(), None),
+            Self::Variable(var, _) => {
                 let var = heap.string_map.alloc(var);
 
                 if let Some((_, unified)) = var_map.iter().find(|(x, _)| *x == var) {
-                    (heap.alloc(HeapTerm::Var(*unified, true, false, 0)), None) // shunted
+                    let ptr = heap.alloc(HeapTerm::Var(*unified));
+                    heap.mark_shunted(ptr);
+                    (ptr, None)
                 } else {
                     let result = heap.alloc_new_var();
                     var_map.push((var, result));
                     (result, None)
                 }
             }
-            Self::Compound(functor, args) => {
+            Self::Compound(functor, args, _) => {
                 let functor = heap.string_map.alloc(functor);
                 let arity = args.len();
                 let result = heap.alloc(HeapTerm::Compound(functor, arity));
@@ -105,12 +698,12 @@ impl ast::Term {
 
                 for (i, arg) in args.iter().enumerate() {
                     let (arg, _) = arg.alloc(heap, var_map, lambdas);
-                    heap.data[args_heap + i] = HeapTerm::Var(arg, false, false, 0);
+                    heap.set(args_heap + i, HeapTerm::Var(arg));
                 }
 
                 (result, Some(ClauseName(functor, args.len())))
             }
-            Self::Lambda(js, args) => {
+            Self::Lambda(js, args, span) => {
                 let lambda_id: LambdaId = lambdas.len();
                 lambdas.push(Lambda {
                     js: js.clone(),
@@ -126,33 +719,14 @@ impl ast::Term {
                 }
 
                 for (i, arg) in args.iter().enumerate() {
-                    let (arg, _) = ast::Term::Variable(arg.clone()).alloc(heap, var_map, lambdas);
-                    heap.data[args_heap + i] = HeapTerm::Var(arg, false, false, 0);
+                    let (arg, _) =
+                        ast::Term::Variable(arg.clone(), *span).alloc(heap, var_map, lambdas);
+                    heap.set(args_heap + i, HeapTerm::Var(arg));
                 }
 
                 (result, None)
             }
-            Self::Cut => (heap.alloc(HeapTerm::Cut(0)), None),
+            Self::Cut(_) => (heap.alloc(HeapTerm::Cut(0)), None),
         }
     }
 }
-
-impl ast::Clause {
-    pub fn alloc_body(
-        &self,
-        heap: &mut Heap,
-        mut goals: HeapTermPtr,
-        var_map: &mut Vec<(StringId, HeapTermPtr)>,
-        lambdas: &mut Vec<Lambda>,
-    ) -> HeapTermPtr {
-        let result = heap.data.len();
-
-        for goal in &self.1 {
-            let (goal, _) = goal.alloc(heap, var_map, lambdas);
-            heap.data[goals] = HeapTerm::Var(goal, false, false, 0);
-            goals += 1;
-        }
-
-        result
-    }
-}
@@ -0,0 +1,69 @@
+use crate::heap::Heap;
+use crate::{HeapTerm, HeapTermPtr};
+
+use std::collections::{HashMap, HashSet};
+
+// Unification and comparison only start remembering the pairs of terms they have
+// visited after this many, so that they terminate on cyclic terms.
+pub const VISITED_THRESHOLD: usize = 1024;
+
+impl Heap {
+    // Finds the structures that are reachable from themselves, i.e. the
+    // targets of back edges in a depth first search from the roots.
+    pub fn cycles(&self, roots: &[HeapTermPtr]) -> HashSet<HeapTermPtr> {
+        let mut cycles = HashSet::new();
+        let mut finished: HashMap<HeapTermPtr, bool> = HashMap::new();
+        let mut stack: Vec<(HeapTermPtr, usize)> = Vec::new();
+
+        for root in roots {
+            let root = self.get_ptr(*root);
+            if self.arity(root) == 0 || finished.contains_key(&root) {
+                continue;
+            }
+
+            finished.insert(root, false);
+            stack.push((root, 1));
+
+            while let Some((term, arg)) = stack.last_mut() {
+                let term = *term;
+
+                if *arg > self.arity(term) {
+                    finished.insert(term, true);
+                    stack.pop();
+                    continue;
+                }
+
+                let child = self.get_ptr(term + *arg);
+                *arg += 1;
+
+                if self.arity(child) == 0 {
+                    continue;
+                }
+
+                match finished.get(&child) {
+                    Some(false) => {
+                        cycles.insert(child);
+                    }
+                    Some(true) => {}
+                    None => {
+                        finished.insert(child, false);
+                        stack.push((child, 1));
+                    }
+                }
+            }
+        }
+
+        cycles
+    }
+
+    pub fn is_cyclic(&self, term: HeapTermPtr) -> bool {
+        !self.cycles(&[term]).is_empty()
+    }
+
+    fn arity(&self, term: HeapTermPtr) -> usize {
+        match self.term(term) {
+            HeapTerm::Compound(_, arity) | HeapTerm::Lambda(_, arity) => arity,
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::ast::{self, Span, Term};
+use crate::program::Database;
+use crate::{ErrorLocation, Solver, Warning};
+
+use std::rc::Rc;
+
+// A goal to run once the whole program is loaded, and the directive it came from
+type Initialization = (Term, Span);
+
+// Directives and initialization goals set things up rather than compute, so
+// one that runs this long is taken to be looping
+const MAX_INFERENCES: usize = 1_000_000;
+
+// Compiles the program clause by clause, running each directive once, in
+// source order, against the clauses before it, and the initialization goals
+// once it's loaded. Goals that fail or raise an error are warned about.
+pub(crate) fn load(program: ast::Program, warnings: &mut Vec<Warning>) -> Database {
+    let ast::Program(clauses, lines, directives, _) = program;
+    let mut database = Database {
+        lines,
+        ..Database::default()
+    };
+    let mut analysis = Vec::new();
+    let mut clauses = clauses.into_iter();
+    let mut loaded = 0;
+    let mut after_load: Vec<Initialization> = Vec::new();
+    let mut main: Vec<Initialization> = Vec::new();
+
+    for directive in directives {
+        let ast::Directive(goals, span, count) = &directive;
+        database.load(
+            clauses.by_ref().take(count - loaded),
+            &directive.dynamic(),
+            &mut analysis,
+        );
+        loaded = *count;
+
+        let mut query = Vec::new();
+
+        for goal in goals {
+            match goal {
+                // Declarations take effect when the program is compiled
+                Term::Compound(name, args, _) if name == "dynamic" && args.len() == 1 => {}
+                Term::Compound(name, args, _) if name == "initialization" => match &args[..] {
+                    [goal] => after_load.push((goal.clone(), *span)),
+                    [goal, Term::Atom(ast::Atom::String(when), _)] => match when.as_str() {
+                        "now" => query.push(goal.clone()),
+                        "after_load" => after_load.push((goal.clone(), *span)),
+                        "main" => main.push((goal.clone(), *span)),
+                        _ => warnings.push(Warning {
+                            location: Some(ErrorLocation::new(&database.lines, *span, false)),
+                            warning: format!("Unsupported initialization `{}`", when),
+                        }),
+                    },
+                    _ => query.push(goal.clone()),
+                },
+                _ => query.push(goal.clone()),
+            }
+        }
+
+        if !query.is_empty() {
+            database = solve(database, query, *span, "directive", warnings);
+        }
+    }
+
+    database.load(clauses, &[], &mut analysis);
+
+    // `main` runs last, as it would end the program once it returns
+    after_load.append(&mut main);
+    for (goal, span) in after_load {
+        database = solve(database, vec![goal], span, "initialization", warnings);
+    }
+
+    database
+}
+
+// Finds the first solution of a goal, like once/1, and keeps the flags it
+// leaves set
+fn solve(
+    database: Database,
+    goals: Vec<Term>,
+    span: Span,
+    kind: &str,
+    warnings: &mut Vec<Warning>,
+) -> Database {
+    // Goals are located in the program, so the query has no lines of its own
+    let query = ast::Query(goals, ast::Lines::default());
+    let database = Rc::new(database);
+    let mut solver = Solver::from_database(database.clone(), query, true);
+    solver.inference_limit = Some(MAX_INFERENCES);
+    let lines = &database.lines;
+
+    // Errors inside clauses keep their location, while those in the goal
+    // itself are located at the directive
+    let locate = |location: Option<ErrorLocation>| match location {
+        Some(location) if !location.query => location,
+        _ => ErrorLocation::new(lines, span, false),
+    };
+
+    let mut found = match solver.step() {
+        Ok(Some(_)) => Vec::new(),
+        Ok(None) => vec![Warning {
+            location: Some(locate(None)),
+            warning: format!("Goal ({}) failed", kind),
+        }],
+        Err(e) => vec![Warning {
+            location: Some(locate(e.location.map(|location| *location))),
+            warning: e.error,
+        }],
+    };
+
+    found.extend(solver.warnings.drain(..).map(|w| Warning {
+        location: Some(locate(w.location)),
+        warning: w.warning,
+    }));
+
+    for warning in found {
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+
+    let flags = solver.flags;
+    drop(solver);
+
+    // The solver is gone, so the database is no longer shared
+    let mut database = Rc::unwrap_or_clone(database);
+    database.flags = flags;
+    database
+}
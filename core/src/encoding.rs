@@ -0,0 +1,74 @@
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Binary images start with a 4 byte magic number and a little-endian version.
+pub fn header(magic: &[u8; 4], version: u32) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    bytes.extend(version.to_le_bytes());
+    bytes
+}
+
+// Returns the bytes following the header, if it matches.
+pub fn check_header<'a>(
+    bytes: &'a [u8],
+    magic: &[u8; 4],
+    version: u32,
+    kind: &str,
+) -> Result<&'a [u8], String> {
+    let rest = match bytes.strip_prefix(magic) {
+        Some(rest) if rest.len() >= 4 => rest,
+        _ => return Err(format!("not a {}", kind)),
+    };
+
+    let found = u32::from_le_bytes(rest[..4].try_into().unwrap());
+    if found != version {
+        return Err(format!(
+            "unsupported version {} (expected {})",
+            found, version
+        ));
+    }
+
+    Ok(&rest[4..])
+}
+
+pub fn write<T: Serialize + ?Sized>(w: &mut Vec<u8>, value: &T) -> bincode::Result<()> {
+    bincode::serialize_into(w, value)
+}
+
+// Lengths can't exceed what is left to read, so a corrupt length is an error
+// rather than an allocation of that size.
+pub fn read<T: DeserializeOwned>(r: &mut &[u8]) -> bincode::Result<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(r.len() as u64)
+        .deserialize_from(r)
+}
+
+// Appends a checksum of the bytes from `start`.
+pub fn seal(bytes: &mut Vec<u8>, start: usize) {
+    let checksum = checksum(&bytes[start..]);
+    bytes.extend(checksum.to_le_bytes());
+}
+
+// Returns the bytes before the checksum, if it matches them.
+pub fn unseal(bytes: &[u8]) -> Result<&[u8], String> {
+    if bytes.len() < 8 {
+        return Err("unexpected end of data".to_string());
+    }
+
+    let (body, expected) = bytes.split_at(bytes.len() - 8);
+    if checksum(body).to_le_bytes() != expected {
+        return Err("checksum mismatch".to_string());
+    }
+
+    Ok(body)
+}
+
+// FNV-1a
+pub fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
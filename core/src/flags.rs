@@ -0,0 +1,101 @@
+use crate::builtins::{self, BuiltinError};
+use crate::{ClauseName, Error, HeapTermPtr, Solver, Warning};
+
+use serde::{Deserialize, Serialize};
+
+// Prolog flags, which a query can change with set_prolog_flag/2
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Flags {
+    pub(crate) unknown: Unknown,
+    pub(crate) double_quotes: DoubleQuotes,
+}
+
+// What happens when a goal calls a predicate that isn't defined
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Unknown {
+    #[default]
+    Error,
+    Warning,
+    Fail,
+}
+
+impl Unknown {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "error" => Some(Unknown::Error),
+            "warning" => Some(Unknown::Warning),
+            "fail" => Some(Unknown::Fail),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Unknown::Error => "error",
+            Unknown::Warning => "warning",
+            Unknown::Fail => "fail",
+        }
+    }
+}
+
+// What text in double quotes reads as
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DoubleQuotes {
+    Codes,
+    Chars,
+    Atom,
+    #[default]
+    String,
+}
+
+impl DoubleQuotes {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "codes" => Some(DoubleQuotes::Codes),
+            "chars" => Some(DoubleQuotes::Chars),
+            "atom" => Some(DoubleQuotes::Atom),
+            "string" => Some(DoubleQuotes::String),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DoubleQuotes::Codes => "codes",
+            DoubleQuotes::Chars => "chars",
+            DoubleQuotes::Atom => "atom",
+            DoubleQuotes::String => "string",
+        }
+    }
+}
+
+impl Solver {
+    // Called instead of failing when a goal has no clauses to try. Predicates
+    // declared dynamic have an empty clause group, so they just fail.
+    pub(crate) fn unknown_procedure(&mut self, goal: HeapTermPtr) -> Result<(), Error> {
+        let goal = self.heap.get_ptr(goal);
+        let error = match self.heap.get_name(goal) {
+            Some(ClauseName(name, arity)) => BuiltinError::UnknownProcedure(name, arity),
+            None => BuiltinError::Type("callable", goal),
+        };
+
+        match self.flags.unknown {
+            Unknown::Error => Err(builtins::error(self, error)),
+            Unknown::Warning => {
+                let Error {
+                    location, error, ..
+                } = builtins::error(self, error);
+                let warning = Warning {
+                    location: location.map(|location| *location),
+                    warning: error,
+                };
+
+                if !self.warnings.contains(&warning) {
+                    self.warnings.push(warning);
+                }
+                Ok(())
+            }
+            Unknown::Fail => Ok(()),
+        }
+    }
+}
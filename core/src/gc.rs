@@ -1,19 +1,92 @@
+use crate::atom::Atom;
 use crate::goal::Goals;
-use crate::heap::Heap;
+use crate::heap::{Barrier, Heap};
 use crate::trail::Trail;
-use crate::{ChoicePoint, HeapTerm, HeapTermPtr, Solver};
+use crate::{ChoicePoint, ClauseName, HeapTerm, HeapTermPtr, Solver, StringId};
+
+use serde::{Deserialize, Serialize};
 
 pub const GC_MARKED: usize = 0;
 pub const GC_SHUNTED: usize = 1 << (std::mem::size_of::<usize>() * 8 - 1);
 pub const GC_UNMARKED: usize = usize::MAX >> 1;
 
+// Atoms are collected when the number of local atoms has grown past this, or past
+// twice the number that survived the previous collection
+const ATOM_GC_THRESHOLD: usize = 1024;
+
+// When the collector runs automatically
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GCConfig {
+    // Minimum number of heap cells
+    pub heap_size_threshold: usize,
+    // Minimum ratio of used to allocated heap memory
+    pub heap_pressure_threshold: f64,
+    // Minimum number of steps between collections
+    pub cooldown: usize,
+    // Marks at most this many cells per step, spreading each collection over several steps
+    pub mark_budget: Option<usize>,
+}
+
+impl Default for GCConfig {
+    fn default() -> Self {
+        Self {
+            heap_size_threshold: 1024,
+            heap_pressure_threshold: 0.9,
+            cooldown: 16,
+            mark_budget: None,
+        }
+    }
+}
+
+// What a single collection did
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GCRecord {
+    // Whether the whole heap was collected rather than just the newest generation
+    pub full: bool,
+    pub cells_before: usize,
+    pub cells_after: usize,
+    pub trail_removed: usize,
+    pub choice_points_scanned: usize,
+    // Number of steps the collection was spread over
+    pub slices: usize,
+    // In milliseconds, in total and of the longest slice
+    pub time: f64,
+    pub max_pause: f64,
+}
+
+pub trait GCSink {
+    fn collected(&mut self, record: &GCRecord);
+}
+
+impl<F: FnMut(&GCRecord)> GCSink for F {
+    fn collected(&mut self, record: &GCRecord) {
+        self(record)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct GarbageCollector {
+    // Only kept between steps while marking incrementally
     map: Vec<usize>,
+    #[serde(skip)]
     trail_map: Vec<usize>,
+    #[serde(skip)]
     map_len: usize,
+    #[serde(skip)]
     trail_map_len: usize,
     scheduler: GCScheduler,
     runs: usize,
+    time: f64,
+    collected: usize,
+    atom_threshold: usize,
+    collected_atoms: usize,
+    collected_trail: usize,
+    last: Option<GCRecord>,
+    forced: bool,
+    max_pause: f64,
+    #[serde(skip)]
+    sink: Option<Box<dyn GCSink>>,
+    marking: Option<Marking>,
 
     // Generational GC
     start_choice_point: usize,
@@ -22,12 +95,29 @@ pub struct GarbageCollector {
     start_goal_ptr: crate::goal::Checkpoint,
 }
 
+// An incremental collection in progress
+#[derive(Serialize, Deserialize)]
+struct Marking {
+    grey: Vec<HeapTermPtr>,
+    // Cells from here on were allocated while marking, and are kept
+    watermark: usize,
+    // Choice points from here on have been scanned
+    next_choice_point: usize,
+    complete: bool,
+    choice_points: usize,
+    slices: usize,
+    time: f64,
+    max_pause: f64,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct GCScheduler {
     absolute_threshold: usize,
     relative_threshold: f64,
     wait_for_resize: Option<usize>,
     cooldown: usize,
     remaining_cooldown: usize,
+    mark_budget: Option<usize>,
 }
 
 pub trait GCRewritable {
@@ -35,20 +125,24 @@ pub trait GCRewritable {
 }
 
 impl GarbageCollector {
-    pub fn new(absolute_threshold: usize, relative_threshold: f64, cooldown: usize) -> Self {
+    pub fn new(config: GCConfig) -> Self {
         Self {
             map: Vec::new(),
             trail_map: Vec::new(),
             map_len: 0,
             trail_map_len: 0,
-            scheduler: GCScheduler {
-                absolute_threshold,
-                relative_threshold,
-                wait_for_resize: None,
-                cooldown,
-                remaining_cooldown: 0,
-            },
+            scheduler: GCScheduler::new(config),
             runs: 0,
+            time: 0.0,
+            collected: 0,
+            atom_threshold: ATOM_GC_THRESHOLD,
+            collected_atoms: 0,
+            collected_trail: 0,
+            last: None,
+            forced: false,
+            max_pause: 0.0,
+            sink: None,
+            marking: None,
             start_choice_point: 0,
             start_heap_ptr: crate::heap::Checkpoint(0),
             start_trail_ptr: crate::trail::Checkpoint(0),
@@ -56,35 +150,103 @@ impl GarbageCollector {
         }
     }
 
+    // Only collects when forced to
     pub fn disabled() -> Self {
-        Self {
-            map: Vec::new(),
-            trail_map: Vec::new(),
-            map_len: 0,
-            trail_map_len: 0,
-            scheduler: GCScheduler {
-                absolute_threshold: usize::MAX,
-                relative_threshold: 0.0,
-                wait_for_resize: None,
-                cooldown: 0,
-                remaining_cooldown: 0,
-            },
-            runs: 0,
-            start_choice_point: 0,
-            start_heap_ptr: crate::heap::Checkpoint(0),
-            start_trail_ptr: crate::trail::Checkpoint(0),
-            start_goal_ptr: crate::goal::Checkpoint(None, 0),
+        Self::new(GCConfig {
+            heap_size_threshold: usize::MAX,
+            heap_pressure_threshold: 0.0,
+            cooldown: 0,
+            mark_budget: None,
+        })
+    }
+
+    pub fn configure(&mut self, config: GCConfig, heap: &mut Heap) {
+        self.cancel(heap);
+        self.scheduler = GCScheduler::new(config);
+    }
+
+    pub fn config(&self) -> GCConfig {
+        GCConfig {
+            heap_size_threshold: self.scheduler.absolute_threshold,
+            heap_pressure_threshold: self.scheduler.relative_threshold,
+            cooldown: self.scheduler.cooldown,
+            mark_budget: self.scheduler.mark_budget,
         }
     }
 
+    pub fn set_sink(&mut self, sink: impl GCSink + 'static) {
+        self.sink = Some(Box::new(sink));
+    }
+
+    // Runs a full collection before the next step
+    pub fn force(&mut self) {
+        self.forced = true;
+    }
+
     pub fn runs(&self) -> usize {
         self.runs
     }
 
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn collected(&self) -> usize {
+        self.collected
+    }
+
+    pub fn collected_atoms(&self) -> usize {
+        self.collected_atoms
+    }
+
+    pub fn collected_trail(&self) -> usize {
+        self.collected_trail
+    }
+
+    pub fn max_pause(&self) -> f64 {
+        self.max_pause
+    }
+
+    pub fn last(&self) -> Option<&GCRecord> {
+        self.last.as_ref()
+    }
+
     pub fn run(solver: &mut Solver) {
-        solver
-            .gc
-            .reset(solver.heap.data.len(), solver.trail.vars.len());
+        let start = crate::stats::now();
+
+        let full = std::mem::take(&mut solver.gc.forced);
+        if full {
+            solver.gc.cancel(&mut solver.heap);
+            solver.gc.start_choice_point = 0;
+        } else if let Some(budget) = solver.gc.scheduler.mark_budget {
+            if solver.gc.marking.is_none() {
+                Self::start_marking(solver);
+            }
+
+            let marking = solver.gc.marking.as_ref().unwrap();
+            if !marking.complete {
+                let complete = Self::mark_incrementally(solver, budget);
+                let marking = solver.gc.marking.as_mut().unwrap();
+                marking.complete = complete;
+                marking.pause(crate::stats::now() - start);
+                return;
+            }
+        }
+
+        let heap_len = solver.heap.data.len();
+        let trail_len = solver.trail.vars.len();
+
+        let marking = if solver.gc.marking.is_some() {
+            // Anything left to mark is what changed since the previous slice
+            Self::mark_incrementally(solver, usize::MAX);
+            let marking = solver.gc.marking.take().unwrap();
+            solver.heap.barrier = None;
+            solver.gc.resume(heap_len, trail_len, marking.watermark);
+            Some(marking)
+        } else {
+            solver.gc.reset(heap_len, trail_len);
+            None
+        };
 
         if solver.gc.start_choice_point > 0 {
             let cp = &solver.choice_points[solver.gc.start_choice_point - 1];
@@ -97,17 +259,16 @@ impl GarbageCollector {
             solver.gc.start_goal_ptr = crate::goal::Checkpoint(None, 0);
         }
 
-        solver.gc.start_heap_ptr =
-            crate::heap::Checkpoint(solver.gc.start_heap_ptr.0.max(solver.heap.code_end));
-
         let roots = solver.gc.get_roots(&solver.var_map, &solver.goals);
         solver.gc.mark_heap(&solver.heap, roots);
-        solver.gc.mark_from_choice_points(
-            &mut solver.heap,
-            &solver.goals,
-            &mut solver.trail,
-            &solver.choice_points,
-        );
+        if marking.is_none() {
+            solver.gc.mark_from_choice_points(
+                &mut solver.heap,
+                &solver.goals,
+                &mut solver.trail,
+                &solver.choice_points,
+            );
+        }
 
         solver.gc.shunt(&solver.heap);
         solver.gc.realign_choice_points(&mut solver.choice_points);
@@ -125,9 +286,42 @@ impl GarbageCollector {
             .gc
             .update_old_pointers(&mut solver.heap, &solver.trail);
 
-        solver.gc.scheduler.post_run(&solver.heap);
-        solver.gc.runs += 1;
-        solver.gc.start_choice_point = solver.choice_points.len();
+        if solver.heap.string_map.local_count() > solver.gc.atom_threshold {
+            Self::collect_atoms(solver);
+        }
+
+        let pause = crate::stats::now() - start;
+        let record = GCRecord {
+            full,
+            cells_before: heap_len,
+            cells_after: solver.heap.data.len(),
+            trail_removed: trail_len - solver.trail.vars.len(),
+            choice_points_scanned: match &marking {
+                Some(marking) => marking.choice_points,
+                None => solver.choice_points.len() - solver.gc.start_choice_point,
+            },
+            slices: marking.as_ref().map(|m| m.slices).unwrap_or(0) + 1,
+            time: marking.as_ref().map(|m| m.time).unwrap_or(0.0) + pause,
+            max_pause: marking
+                .as_ref()
+                .map(|m| m.max_pause)
+                .unwrap_or(0.0)
+                .max(pause),
+        };
+
+        let gc = &mut solver.gc;
+        gc.scheduler.post_run(&solver.heap);
+        gc.runs += 1;
+        gc.collected += record.cells_before - record.cells_after;
+        gc.collected_trail += record.trail_removed;
+        gc.time += record.time;
+        gc.max_pause = gc.max_pause.max(record.max_pause);
+        gc.start_choice_point = solver.choice_points.len();
+        gc.last = Some(record);
+        gc.map.clear();
+        if let Some(sink) = &mut gc.sink {
+            sink.collected(&record);
+        }
 
         solver.choice_point_age = solver
             .choice_points
@@ -136,6 +330,153 @@ impl GarbageCollector {
             .unwrap_or(crate::heap::Checkpoint(0));
     }
 
+    // Only the atoms interned while solving are collected. The program's atoms (which
+    // include the preloaded ones) are shared and can't be freed, and are the only ones
+    // the code refers to. Goals, the var map and the registers all point into the heap,
+    // so the remaining references are from the heap and the debugging tools.
+    pub fn collect_atoms(solver: &mut Solver) {
+        let string_map = &solver.heap.string_map;
+        let offset = string_map.shared.len();
+        let mut marked = vec![false; string_map.local_strings().len()];
+
+        let mut mark = |id: StringId| {
+            if let Some(local) = id.checked_sub(offset) {
+                marked[local] = true;
+            }
+        };
+
+        for cell in solver
+            .heap
+            .data
+            .iter()
+            .filter(|c| !c.is_boxed() && !c.is_raw())
+        {
+            match cell.decode(|| unreachable!()) {
+                HeapTerm::Atom(Atom::String(id) | Atom::Text(id)) | HeapTerm::Compound(id, _) => {
+                    mark(id)
+                }
+                _ => {}
+            }
+        }
+
+        solver.tracer.spy_points().for_each(&mut mark);
+
+        if let Some(profiler) = &solver.profiler {
+            profiler
+                .names()
+                .for_each(|ClauseName(functor, _)| mark(functor));
+        }
+
+        let string_map = &mut solver.heap.string_map;
+        solver.gc.collected_atoms += string_map.collect(&marked);
+        solver.gc.atom_threshold = ATOM_GC_THRESHOLD.max(2 * string_map.local_count());
+    }
+
+    // Incremental marking keeps everything that was reachable when it started. The
+    // roots are taken now, the heap records the bindings that are undone in the
+    // meantime, and cells allocated in the meantime are kept.
+    fn start_marking(solver: &mut Solver) {
+        let heap_len = solver.heap.data.len();
+
+        solver.gc.map.clear();
+        solver.gc.map.resize(heap_len + 1, GC_UNMARKED);
+
+        solver.heap.barrier = Some(Barrier {
+            deleted: Vec::new(),
+            low_water: heap_len,
+        });
+
+        let grey = solver
+            .gc
+            .get_roots(&solver.var_map, &solver.goals)
+            .collect();
+
+        solver.gc.marking = Some(Marking {
+            grey,
+            watermark: heap_len,
+            next_choice_point: solver.choice_points.len(),
+            complete: false,
+            choice_points: 0,
+            slices: 0,
+            time: 0.0,
+            max_pause: 0.0,
+        });
+    }
+
+    // Marks at most `budget` cells, then returns whether marking is complete. Choice
+    // points are scanned from the newest like `mark_from_choice_points`, once
+    // everything reachable from the newer ones has been marked.
+    fn mark_incrementally(solver: &mut Solver, mut budget: usize) -> bool {
+        let gc = &mut solver.gc;
+        let heap = &mut solver.heap;
+        let marking = gc.marking.as_mut().unwrap();
+
+        let barrier = heap.barrier.as_mut().unwrap();
+        marking.watermark = marking.watermark.min(barrier.low_water);
+        marking.grey.append(&mut barrier.deleted);
+
+        loop {
+            if !marking.mark(&mut gc.map, heap, &mut budget) {
+                return false;
+            }
+
+            let next = marking.next_choice_point;
+            if next <= gc.start_choice_point {
+                return true;
+            }
+
+            let cp = &solver.choice_points[next - 1];
+            let top = solver
+                .choice_points
+                .get(next)
+                .map(|cp| cp.trail_checkpoint.0)
+                .unwrap_or(solver.trail.vars.len());
+
+            // Early reset, bypassing the barrier as nothing can reach these bindings
+            for &var in &solver.trail.vars[cp.trail_checkpoint.0..top] {
+                if var < marking.watermark && gc.map[var] == GC_UNMARKED {
+                    heap.data[var].set_ptr(var);
+                }
+            }
+
+            marking
+                .grey
+                .extend(solver.goals.iter_from(cp.goals_checkpoint));
+            marking.next_choice_point -= 1;
+            marking.choice_points += 1;
+        }
+    }
+
+    // Whether marking can continue on this heap, e.g. after restoring a snapshot
+    pub fn is_consistent(&self, heap: &Heap) -> bool {
+        match &self.marking {
+            Some(marking) => {
+                heap.barrier.is_some()
+                    && marking.watermark < self.map.len()
+                    && marking.watermark <= heap.len()
+            }
+            None => heap.barrier.is_none(),
+        }
+    }
+
+    // Abandons incremental marking
+    pub fn cancel(&mut self, heap: &mut Heap) {
+        self.marking = None;
+        heap.barrier = None;
+    }
+
+    // Prepares the maps for the rest of the collection after incremental marking
+    fn resume(&mut self, heap_len: usize, trail_len: usize, watermark: usize) {
+        self.map.truncate(watermark);
+        self.map.resize(heap_len, GC_MARKED);
+        self.map.push(GC_UNMARKED);
+        self.map_len = heap_len;
+
+        self.trail_map.clear();
+        self.trail_map.resize(trail_len + 1, GC_MARKED);
+        self.trail_map_len = trail_len;
+    }
+
     fn reset(&mut self, heap_len: usize, trail_len: usize) {
         self.map.clear();
         self.map.resize(heap_len + 1, GC_UNMARKED);
@@ -195,17 +536,26 @@ impl GarbageCollector {
 
             self.map[ptr] = GC_MARKED;
 
-            match &heap.data[ptr] {
-                HeapTerm::Var(next, _, true, attr) => {
-                    self.mark(heap, *attr);
-                    ptr = *next;
+            let cell = heap.data[ptr];
+            if cell.is_var() {
+                if let Some(attribute) = heap.attribute(ptr) {
+                    self.mark(heap, attribute);
                 }
-                HeapTerm::Var(next, _, false, _) => ptr = *next,
-                HeapTerm::Compound(_, arity) if *arity > 0 => {
+                ptr = cell.ptr();
+                continue;
+            }
+
+            if cell.is_boxed() {
+                self.map[ptr + 1] = GC_MARKED;
+                return;
+            }
+
+            match heap.term(ptr) {
+                HeapTerm::Compound(_, arity) if arity > 0 => {
                     for i in 1..=(arity - 1) {
                         self.mark(heap, ptr + i);
                     }
-                    ptr += *arity; // tail recursion to avoid stack overflow on lists
+                    ptr += arity; // tail recursion to avoid stack overflow on lists
                 }
                 _ => return,
             }
@@ -213,18 +563,19 @@ impl GarbageCollector {
     }
 
     fn shunt(&mut self, heap: &Heap) {
-        for (i, term) in heap
+        for (i, cell) in heap
             .data
             .iter()
             .enumerate()
             .skip(self.start_heap_ptr.0)
             .rev()
         {
-            if let HeapTerm::Var(ptr, true, _, _) = term {
-                match self.map[*ptr] {
-                    GC_MARKED => self.map[i] = GC_SHUNTED | *ptr, // End of shunted chain
-                    GC_UNMARKED => (),                            // Dead variable
-                    next => self.map[i] = next,                   // Earlier in shunted chain
+            if cell.is_shunted() {
+                let ptr = cell.ptr();
+                match self.map[ptr] {
+                    GC_MARKED => self.map[i] = GC_SHUNTED | ptr, // End of shunted chain
+                    GC_UNMARKED => (),                           // Dead variable
+                    next => self.map[i] = next,                  // Earlier in shunted chain
                 }
             }
         }
@@ -297,15 +648,20 @@ impl GarbageCollector {
         }
 
         // Rewrite internal pointers
-        for term in heap.data.iter_mut().skip(self.start_heap_ptr.0) {
-            if let HeapTerm::Var(ptr, _, attributed, attribute) = term {
-                *ptr = self.map[*ptr];
-
-                if *attributed {
-                    *attribute = self.map[*attribute];
-                }
+        for cell in heap.data.iter_mut().skip(self.start_heap_ptr.0) {
+            if cell.is_var() {
+                cell.set_ptr(self.map[cell.ptr()]);
             }
         }
+
+        // Attributes are kept apart from the cells, keyed by variable
+        if !heap.attributes.is_empty() {
+            heap.attributes = std::mem::take(&mut heap.attributes)
+                .into_iter()
+                .map(|(var, goal)| (self.map[var], self.map[goal]))
+                .filter(|(var, goal)| *var < GC_UNMARKED && *goal < GC_UNMARKED)
+                .collect();
+        }
     }
 
     fn collect_trail(&mut self, trail: &mut Trail) {
@@ -346,21 +702,77 @@ impl GarbageCollector {
     // pointers from the old generation to the new generation.
     fn update_old_pointers(&mut self, heap: &mut Heap, trail: &Trail) {
         for var in trail.vars.iter().skip(self.start_trail_ptr.0) {
-            if let HeapTerm::Var(ptr, _, _, _) = &mut heap.data[*var] {
+            let cell = &mut heap.data[*var];
+            if cell.is_var() {
+                let ptr = cell.ptr();
                 if *var < self.start_heap_ptr.0
-                    && *ptr >= self.start_heap_ptr.0
-                    && self.map[*ptr] < GC_UNMARKED
+                    && ptr >= self.start_heap_ptr.0
+                    && self.map[ptr] < GC_UNMARKED
                 {
-                    *ptr = self.map[*ptr];
+                    cell.set_ptr(self.map[ptr]);
                 }
             }
         }
     }
 
-    pub fn pre_run(&mut self, heap: &Heap, choice_points: usize) -> bool {
+    pub fn pre_run(&mut self, heap: &Heap, goals: &Goals, choice_points: usize) -> bool {
         self.start_choice_point = self.start_choice_point.min(choice_points);
 
-        self.scheduler.pre_run(heap)
+        if let Some(marking) = &mut self.marking {
+            // Backtracked to a choice point that hasn't been scanned, so its goals are roots again
+            if choice_points < marking.next_choice_point {
+                marking.grey.extend(goals.iter());
+                marking.next_choice_point = choice_points;
+                marking.complete = false;
+            }
+            return true;
+        }
+
+        self.scheduler.pre_run(heap) || self.forced
+    }
+}
+
+impl Marking {
+    // Marks from the grey cells until they run out or the budget does
+    fn mark(&mut self, map: &mut [usize], heap: &Heap, budget: &mut usize) -> bool {
+        while let Some(mut ptr) = self.grey.pop() {
+            while ptr < self.watermark && map[ptr] != GC_MARKED {
+                map[ptr] = GC_MARKED;
+                *budget = budget.saturating_sub(1);
+
+                let cell = heap.data[ptr];
+                if cell.is_var() {
+                    self.grey.extend(heap.attribute(ptr));
+                    ptr = cell.ptr();
+                    continue;
+                }
+
+                if cell.is_boxed() {
+                    map[ptr + 1] = GC_MARKED;
+                    break;
+                }
+
+                match heap.term(ptr) {
+                    HeapTerm::Compound(_, arity) if arity > 0 => {
+                        self.grey.extend(ptr + 1..ptr + arity);
+                        ptr += arity;
+                    }
+                    _ => break,
+                }
+            }
+
+            if *budget == 0 {
+                return self.grey.is_empty();
+            }
+        }
+
+        true
+    }
+
+    fn pause(&mut self, time: f64) {
+        self.slices += 1;
+        self.time += time;
+        self.max_pause = self.max_pause.max(time);
     }
 }
 
@@ -373,6 +785,17 @@ impl GCRewritable for [(String, usize)] {
 }
 
 impl GCScheduler {
+    fn new(config: GCConfig) -> Self {
+        Self {
+            absolute_threshold: config.heap_size_threshold,
+            relative_threshold: config.heap_pressure_threshold,
+            wait_for_resize: None,
+            cooldown: config.cooldown,
+            remaining_cooldown: 0,
+            mark_budget: config.mark_budget,
+        }
+    }
+
     fn pre_run(&mut self, heap: &Heap) -> bool {
         let result = heap.data.len() > self.absolute_threshold
             && self
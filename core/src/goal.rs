@@ -1,19 +1,41 @@
-use crate::gc::GCRewritable;
+use crate::gc::{GCRewritable, GC_UNMARKED};
 use crate::HeapTermPtr;
 
-#[derive(Clone, Copy, Debug)]
-pub struct Goal(pub(crate) HeapTermPtr, pub(crate) Option<GoalPtr>);
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Goal(
+    pub(crate) HeapTermPtr,
+    pub(crate) Option<GoalPtr>,
+    pub(crate) Option<Origin>,
+    pub(crate) Option<Caller>,
+);
+
+// The goal that called the clause a goal was written in, and where that goal
+// was written, kept for backtraces after the goal itself is gone. It isn't a
+// garbage collection root, so the collector may drop it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Caller(pub(crate) HeapTermPtr, pub(crate) Option<Origin>);
 
 pub type GoalPtr = usize;
 
-#[derive(Default, Debug)]
+// Where a goal was written: its position in the query, or the index of its
+// source in the program's database
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Origin {
+    Query(u32),
+    Program(u32),
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Goals {
     pub(crate) current: Option<GoalPtr>,
     pub(crate) goals: Vec<Goal>,
-    pub(crate) pending: Option<HeapTermPtr>,
+    pub(crate) pending: Vec<HeapTermPtr>,
+    pub(crate) retain: bool,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Checkpoint(pub(crate) Option<GoalPtr>, pub(crate) usize);
 
 impl Goals {
@@ -21,8 +43,8 @@ impl Goals {
         let mut goals = Self::default();
         let mut goal = None;
 
-        for term in query.iter().rev() {
-            goal = Some(goals.alloc(*term, goal));
+        for (i, term) in query.iter().enumerate().rev() {
+            goal = Some(goals.alloc(*term, goal, Some(Origin::Query(i as u32)), None));
         }
 
         goals.current = goal;
@@ -30,9 +52,15 @@ impl Goals {
         goals
     }
 
-    pub fn alloc(&mut self, term: HeapTermPtr, prev: Option<GoalPtr>) -> GoalPtr {
+    pub fn alloc(
+        &mut self,
+        term: HeapTermPtr,
+        prev: Option<GoalPtr>,
+        origin: Option<Origin>,
+        caller: Option<Caller>,
+    ) -> GoalPtr {
         let result = self.goals.len();
-        self.goals.push(Goal(term, prev));
+        self.goals.push(Goal(term, prev, origin, caller));
         result
     }
 
@@ -40,26 +68,41 @@ impl Goals {
         self.current.map(|ptr| self.goals[ptr].term())
     }
 
+    pub fn origin(&self) -> Option<Origin> {
+        self.current.and_then(|ptr| self.goals[ptr].origin())
+    }
+
+    pub fn caller(&self) -> Option<Caller> {
+        self.current.and_then(|ptr| self.goals[ptr].caller())
+    }
+
     pub fn pop(&mut self, determinate: bool) {
+        let mut origin = None;
+        let mut caller = None;
+
         if let Some(ptr) = self.current.take() {
             self.current = self.goals[ptr].prev_ptr();
-            if determinate && ptr == self.goals.len() - 1 {
+            origin = self.goals[ptr].origin();
+            caller = self.goals[ptr].caller();
+            if determinate && !self.retain && ptr == self.goals.len() - 1 {
                 self.goals.pop();
             }
         }
 
-        if let Some(pending) = self.pending.take() {
-            self.push(pending);
+        // Goals called by a goal are reported where it was written, and run in
+        // the order they were added
+        for pending in std::mem::take(&mut self.pending).into_iter().rev() {
+            self.push(pending, origin, caller);
         }
     }
 
-    pub fn push(&mut self, term: HeapTermPtr) {
+    pub fn push(&mut self, term: HeapTermPtr, origin: Option<Origin>, caller: Option<Caller>) {
         let prev = self.current.take();
-        self.current = Some(self.alloc(term, prev));
+        self.current = Some(self.alloc(term, prev, origin, caller));
     }
 
     pub fn push_pending(&mut self, term: HeapTermPtr) {
-        self.pending = Some(term);
+        self.pending.push(term);
     }
 
     pub fn is_complete(&self) -> bool {
@@ -71,18 +114,19 @@ impl Goals {
     }
 
     pub fn undo(&mut self, checkpoint: Checkpoint) {
+        self.pending.clear();
         self.current = checkpoint.0;
         self.goals.truncate(checkpoint.1);
     }
 
-    pub fn iter(&self) -> GoalIterator {
+    pub fn iter(&self) -> GoalIterator<'_> {
         GoalIterator {
             goals: self,
             current: self.current,
         }
     }
 
-    pub fn iter_from(&self, checkpoint: Checkpoint) -> GoalIterator {
+    pub fn iter_from(&self, checkpoint: Checkpoint) -> GoalIterator<'_> {
         GoalIterator {
             goals: self,
             current: checkpoint.0,
@@ -98,15 +142,32 @@ impl Goal {
     pub fn prev_ptr(&self) -> Option<GoalPtr> {
         self.1
     }
+
+    pub fn origin(&self) -> Option<Origin> {
+        self.2
+    }
+
+    pub fn caller(&self) -> Option<Caller> {
+        self.3
+    }
 }
 
 impl GCRewritable for Goals {
     fn rewrite(&mut self, from: usize, map: &[usize], _: &[usize]) {
-        for Goal(term, _) in self.goals.iter_mut().skip(from) {
+        for Goal(term, _, _, caller) in self.goals.iter_mut().skip(from) {
             // TODO: make determinacy analysis fully accurate to avoid garbage on the goal stack from cuts
             if *term < map.len() {
                 *term = map[*term];
             }
+            // Callers don't keep their terms alive, and are forgotten with them
+            if let Some(Caller(term, _)) = caller {
+                if *term < map.len() {
+                    *term = map[*term];
+                }
+                if *term >= GC_UNMARKED {
+                    *caller = None;
+                }
+            }
         }
     }
 }
@@ -121,7 +182,7 @@ impl Iterator for GoalIterator<'_> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(ptr) = self.current {
-            let Goal(heap_term_ptr, next) = &self.goals.goals[ptr];
+            let Goal(heap_term_ptr, next, _, _) = &self.goals.goals[ptr];
             self.current = *next;
             Some(*heap_term_ptr)
         } else {
@@ -1,15 +1,32 @@
 use crate::atom::Atom;
-use crate::stringmap::StringMap;
-use crate::{ChoicePointIdx, ClauseName, HeapClausePtr, HeapTerm, HeapTermPtr, StringId};
+use crate::cell::Cell;
+use crate::stringmap::{StringMap, Strings};
+use crate::{ClauseName, HeapTerm, HeapTermPtr, StringId};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::rc::Rc;
 
 #[derive(Default)]
 pub struct Heap {
-    pub(crate) data: Vec<HeapTerm>,
+    pub(crate) data: Vec<Cell>,
+    // Goals delayed on variables, for the few cells that have the attributed flag
+    pub(crate) attributes: HashMap<HeapTermPtr, HeapTermPtr>,
     pub(crate) string_map: StringMap,
-    pub(crate) code_end: HeapTermPtr,
+    pub(crate) barrier: Option<Barrier>,
+}
+
+// What the garbage collector needs to know about changes made while it marks incrementally
+#[derive(Default, Serialize, Deserialize)]
+pub struct Barrier {
+    // Bindings that were undone, which may have been the only references to their values
+    pub(crate) deleted: Vec<HeapTermPtr>,
+    // Cells from here on have been allocated since marking started
+    pub(crate) low_water: usize,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Checkpoint(pub usize);
 
 impl Heap {
@@ -17,128 +34,190 @@ impl Heap {
         Self::default()
     }
 
+    pub fn with_strings(strings: Rc<Strings>) -> Self {
+        Self {
+            data: Vec::new(),
+            attributes: HashMap::new(),
+            string_map: StringMap::new(strings),
+            barrier: None,
+        }
+    }
+
     pub fn checkpoint(&self) -> Checkpoint {
         Checkpoint(self.data.len())
     }
 
     pub fn undo(&mut self, checkpoint: Checkpoint) {
         self.data.truncate(checkpoint.0);
-    }
-
-    pub fn copy_clause_head(&mut self, clause: &HeapClausePtr) -> HeapTermPtr {
-        let result = self.data.len();
-        let offset = self.data.len() - clause.head();
-
-        self.data.extend_from_within(clause.head()..clause.body());
 
-        for term in &mut self.data[result..] {
-            if let HeapTerm::Var(x, _, _, _) = term {
-                *x += offset;
-            }
+        if let Some(barrier) = &mut self.barrier {
+            barrier.low_water = barrier.low_water.min(checkpoint.0);
         }
 
-        result
-    }
-
-    pub fn copy_clause_body(&mut self, clause: &HeapClausePtr, choice_point_idx: ChoicePointIdx) {
-        let result = self.data.len();
-        let offset = self.data.len() - clause.body();
-
-        self.data.extend_from_within(clause.body()..clause.end());
-
-        for term in &mut self.data[result..] {
-            match term {
-                HeapTerm::Var(x, _, _, _) => *x += offset,
-                HeapTerm::Cut(choice_point) => *choice_point += choice_point_idx,
-                _ => {}
-            }
+        if !self.attributes.is_empty() {
+            self.attributes.retain(|var, _| *var < checkpoint.0);
         }
     }
 
-    pub fn clause_goals<'a>(
-        &'a mut self,
-        clause: &HeapClausePtr,
-    ) -> impl DoubleEndedIterator<Item = HeapTermPtr> + 'a {
-        let offset = self.data.len() - clause.end();
-        self.data[clause.goals()..clause.head()]
-            .iter()
-            .map(move |term| {
-                if let HeapTerm::Var(x, _, _, _) = term {
-                    x + offset
-                } else {
-                    unreachable!()
-                }
-            })
+    pub fn len(&self) -> usize {
+        self.data.len()
     }
 
+    #[inline(always)]
     pub fn alloc(&mut self, term: HeapTerm) -> HeapTermPtr {
         let result = self.data.len();
-        self.data.push(term);
+        let (cell, boxed) = Cell::encode(term);
+        self.data.push(cell);
+        if let Some(raw) = boxed {
+            self.data.push(raw);
+        }
         result
     }
 
+    #[inline(always)]
     pub fn alloc_new_var(&mut self) -> HeapTermPtr {
         let result = self.data.len();
-        self.data.push(HeapTerm::Var(result, false, false, 0));
+        self.data.push(Cell::var(result));
+        result
+    }
+
+    // Marks the end of a goal's body so the exit port can be recorded.
+    // The clause number is filled in once a clause has been selected.
+    pub fn alloc_exit(&mut self, goal: HeapTermPtr, depth: usize) -> HeapTermPtr {
+        let result = self.alloc(HeapTerm::Compound(crate::stringmap::str::EXIT, 3));
+        self.alloc(HeapTerm::Var(goal));
+        self.alloc_new_var();
+        self.alloc_new_var();
+        self.set(result + 2, HeapTerm::Atom(Atom::Integer(depth as i64)));
+        self.set(result + 3, HeapTerm::Atom(Atom::Integer(0)));
         result
     }
 
-    pub fn get(&self, var: HeapTermPtr) -> &HeapTerm {
-        &self.data[self.get_ptr(var)]
+    // Overwrites a cell, such as an argument of a structure. Boxed terms
+    // don't fit, so they are allocated separately and referred to instead.
+    #[inline(always)]
+    pub fn set(&mut self, ptr: HeapTermPtr, term: HeapTerm) {
+        match Cell::encode(term) {
+            (cell, None) => self.data[ptr] = cell,
+            (_, Some(_)) => self.data[ptr] = Cell::var(self.alloc(term)),
+        }
     }
 
+    // The term in a cell, without following bindings
+    #[inline(always)]
+    pub fn term(&self, ptr: HeapTermPtr) -> HeapTerm {
+        self.data[ptr].decode(|| self.data[ptr + 1])
+    }
+
+    #[inline(always)]
+    pub fn get(&self, var: HeapTermPtr) -> HeapTerm {
+        self.term(self.get_ptr(var))
+    }
+
+    #[inline(always)]
     pub fn get_ptr(&self, mut var: HeapTermPtr) -> HeapTermPtr {
         debug_assert!(var < self.data.len());
 
         // Follow the chain of variable bindings until we reach the root.
         loop {
-            match &self.data[var] {
-                HeapTerm::Var(x, _, _, _) if *x != var => var = *x,
-                _ => return var,
+            let cell = self.data[var];
+            if !cell.is_var() || cell.ptr() == var {
+                return var;
             }
+            var = cell.ptr();
         }
     }
 
+    #[inline(always)]
     pub fn unify(&mut self, a: HeapTermPtr, b: HeapTermPtr) {
-        match &mut self.data[a] {
-            HeapTerm::Var(x, _, _, _) => *x = b,
-            _ => unreachable!(),
-        }
+        debug_assert!(self.data[a].is_var());
+        self.data[a].set_ptr(b);
     }
 
+    #[inline(always)]
     pub fn unbind(&mut self, term: HeapTermPtr) {
-        match &mut self.data[term] {
-            HeapTerm::Var(x, _, _, _) => *x = term,
-            _ => unreachable!(),
+        debug_assert!(self.data[term].is_var());
+        if let Some(barrier) = &mut self.barrier {
+            barrier.deleted.push(self.data[term].ptr());
         }
+        self.data[term].set_ptr(term);
     }
 
+    #[inline]
     pub fn mark_shunted(&mut self, term: HeapTermPtr) {
-        match &mut self.data[term] {
-            HeapTerm::Var(_, shunted, _, _) => *shunted = true,
-            _ => unreachable!(),
+        self.data[term].set_shunted();
+    }
+
+    #[inline]
+    pub fn is_shunted(&self, term: HeapTermPtr) -> bool {
+        self.data[term].is_shunted()
+    }
+
+    #[inline(always)]
+    pub fn attribute(&self, var: HeapTermPtr) -> Option<HeapTermPtr> {
+        if self.data[var].is_attributed() {
+            self.attributes.get(&var).copied()
+        } else {
+            None
+        }
+    }
+
+    pub fn set_attribute(&mut self, var: HeapTermPtr, goal: HeapTermPtr) {
+        self.data[var].set_attributed(true);
+        if let (Some(old), Some(barrier)) = (self.attributes.insert(var, goal), &mut self.barrier) {
+            barrier.deleted.push(old);
         }
     }
 
+    #[inline(always)]
+    pub fn take_attribute(&mut self, var: HeapTermPtr) -> Option<HeapTermPtr> {
+        let result = self.attribute(var);
+        if let Some(goal) = result {
+            self.data[var].set_attributed(false);
+            self.attributes.remove(&var);
+            if let Some(barrier) = &mut self.barrier {
+                barrier.deleted.push(goal);
+            }
+        }
+        result
+    }
+
+    // Checks that the cells can be decoded, i.e. that every tag is known and that
+    // every boxed value is complete.
+    pub fn has_valid_cells(&self) -> bool {
+        let mut cells = self.data.iter();
+
+        while let Some(cell) = cells.next() {
+            if !cell.is_valid() || cell.is_boxed() && !cells.next().is_some_and(|raw| raw.is_raw())
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
     pub fn get_atom(&self, atom: StringId) -> &str {
         self.string_map.get(atom).unwrap()
     }
 
-    pub fn get_name(&self, term: HeapTermPtr) -> ClauseName {
+    // The predicate a goal calls, or `None` if the term isn't callable
+    #[inline(always)]
+    pub fn get_name(&self, term: HeapTermPtr) -> Option<ClauseName> {
         match self.get(term) {
-            HeapTerm::Atom(Atom::String(name)) => ClauseName(*name, 0),
-            HeapTerm::Compound(functor, arity) => ClauseName(*functor, *arity),
-            HeapTerm::Cut(_) => ClauseName(crate::stringmap::str::EXCL, 0),
-            HeapTerm::Lambda(code, arity) => ClauseName(*code, *arity),
-            _ => unreachable!(),
+            HeapTerm::Atom(Atom::String(name)) => Some(ClauseName(name, 0)),
+            HeapTerm::Compound(functor, arity) => Some(ClauseName(functor, arity)),
+            HeapTerm::Cut(_) => Some(ClauseName(crate::stringmap::str::EXCL, 0)),
+            HeapTerm::Lambda(code, arity) => Some(ClauseName(code, arity)),
+            _ => None,
         }
     }
 
     pub fn size(&self) -> usize {
-        self.data.len() * std::mem::size_of::<HeapTerm>()
+        self.data.len() * std::mem::size_of::<Cell>()
     }
 
     pub fn capacity(&self) -> usize {
-        self.data.capacity() * std::mem::size_of::<HeapTerm>()
+        self.data.capacity() * std::mem::size_of::<Cell>()
     }
 }
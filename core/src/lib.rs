@@ -1,29 +1,62 @@
 pub mod ast;
 mod atom;
+mod backtrace;
 mod builtins;
+mod cell;
 mod compile;
+mod cyclic;
+mod directive;
+mod encoding;
+mod flags;
 mod gc;
 mod goal;
 mod heap;
+mod lint;
+mod profile;
+mod program;
 mod serialize;
+mod snapshot;
+mod stats;
 mod stringmap;
+mod trace;
 mod trail;
+mod verify;
+mod vm;
 mod wasm;
 
 pub use wasm::*;
 
-lalrpop_util::lalrpop_mod!(grammar);
+lalrpop_util::lalrpop_mod!(
+    #[allow(clippy::ptr_arg)]
+    grammar
+);
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::rc::Rc;
 
 #[cfg(test)]
 mod tests;
 
 use atom::Atom;
+use flags::Flags;
 use gc::{GCRewritable, GarbageCollector};
-use goal::Goals;
+use goal::{Caller, Goals, Origin};
 use heap::Heap;
+use profile::Profiler;
+use program::Database;
+use stats::Statistics;
+use stringmap::str;
+use trace::Tracer;
 use trail::Trail;
+use verify::Verifier;
+use vm::CompiledClause;
+
+pub use gc::{GCConfig, GCRecord, GCSink};
+pub use profile::{Port, Profile, ProfileEntry};
+pub use program::Program;
+pub use trace::{DebugCommand, DebugStep, TraceEvent, TraceSink};
 
 type HeapTermPtr = usize;
 type ChoicePointIdx = usize;
@@ -31,42 +64,33 @@ type ChoicePointIdx = usize;
 type StringId = usize;
 type LambdaId = usize;
 
-static GC_HEAP_SIZE_THRESHOLD: usize = 1024;
-static GC_HEAP_PRESSURE_THRESHOLD: f64 = 0.9;
-static GC_COOLDOWN: usize = 16;
-
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum HeapTerm {
     Atom(Atom),
-    Var(HeapTermPtr, bool, bool, HeapTermPtr), // ptr, shunted, attributed, goal
+    Var(HeapTermPtr),
     Compound(StringId, usize),
     Cut(ChoicePointIdx),
     Lambda(LambdaId, usize),
 }
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
 pub struct ClauseName(pub StringId, pub usize); // functor, arity
 
-pub struct HeapClausePtr {
-    ptr: HeapTermPtr,
-    goals_length: usize,
-    head_length: usize,
-    body_length: usize,
-}
-
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct Lambda {
     js: String,
     arg_names: Vec<String>,
 }
 
-pub type Index = Vec<(ClauseName, Vec<HeapClausePtr>)>;
+pub type Index = Vec<(ClauseName, Vec<CompiledClause>)>;
 
 pub type Solution = Vec<(String, String)>;
 
 pub struct Solver {
-    index: Index,
+    program: Rc<Database>,
+    registers: Vec<HeapTermPtr>,
+    unify_stack: Vec<(HeapTermPtr, HeapTermPtr)>,
     goals: Goals,
     group: Option<usize>,
     clause: usize,
@@ -75,7 +99,21 @@ pub struct Solver {
     heap: Heap,
     gc: GarbageCollector,
     var_map: Vec<(String, HeapTermPtr)>,
+    query_spans: Vec<ast::Span>,
+    query_lines: ast::Lines,
+    warnings: Vec<Warning>,
+    flags: Flags,
+    // Text for read_term/2, which has read up to the offset
+    input: String,
+    input_offset: usize,
     trail: Trail,
+    stats: Statistics,
+    // Stops the query with a resource error once it has made this many inferences
+    inference_limit: Option<usize>,
+    profiler: Option<Profiler>,
+    tracer: Tracer,
+    verifier: Option<Verifier>,
+    depth: usize,
 
     #[allow(dead_code)]
     lambdas: Vec<Lambda>,
@@ -84,27 +122,148 @@ pub struct Solver {
     interrupt: std::sync::Arc<std::sync::atomic::AtomicBool>,
 }
 
-#[derive(Copy, Clone)]
+// Where solving stopped: at a solution, an error, a port the debugger paused
+// at, or once there are no more solutions
+enum Stop {
+    Solution(Solution),
+    Error(Error),
+    Paused,
+    Done,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
 struct ChoicePoint {
     group: Option<usize>,
     clause: usize,
+    depth: usize,
     trail_checkpoint: trail::Checkpoint,
     heap_checkpoint: heap::Checkpoint,
     goals_checkpoint: goal::Checkpoint,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct Error {
-    pub location: Option<ErrorLocation>,
+    pub kind: ErrorKind,
+    // Boxed, like the error term, so that results carrying errors stay small
+    pub location: Option<Box<ErrorLocation>>,
     pub error: String,
+    // For runtime errors, the goals that were running, innermost first
+    pub backtrace: Vec<Frame>,
+    // The term that caused the error, and the type or domain it should have been in
+    pub culprit: Option<String>,
+    pub expected: Option<String>,
+    // The error as ISO Prolog would throw it, `error(Formal, Context)`
+    pub term: Option<Box<ErrorTerm>>,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+// The classes of ISO Prolog errors, and the ones specific to running in a browser
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Syntax,
+    Instantiation,
+    Type,
+    Domain,
+    Existence,
+    Evaluation,
+    Representation,
+    Resource,
+    #[serde(rename = "javascript")]
+    JavaScript,
+    // Invalid compiled programs and snapshots, and other failures of the system itself
+    System,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ErrorTerm {
+    pub text: String,
+    pub term: Term,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 pub struct ErrorLocation {
     pub query: bool,
     pub offset: usize,
     pub line: usize,
     pub column: usize,
+    pub end: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    // The offsets in UTF-16 code units, as JavaScript strings count them
+    pub utf16_offset: usize,
+    pub utf16_end: usize,
+    // For runtime errors in the program, the predicate indicator and the
+    // number of the clause containing the goal
+    pub predicate: Option<String>,
+    pub clause: Option<usize>,
+}
+
+// A goal that was running when an error happened, and where it was called
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Frame {
+    // Printed to a bounded depth
+    pub goal: String,
+    pub location: Option<ErrorLocation>,
+}
+
+// A likely mistake in a program or query that doesn't stop it from running
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    pub location: Option<ErrorLocation>,
+    pub warning: String,
+}
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind, error: String) -> Self {
+        Error {
+            kind,
+            location: None,
+            error,
+            backtrace: Vec::new(),
+            culprit: None,
+            expected: None,
+            term: None,
+        }
+    }
+
+    // Fills in the ISO error term. Formal terms like `type_error(Type, Culprit)`
+    // also give the culprit and what was expected instead.
+    pub(crate) fn with_term(mut self, formal: Term, context: Term) -> Self {
+        if let Term::Compound(_, args) = &formal {
+            if let [expected, culprit] = args.as_slice() {
+                self.expected = Some(expected.to_string());
+                self.culprit = Some(culprit.to_string());
+            }
+        }
+
+        let term = Term::Compound("error".into(), vec![formal, context]);
+        self.term = Some(Box::new(ErrorTerm {
+            text: term.to_string(),
+            term,
+        }));
+        self
+    }
+}
+
+impl ErrorLocation {
+    pub(crate) fn new(lines: &ast::Lines, span: ast::Span, query: bool) -> Self {
+        let (line, column) = lines.locate(span.0);
+        let (end_line, end_column) = lines.locate(span.1);
+
+        ErrorLocation {
+            query,
+            offset: span.0,
+            line,
+            column,
+            end: span.1,
+            end_line,
+            end_column,
+            utf16_offset: lines.utf16(span.0),
+            utf16_end: lines.utf16(span.1),
+            predicate: None,
+            clause: None,
+        }
+    }
 }
 
 impl Solver {
@@ -122,27 +281,38 @@ impl Solver {
         program: impl AsRef<str>,
         query: impl AsRef<str>,
     ) -> Result<(ast::Program, ast::Query), Error> {
-        let program = grammar::ProgramParser::new()
-            .parse(program.as_ref())
-            .map_err(|e| ast::parse_error(program.as_ref(), false, e))?;
-
-        let query = grammar::QueryParser::new()
-            .parse(query.as_ref())
-            .map_err(|e| ast::parse_error(query.as_ref(), true, e))?;
-
+        let program = Program::parse(program)?;
+        let query = Program::parse_query_with(query, program.3)?;
         Ok((program, query))
     }
 
     pub fn from_ast(program: ast::Program, query: ast::Query, gc: bool) -> Self {
-        let mut heap = Heap::new();
-        let mut lambdas = Vec::new();
+        Program::from_ast(program).query_ast(query, gc)
+    }
 
-        let index = compile::compile(program, &mut heap, &mut lambdas);
-        let (query, var_map) = compile::alloc_query(query, &mut heap, &mut lambdas);
-        let goals = Goals::new(&query);
+    fn from_database(program: Rc<Database>, query: ast::Query, gc: bool) -> Self {
+        let mut heap = Heap::with_strings(program.strings.clone());
+        let mut lambdas = program.lambdas.clone();
+
+        let defined: Vec<_> = program
+            .index
+            .iter()
+            .map(|(ClauseName(functor, arity), _)| {
+                (program.strings.strings()[*functor].as_str(), *arity)
+            })
+            .collect();
+        let warnings = lint::lint_query(&query, &defined);
+
+        let (heap_query, var_map) = compile::alloc_query(&query, &mut heap, &mut lambdas);
+        let goals = Goals::new(&heap_query);
+        let query_spans = query.0.iter().map(ast::Term::span).collect();
+        let ast::Query(_, query_lines) = query;
+        let flags = program.flags;
 
         let mut solver = Solver {
-            index,
+            program,
+            registers: Vec::new(),
+            unify_stack: Vec::new(),
             goals,
             group: None,
             clause: 0,
@@ -150,16 +320,24 @@ impl Solver {
             choice_point_age: heap::Checkpoint(0),
             heap,
             gc: if gc {
-                GarbageCollector::new(
-                    GC_HEAP_SIZE_THRESHOLD,
-                    GC_HEAP_PRESSURE_THRESHOLD,
-                    GC_COOLDOWN,
-                )
+                GarbageCollector::new(GCConfig::default())
             } else {
                 GarbageCollector::disabled()
             },
             var_map,
+            query_spans,
+            query_lines,
+            warnings,
+            flags,
+            input: String::new(),
+            input_offset: 0,
             trail: Trail::new(),
+            stats: Statistics::new(),
+            inference_limit: None,
+            profiler: None,
+            tracer: Tracer::default(),
+            verifier: None,
+            depth: 0,
             lambdas,
 
             #[cfg(test)]
@@ -171,8 +349,61 @@ impl Solver {
         solver
     }
 
+    // Enables automatic garbage collection with the given settings
+    pub fn configure_gc(&mut self, config: GCConfig) {
+        self.gc.configure(config, &mut self.heap);
+    }
+
+    pub fn gc_config(&self) -> GCConfig {
+        self.gc.config()
+    }
+
+    // Called after every collection
+    pub fn set_gc_sink(&mut self, sink: impl GCSink + 'static) {
+        self.gc.set_sink(sink);
+    }
+
+    pub fn last_gc(&self) -> Option<&GCRecord> {
+        self.gc.last()
+    }
+
+    // Appends text to the input that read_term/2 reads from
+    pub fn add_input(&mut self, text: impl AsRef<str>) {
+        self.input.drain(..self.input_offset);
+        self.input_offset = 0;
+        self.input.push_str(text.as_ref());
+    }
+
+    // Warnings about the program, followed by those about the query
+    pub fn warnings(&self) -> Vec<&Warning> {
+        self.program.warnings.iter().chain(&self.warnings).collect()
+    }
+
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    pub fn profile(&self) -> Option<Profile> {
+        self.profiler
+            .as_ref()
+            .map(|profiler| profiler.report(&self.heap))
+    }
+
     fn step(&mut self) -> Result<Option<Solution>, Error> {
-        self.step_inner().transpose()
+        self.next().transpose()
+    }
+
+    fn resume(&mut self) -> Stop {
+        self.stats.resume();
+        let result = self.step_inner();
+        self.stats.suspend();
+
+        match result {
+            Some(Ok(solution)) => Stop::Solution(solution),
+            Some(Err(e)) => Stop::Error(e),
+            None if self.tracer.is_paused() => Stop::Paused,
+            None => Stop::Done,
+        }
     }
 
     fn step_inner(&mut self) -> Option<Result<Solution, Error>> {
@@ -180,16 +411,56 @@ impl Solver {
             #[cfg(test)]
             self.check_interrupted()?;
 
-            if self.gc.pre_run(&self.heap, self.choice_points.len()) {
-                GarbageCollector::run(self);
+            if let Some(limit) = self.inference_limit {
+                if self.stats.inferences >= limit {
+                    let e = builtins::BuiltinError::InferenceLimit(limit);
+                    return Some(Err(self.raise(builtins::error(self, e))));
+                }
+            }
+
+            let runs = self.gc.runs();
+            if self
+                .gc
+                .pre_run(&self.heap, &self.goals, self.choice_points.len())
+            {
+                if self.tracer.is_debugging() {
+                    self.gc.cancel(&mut self.heap);
+                } else {
+                    GarbageCollector::run(self);
+                }
+            }
+
+            if let Err(e) = self.verify_step(self.gc.runs() != runs) {
+                return Some(Err(e));
+            }
+
+            if self.tracer.is_paused() {
+                return None;
             }
 
             let goal: HeapTermPtr = self.goals.current()?;
 
+            if self.clause == 0 {
+                // Markers that record the exit port aren't calls of the program
+                if !matches!(self.heap.get(goal), HeapTerm::Compound(str::EXIT, 3)) {
+                    self.stats.inferences += 1;
+                }
+                self.port(Port::Call, goal, None);
+            } else {
+                self.port(Port::Redo, goal, Some(self.clause));
+            }
+
+            if self.tracer.is_paused() {
+                return None;
+            }
+
+            let choice_points = self.choice_points.len();
             match builtins::eval(self, goal) {
                 Some(Ok(true)) => {
-                    // Built-in predicate succeeded
-                    self.goals.pop(true);
+                    // Built-in predicate succeeded, and is kept for a retry if it
+                    // left a choice point
+                    self.port(Port::Exit, goal, None);
+                    self.goals.pop(self.choice_points.len() <= choice_points);
                     self.find_clause_group();
                     if self.goals.is_complete() {
                         let solution = self.serialize_solution();
@@ -200,40 +471,90 @@ impl Solver {
                 }
                 Some(Ok(false)) => {
                     // Built-in predicate failed
+                    self.port(Port::Fail, goal, None);
                     self.pop_choice_point()?;
                     continue;
                 }
-                Some(Err(e)) => return Some(Err(builtins::error(self, e))), // Built-in predicate had an error
+                Some(Err(e)) => {
+                    // Built-in predicate had an error
+                    self.port(Port::Exception, goal, None);
+                    return Some(Err(self.raise(builtins::error(self, e))));
+                }
                 None => {} // This goal is not a built-in predicate
             };
 
+            if self.group.is_none() {
+                if let Err(e) = self.unknown_procedure(goal) {
+                    self.port(Port::Exception, goal, None);
+                    return Some(Err(self.raise(e)));
+                }
+            }
+
             if let Some(group) = self.group {
-                while self.clause < self.index[group].1.len() {
-                    let determinate = self.clause + 1 == self.index[group].1.len();
-                    let choice_point = self.enter();
+                let exit_marker = self
+                    .ports_enabled()
+                    .then(|| self.heap.alloc_exit(goal, self.depth));
+                let origin = self.goals.origin();
+                let caller = self.goals.caller();
+
+                let key = self.first_arg_key(goal);
+                let program = self.program.clone();
+                let clauses = &program.index[group].1;
+                let candidates = program.switches[group].clauses(key);
+                let mut i = candidates.partition_point(|clause| *clause < self.clause);
+
+                while let Some(&candidate) = candidates.get(i) {
+                    self.clause = candidate;
+                    let clause = clauses[candidate];
+                    i += 1;
+
+                    // If no later clause can match, don't push a choice point
+                    let next = candidates.get(i).copied().unwrap_or(clauses.len());
+                    let determinate = next == clauses.len();
+
+                    // Clauses that commit once their guards succeed don't need a choice point
+                    let commit =
+                        !determinate && exit_marker.is_none() && self.can_commit(goal, key, clause);
+
+                    let mut choice_point = self.enter();
+                    choice_point.clause = next;
                     let choice_point_idx = self.choice_points.len();
 
                     if !determinate {
                         self.choice_point_age = choice_point.heap_checkpoint;
                     }
 
-                    let head = self
-                        .heap
-                        .copy_clause_head(&self.index[group].1[self.clause]);
-
-                    if self.unify(goal, head) {
-                        // If this was the only choice, don't push a choice point
-                        if !determinate {
+                    if self.unify_head(goal, clause) {
+                        if !determinate && !commit {
                             self.push_choice_point(choice_point);
                         }
 
-                        let clause = &self.index[group].1[self.clause];
-
                         self.goals.pop(determinate);
-                        self.heap.copy_clause_body(clause, choice_point_idx);
 
-                        for goal in self.heap.clause_goals(clause).rev() {
-                            self.goals.push(goal);
+                        match exit_marker {
+                            Some(marker) if clause.goals > 0 => {
+                                self.heap.set(
+                                    marker + 3,
+                                    HeapTerm::Atom(Atom::Integer(self.clause as i64)),
+                                );
+                                self.goals.push(marker, origin, caller);
+                                self.depth += 1;
+                            }
+                            Some(_) => self.port(Port::Exit, goal, Some(self.clause)),
+                            None => {}
+                        }
+
+                        self.build_body(clause, choice_point_idx, Caller(goal, origin));
+
+                        if commit {
+                            match self.run_guards(clause.guards) {
+                                Ok(true) => self.reset_choice_point_age(),
+                                Ok(false) => {
+                                    self.undo(choice_point);
+                                    continue;
+                                }
+                                Err(e) => return Some(Err(self.raise(e))),
+                            }
                         }
 
                         self.find_clause_group();
@@ -251,6 +572,7 @@ impl Solver {
                 }
             }
 
+            self.port(Port::Fail, goal, None);
             self.pop_choice_point()?;
         }
     }
@@ -259,50 +581,98 @@ impl Solver {
         let a_root = self.heap.get_ptr(a_ptr);
         let b_root = self.heap.get_ptr(b_ptr);
 
-        match (self.heap.get(a_root), self.heap.get(b_root)) {
-            (HeapTerm::Atom(a), HeapTerm::Atom(b)) => a == b,
+        match self.unify_roots(a_root, b_root) {
+            Some(0) => true,
+            Some(_) => self.unify_structures(a_root, b_root),
+            None => false,
+        }
+    }
 
-            // Unify variables downwards (i.e. newer variables point to older ones)
-            (HeapTerm::Var(a, _, _, _), HeapTerm::Var(b, _, _, _)) if *a < b_root => {
-                self.unify_var(*b, a_root)
+    // Unifies two dereferenced terms, returning the arity of the structures
+    // whose arguments still need to be unified.
+    #[inline]
+    fn unify_roots(&mut self, a_root: HeapTermPtr, b_root: HeapTermPtr) -> Option<usize> {
+        let (a, b) = (self.heap.data[a_root], self.heap.data[b_root]);
+
+        // Unify variables downwards (i.e. newer variables point to older ones)
+        if b.is_var() && (!a.is_var() || a_root < b_root) {
+            self.unify_var(b_root, a_root);
+            return Some(0);
+        } else if a.is_var() {
+            self.unify_var(a_root, b_root);
+            return Some(0);
+        }
+
+        match (self.heap.term(a_root), self.heap.term(b_root)) {
+            (HeapTerm::Atom(a), HeapTerm::Atom(b)) if a == b => Some(0),
+            (HeapTerm::Compound(f, a_arity), HeapTerm::Compound(g, b_arity))
+                if f == g && a_arity == b_arity =>
+            {
+                Some(a_arity)
             }
-            (HeapTerm::Var(a, _, _, _), _) => self.unify_var(*a, b_root),
-            (_, HeapTerm::Var(b, _, _, _)) => self.unify_var(*b, a_root),
+            _ => None,
+        }
+    }
 
-            (HeapTerm::Compound(f, a_arity), HeapTerm::Compound(g, b_arity)) => {
-                if f != g || a_arity != b_arity {
-                    return false;
-                }
+    fn unify_structures(&mut self, a_root: HeapTermPtr, b_root: HeapTermPtr) -> bool {
+        let checkpoint = self.trail.checkpoint();
+
+        let mut stack = std::mem::take(&mut self.unify_stack);
+        stack.push((a_root, b_root));
 
-                let checkpoint = self.trail.checkpoint();
+        // Pairs of structures that are assumed to unify, so that cyclic terms terminate
+        let mut visited: Option<HashSet<_>> = None;
+        let mut structures = 0;
 
-                for (a_arg, b_arg) in (1..=*a_arity).map(|i| (a_root + i, b_root + i)) {
-                    if !self.unify(a_arg, b_arg) {
-                        self.trail.undo(checkpoint, &mut self.heap);
-                        return false;
+        let result = loop {
+            let Some((a_ptr, b_ptr)) = stack.pop() else {
+                break true;
+            };
+
+            let a_root = self.heap.get_ptr(a_ptr);
+            let b_root = self.heap.get_ptr(b_ptr);
+
+            match self.unify_roots(a_root, b_root) {
+                Some(0) => {}
+                Some(arity) => {
+                    structures += 1;
+                    if a_root == b_root
+                        || structures > cyclic::VISITED_THRESHOLD
+                            && !visited
+                                .get_or_insert_with(HashSet::new)
+                                .insert((a_root, b_root))
+                    {
+                        continue;
                     }
-                }
 
-                true
+                    for i in (1..=arity).rev() {
+                        stack.push((a_root + i, b_root + i));
+                    }
+                }
+                None => break false,
             }
-            _ => false,
+        };
+
+        if !result {
+            self.trail.undo(checkpoint, &mut self.heap);
         }
+
+        stack.clear();
+        self.unify_stack = stack;
+
+        result
     }
 
     #[inline]
     fn unify_var(&mut self, a: HeapTermPtr, b: HeapTermPtr) -> bool {
-        if a < self.choice_point_age.0 {
+        if a < self.choice_point_age.0 || self.tracer.is_debugging() {
             self.trail.push(a);
         } else {
             self.heap.mark_shunted(a);
         }
 
-        if let HeapTerm::Var(_, _, attributed, attribute) = &mut self.heap.data[a] {
-            if *attributed {
-                self.goals.push_pending(*attribute);
-                *attributed = false;
-                *attribute = 0;
-            }
+        if let Some(attribute) = self.heap.take_attribute(a) {
+            self.goals.push_pending(attribute);
         }
 
         self.heap.unify(a, b);
@@ -314,6 +684,7 @@ impl Solver {
         ChoicePoint {
             group: self.group,
             clause: self.clause + 1,
+            depth: self.depth,
             trail_checkpoint: self.trail.checkpoint(),
             heap_checkpoint: self.heap.checkpoint(),
             goals_checkpoint: self.goals.checkpoint(),
@@ -324,6 +695,7 @@ impl Solver {
     fn undo(&mut self, choice_point: ChoicePoint) {
         self.group = choice_point.group;
         self.clause = choice_point.clause;
+        self.depth = choice_point.depth;
         self.trail
             .undo(choice_point.trail_checkpoint, &mut self.heap);
         self.heap.undo(choice_point.heap_checkpoint);
@@ -338,32 +710,113 @@ impl Solver {
     #[inline]
     fn pop_choice_point(&mut self) -> Option<()> {
         self.choice_points.pop().map(|choice_point| {
-            self.choice_point_age = self
-                .choice_points
-                .last()
-                .map(|cp| cp.heap_checkpoint)
-                .unwrap_or(heap::Checkpoint(0));
+            self.reset_choice_point_age();
             self.undo(choice_point)
         })
     }
 
+    #[inline]
+    fn reset_choice_point_age(&mut self) {
+        self.choice_point_age = self
+            .choice_points
+            .last()
+            .map(|cp| cp.heap_checkpoint)
+            .unwrap_or(heap::Checkpoint(0));
+    }
+
+    // Runs the guards at the top of the goal stack, which are all built-in predicates.
+    fn run_guards(&mut self, guards: usize) -> Result<bool, Error> {
+        for _ in 0..guards {
+            let goal = self.goals.current().unwrap();
+            self.stats.inferences += 1;
+
+            match builtins::eval(self, goal) {
+                Some(Ok(true)) => self.goals.pop(true),
+                Some(Ok(false)) => return Ok(false),
+                Some(Err(e)) => return Err(builtins::error(self, e)),
+                None => unreachable!("Guards are built-in predicates"),
+            }
+        }
+
+        Ok(true)
+    }
+
     #[inline]
     fn find_clause_group(&mut self) {
         if let Some(goal) = self.goals.current() {
             let name = self.heap.get_name(goal);
             self.group = self
+                .program
                 .index
                 .iter()
-                .position(|(clause_name, _)| clause_name == &name);
+                .position(|(clause_name, _)| Some(*clause_name) == name);
             self.clause = 0;
         }
     }
 
+    #[inline]
+    fn ports_enabled(&self) -> bool {
+        self.profiler.is_some() || self.tracer.is_active()
+    }
+
+    #[inline]
+    fn port(&mut self, port: Port, goal: HeapTermPtr, clause: Option<usize>) {
+        if self.ports_enabled() {
+            self.record_port(port, goal, clause);
+        }
+    }
+
+    fn record_port(&mut self, port: Port, goal: HeapTermPtr, clause: Option<usize>) {
+        let name = match self.heap.get(goal) {
+            HeapTerm::Compound(crate::stringmap::str::EXIT, 3) => return,
+            HeapTerm::Lambda(_, _) => return,
+            _ => match self.heap.get_name(goal) {
+                Some(name) => name,
+                None => return,
+            },
+        };
+
+        if !self.tracer.resume(port) {
+            return;
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(name, port);
+        }
+
+        if self.tracer.is_active() {
+            self.trace(port, goal, name, clause);
+        }
+    }
+
     #[inline]
     fn cut(&mut self, choice_point_idx: ChoicePointIdx) {
         self.choice_points.truncate(choice_point_idx);
     }
 
+    // Where the current goal was written, to report a runtime error
+    pub(crate) fn goal_location(&self) -> Option<ErrorLocation> {
+        self.origin_location(self.goals.origin()?)
+    }
+
+    pub(crate) fn origin_location(&self, origin: Origin) -> Option<ErrorLocation> {
+        match origin {
+            Origin::Query(goal) => {
+                let span = *self.query_spans.get(goal as usize)?;
+                Some(ErrorLocation::new(&self.query_lines, span, true))
+            }
+            Origin::Program(source) => {
+                let source = self.program.sources.get(source as usize)?;
+                let (ClauseName(functor, arity), _) = &self.program.index[source.predicate];
+
+                let mut location = ErrorLocation::new(&self.program.lines, source.span, false);
+                location.predicate = Some(format!("{}/{}", self.heap.get_atom(*functor), arity));
+                location.clause = Some(source.clause + 1);
+                Some(location)
+            }
+        }
+    }
+
     #[inline]
     pub(crate) fn serialize_solution(&mut self) -> Solution {
         self.heap.serialize(&self.var_map)
@@ -374,7 +827,16 @@ impl Iterator for Solver {
     type Item = Result<Solution, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.step_inner()
+        match self.resume() {
+            Stop::Solution(solution) => Some(Ok(solution)),
+            Stop::Error(e) => Some(Err(e)),
+            // Only the debugger can continue, so this isn't the end of the solutions
+            Stop::Paused => Some(Err(Error::new(
+                ErrorKind::System,
+                "Paused in the debugger, continue with `debug_step`".into(),
+            ))),
+            Stop::Done => None,
+        }
     }
 }
 
@@ -386,25 +848,3 @@ impl GCRewritable for [ChoicePoint] {
         }
     }
 }
-
-impl HeapClausePtr {
-    #[inline]
-    fn goals(&self) -> HeapTermPtr {
-        self.ptr
-    }
-
-    #[inline]
-    fn head(&self) -> HeapTermPtr {
-        self.ptr + self.goals_length
-    }
-
-    #[inline]
-    fn body(&self) -> HeapTermPtr {
-        self.ptr + self.goals_length + self.head_length
-    }
-
-    #[inline]
-    fn end(&self) -> HeapTermPtr {
-        self.ptr + self.goals_length + self.head_length + self.body_length
-    }
-}
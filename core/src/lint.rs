@@ -0,0 +1,176 @@
+use crate::ast::{self, Span, Term};
+use crate::builtins;
+use crate::stringmap::Strings;
+use crate::{ErrorLocation, Warning};
+
+// Predicates as written in the source, by name and arity
+type Predicate<'a> = (&'a str, usize);
+
+// Finds likely mistakes in a program that still compiles
+pub fn lint(program: &ast::Program) -> Vec<Warning> {
+    let lines = &program.1;
+    let strings = Strings::default();
+    let builtins = builtin_predicates(&strings);
+
+    let heads: Vec<Option<Predicate>> = program
+        .0
+        .iter()
+        .map(|clause| predicate(&clause.0))
+        .collect();
+
+    let mut defined: Vec<Predicate> = program.dynamic();
+    for head in heads.iter().flatten() {
+        if !defined.contains(head) {
+            defined.push(*head);
+        }
+    }
+
+    let mut warnings = Vec::new();
+
+    let mut clauses: Vec<(Predicate, usize)> = Vec::new();
+    let mut discontiguous: Vec<Predicate> = Vec::new();
+    let mut last = None;
+
+    for (clause, head) in program.0.iter().zip(&heads) {
+        let Some(head) = *head else {
+            continue;
+        };
+
+        let number = match clauses.iter_mut().find(|(name, _)| *name == head) {
+            Some((_, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                clauses.push((head, 1));
+                1
+            }
+        };
+
+        let mut warn = |span: Span, warning: String| {
+            let mut location = ErrorLocation::new(lines, span, false);
+            location.predicate = Some(format!("{}/{}", head.0, head.1));
+            location.clause = Some(number);
+            warnings.push(Warning {
+                location: Some(location),
+                warning,
+            });
+        };
+
+        if number == 1 && builtins.contains(&head) {
+            warn(
+                clause.2,
+                format!(
+                    "`{}/{}` is a built-in predicate, so its clauses are never called",
+                    head.0, head.1
+                ),
+            );
+        }
+
+        if number > 1 && last != Some(head) && !discontiguous.contains(&head) {
+            discontiguous.push(head);
+            warn(
+                clause.2,
+                format!("Clauses of `{}/{}` are not together", head.0, head.1),
+            );
+        }
+        last = Some(head);
+
+        for (var, span) in singletons(std::iter::once(&clause.0).chain(&clause.1)) {
+            warn(span, format!("Singleton variable `{}`", var));
+        }
+
+        for goal in &clause.1 {
+            if let Some(warning) = unknown_predicate(goal, &defined, &builtins) {
+                warn(goal.span(), warning);
+            }
+        }
+    }
+
+    warnings
+}
+
+// Finds goals of a query that call predicates the program doesn't define
+pub fn lint_query(query: &ast::Query, defined: &[Predicate]) -> Vec<Warning> {
+    let strings = Strings::default();
+    let builtins = builtin_predicates(&strings);
+
+    query
+        .0
+        .iter()
+        .filter_map(|goal| {
+            unknown_predicate(goal, defined, &builtins).map(|warning| Warning {
+                location: Some(ErrorLocation::new(&query.1, goal.span(), true)),
+                warning,
+            })
+        })
+        .collect()
+}
+
+// Built-in predicates are identified by their preloaded string ids
+fn builtin_predicates(strings: &Strings) -> Vec<Predicate<'_>> {
+    builtins::PREDICATES
+        .iter()
+        .map(|(name, arity)| (strings.strings()[*name].as_str(), *arity))
+        .collect()
+}
+
+fn predicate(term: &Term) -> Option<Predicate<'_>> {
+    match term {
+        Term::Atom(ast::Atom::String(name), _) => Some((name, 0)),
+        Term::Compound(name, args, _) => Some((name, args.len())),
+        _ => None,
+    }
+}
+
+// Predicates with the same name but another arity are likely what was meant
+fn unknown_predicate(goal: &Term, defined: &[Predicate], builtins: &[Predicate]) -> Option<String> {
+    let (name, arity) = predicate(goal)?;
+    let mut known = defined.iter().chain(builtins);
+
+    if known.clone().any(|known| *known == (name, arity)) {
+        return None;
+    }
+
+    Some(match known.find(|known| known.0 == name) {
+        Some((_, other)) => format!(
+            "Unknown procedure `{}/{}`, did you mean `{}/{}`?",
+            name, arity, name, other
+        ),
+        None => format!("Unknown procedure `{}/{}`", name, arity),
+    })
+}
+
+// Named variables that occur only once in some terms and where, in order of
+// appearance, as in a clause or a term read by read_term/2
+pub(crate) fn singletons<'a>(terms: impl IntoIterator<Item = &'a Term>) -> Vec<(&'a str, Span)> {
+    fn count<'a>(term: &'a Term, vars: &mut Vec<(&'a str, Span, usize)>) {
+        let mut add = |var: &'a str, span: Span, n: usize| {
+            if var.starts_with('_') {
+                return;
+            }
+            match vars.iter_mut().find(|(name, _, _)| *name == var) {
+                Some((_, _, count)) => *count += n,
+                None => vars.push((var, span, n)),
+            }
+        };
+
+        match term {
+            Term::Variable(var, span) => add(var, *span, 1),
+            // Lambda arguments are used by the JavaScript code
+            Term::Lambda(_, args, span) => args.iter().for_each(|arg| add(arg, *span, 2)),
+            Term::Compound(_, args, _) => args.iter().for_each(|arg| count(arg, vars)),
+            Term::Atom(_, _) | Term::Cut(_) => {}
+        }
+    }
+
+    let mut vars = Vec::new();
+    for term in terms {
+        count(term, &mut vars);
+    }
+
+    vars.into_iter()
+        .filter(|(_, _, count)| *count == 1)
+        .map(|(var, span, _)| (var, span))
+        .collect()
+}
@@ -1,28 +1,85 @@
-use webpl::Solver;
-
-static PROGRAM: &str = r#"
-take([H|T], H, T).
-take([H|T], R, [H|S]) :- take(T, R, S).
-perm([], []).
-perm(L, [H|R]) :- take(L, H, T), perm(T,R).
-generate_list(1, [1]).
-generate_list(N, [N|T]) :- N > 1, M is N - 1, generate_list(M, T).
-abs(0, 0).
-abs(N, N) :- N > 0.
-abs(N, M) :- N < 0, M is N * -1.
-n_queens(N, Qs) :- generate_list(N, Qs1), perm(Qs1, Qs), safe_queens(Qs).
-safe_queens([]).
-safe_queens([Q|Qs]) :- safe_queens(Qs, Q, 1), safe_queens(Qs).
-safe_queens([], Y, X).
-safe_queens([Q|Qs], Q0, D0) :- Q0 =\= Q, Diff is Q0 - Q, abs(Diff, AbsDiff), AbsDiff =\= D0, D1 is D0 + 1, safe_queens(Qs, Q0, D1).
-"#;
-
-static QUERY: &str = r#"n_queens(8, Qs), statistics(memory, Used)."#;
-
-fn main() {
-    let mut solver = Solver::new_with_gc(PROGRAM, QUERY).unwrap();
-
-    for solution in &mut solver {
-        println!("{:?}", solution.unwrap());
+use webpl::Program;
+
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage:
+  webpl compile <program.pl> [-o <program.plc>]
+  webpl dump <program.pl | program.plc>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>()[..] {
+        ["compile", input] => compile(Path::new(input), &Path::new(input).with_extension("plc")),
+        ["compile", input, "-o", output] => compile(Path::new(input), Path::new(output)),
+        ["dump", input] => dump(Path::new(input)),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn compile(input: &Path, output: &Path) -> Result<(), String> {
+    let program = load(input)?;
+
+    std::fs::write(output, program.to_bytes())
+        .map_err(|e| format!("Couldn't write {}: {}", output.display(), e))
+}
+
+fn dump(input: &Path) -> Result<(), String> {
+    print!("{}", load(input)?.database().dump());
+    Ok(())
+}
+
+fn load(input: &Path) -> Result<Program, String> {
+    let bytes =
+        std::fs::read(input).map_err(|e| format!("Couldn't read {}: {}", input.display(), e))?;
+
+    if input.extension().is_some_and(|ext| ext == "plc") {
+        return Program::from_bytes(&bytes)
+            .map_err(|e| format!("{}: {}", input.display(), e.error));
+    }
+
+    let src = String::from_utf8(bytes).map_err(|e| format!("{}: {}", input.display(), e))?;
+
+    let program = Program::parse_all(src).map_err(|errors| {
+        let errors: Vec<String> = errors
+            .into_iter()
+            .map(|e| match e.location {
+                Some(location) => format!(
+                    "{}:{}:{}: {}",
+                    input.display(),
+                    location.line,
+                    location.column,
+                    e.error
+                ),
+                None => format!("{}: {}", input.display(), e.error),
+            })
+            .collect();
+
+        errors.join("\n")
+    })?;
+
+    let program = Program::from_ast(program);
+    for warning in program.warnings() {
+        match &warning.location {
+            Some(location) => eprintln!(
+                "{}:{}:{}: warning: {}",
+                input.display(),
+                location.line,
+                location.column,
+                warning.warning
+            ),
+            None => eprintln!("{}: warning: {}", input.display(), warning.warning),
+        }
     }
+
+    Ok(program)
 }
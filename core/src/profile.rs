@@ -0,0 +1,100 @@
+use crate::heap::Heap;
+use crate::stats::now;
+use crate::ClauseName;
+
+use serde::Serialize;
+
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Port {
+    Call,
+    Redo,
+    Exit,
+    Fail,
+    Exception,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    counters: HashMap<ClauseName, Counters>,
+    current: Option<ClauseName>,
+    last: f64,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Counters {
+    calls: usize,
+    redos: usize,
+    exits: usize,
+    fails: usize,
+    time: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProfileEntry {
+    pub predicate: String,
+    pub calls: usize,
+    pub redos: usize,
+    pub exits: usize,
+    pub fails: usize,
+    pub time: f64,
+}
+
+pub type Profile = Vec<ProfileEntry>;
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            last: now(),
+            ..Default::default()
+        }
+    }
+
+    pub fn record(&mut self, name: ClauseName, port: Port) {
+        // Time is attributed to the predicate of the most recent port event
+        let time = now();
+        if let Some(current) = self.current {
+            self.counters.entry(current).or_default().time += time - self.last;
+        }
+        self.current = Some(name);
+        self.last = time;
+
+        let counters = self.counters.entry(name).or_default();
+        match port {
+            Port::Call => counters.calls += 1,
+            Port::Redo => counters.redos += 1,
+            Port::Exit => counters.exits += 1,
+            Port::Fail => counters.fails += 1,
+            Port::Exception => {}
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = ClauseName> + '_ {
+        self.counters.keys().copied()
+    }
+
+    pub fn report(&self, heap: &Heap) -> Profile {
+        let mut profile: Profile = self
+            .counters
+            .iter()
+            .map(|(ClauseName(functor, arity), counters)| ProfileEntry {
+                predicate: format!("{}/{}", heap.get_atom(*functor), arity),
+                calls: counters.calls,
+                redos: counters.redos,
+                exits: counters.exits,
+                fails: counters.fails,
+                time: counters.time,
+            })
+            .collect();
+
+        profile.sort_by(|a, b| {
+            b.time
+                .total_cmp(&a.time)
+                .then(a.predicate.cmp(&b.predicate))
+        });
+
+        profile
+    }
+}
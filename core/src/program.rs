@@ -0,0 +1,448 @@
+use crate::encoding::{check_header, header, read, seal, unseal, write};
+use crate::flags::Flags;
+use crate::stringmap::{StringMap, Strings};
+use crate::vm::{Commit, GoalSource, Instruction, Key, Register, Switch};
+use crate::{
+    ast, builtins, compile, directive, grammar, lint, Atom, ClauseName, Error, ErrorKind, Index,
+    Lambda, Solver, Warning,
+};
+
+use std::cell::Cell;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::sync::LazyLock;
+
+// Building a parser compiles the regular expressions of its tokenizer, which
+// is slow with Unicode character classes, so each is only built once
+static PROGRAM_PARSER: LazyLock<grammar::ProgramParser> =
+    LazyLock::new(grammar::ProgramParser::new);
+static QUERY_PARSER: LazyLock<grammar::QueryParser> = LazyLock::new(grammar::QueryParser::new);
+
+const MAGIC: &[u8; 4] = b"WPLP";
+const VERSION: u32 = 7;
+
+// The compiled clauses of a program, which don't change while solving a query
+#[derive(Clone, Default)]
+pub struct Database {
+    pub(crate) index: Index,
+    // Derived from the index when the program is compiled or read
+    pub(crate) switches: Vec<Switch>,
+    pub(crate) code: Vec<Instruction>,
+    pub(crate) strings: Rc<Strings>,
+    pub(crate) lambdas: Vec<Lambda>,
+    pub(crate) sources: Vec<GoalSource>,
+    pub(crate) lines: ast::Lines,
+    // The flags set by the program's directives, which queries start with
+    pub(crate) flags: Flags,
+    // Found when compiling from source, and not part of an image
+    pub(crate) warnings: Vec<Warning>,
+}
+
+// A compiled program, which can be used to solve any number of queries
+#[derive(Clone)]
+pub struct Program(Rc<Database>);
+
+impl Program {
+    pub fn new(program: impl AsRef<str>) -> Result<Self, Error> {
+        Ok(Self::from_ast(Self::parse(program)?))
+    }
+
+    pub fn parse(program: impl AsRef<str>) -> Result<ast::Program, Error> {
+        Self::parse_all(program).map_err(|mut errors| errors.remove(0))
+    }
+
+    // Parses a program, reporting every syntax error rather than only the first
+    pub fn parse_all(program: impl AsRef<str>) -> Result<ast::Program, Vec<Error>> {
+        let input = program.as_ref();
+        let mut recovered = Vec::new();
+        let flags = Cell::new(Flags::default());
+        let result = PROGRAM_PARSER.parse(&mut recovered, &flags, input);
+
+        let mut errors: Vec<Error> = recovered
+            .into_iter()
+            .map(|e| ast::parse_error(input, false, e.error))
+            .collect();
+
+        match result {
+            Ok(mut ast) if errors.is_empty() => {
+                ast.1 = ast::Lines::new(input);
+                Ok(ast)
+            }
+            Ok(_) => Err(errors),
+            Err(e) => {
+                errors.push(ast::parse_error(input, false, e));
+                Err(errors)
+            }
+        }
+    }
+
+    // Runs the program's directives while loading it, then its initialization goals
+    pub fn from_ast(program: ast::Program) -> Self {
+        let mut warnings = lint::lint(&program);
+        let mut database = directive::load(program, &mut warnings);
+        database.warnings = warnings;
+
+        Program(Rc::new(database))
+    }
+
+    pub fn query(&self, query: impl AsRef<str>) -> Result<Solver, Error> {
+        Ok(self.query_ast(Self::parse_query_with(query, self.0.flags)?, false))
+    }
+
+    pub fn query_with_gc(&self, query: impl AsRef<str>) -> Result<Solver, Error> {
+        Ok(self.query_ast(Self::parse_query_with(query, self.0.flags)?, true))
+    }
+
+    pub fn parse_query(query: impl AsRef<str>) -> Result<ast::Query, Error> {
+        Self::parse_query_with(query, Flags::default())
+    }
+
+    // Parses a query the way a program with these flags reads it
+    pub fn parse_query_with(query: impl AsRef<str>, flags: Flags) -> Result<ast::Query, Error> {
+        let mut ast = QUERY_PARSER
+            .parse(&mut Vec::new(), &Cell::new(flags), query.as_ref())
+            .map_err(|e| ast::parse_error(query.as_ref(), true, e))?;
+
+        ast.1 = ast::Lines::new(query.as_ref());
+        Ok(ast)
+    }
+
+    pub fn query_ast(&self, query: ast::Query, gc: bool) -> Solver {
+        Solver::from_database(self.0.clone(), query, gc)
+    }
+
+    // A compiled program image, followed by a checksum of its contents.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = header(MAGIC, VERSION);
+        let start = bytes.len();
+
+        self.0
+            .write(&mut bytes)
+            .expect("Serializing to memory can't fail");
+
+        seal(&mut bytes, start);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let body = check_header(bytes, MAGIC, VERSION, "compiled program").map_err(image_error)?;
+        let mut reader = unseal(body).map_err(image_error)?;
+
+        let database = Database::read(&mut reader).map_err(|e| image_error(e.to_string()))?;
+        if !reader.is_empty() {
+            return Err(image_error("unexpected data at the end".to_string()));
+        }
+
+        database.validate().map_err(image_error)?;
+
+        Ok(Program(Rc::new(database)))
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.0.warnings
+    }
+
+    pub fn database(&self) -> &Database {
+        &self.0
+    }
+}
+
+impl Database {
+    // Compiles more clauses into the program, and declares predicates dynamic
+    pub(crate) fn load(
+        &mut self,
+        clauses: impl IntoIterator<Item = ast::Clause>,
+        dynamic: &[(&str, usize)],
+        analysis: &mut compile::Analyses,
+    ) {
+        let mut string_map = StringMap::new(std::mem::take(&mut self.strings));
+
+        let changed = compile::compile(
+            clauses,
+            dynamic,
+            &mut self.index,
+            analysis,
+            &mut string_map,
+            &mut self.lambdas,
+            &mut self.code,
+            &mut self.sources,
+        );
+
+        self.strings = string_map.share();
+        self.switches.resize_with(self.index.len(), Switch::default);
+        for predicate in changed {
+            self.switches[predicate] = Switch::new(&self.index[predicate].1);
+        }
+    }
+
+    pub(crate) fn write(&self, w: &mut Vec<u8>) -> bincode::Result<()> {
+        write(w, self.strings.strings())?;
+        write(w, &self.index)?;
+        write(w, &self.code)?;
+        write(w, &self.lambdas)?;
+        write(w, &self.sources)?;
+        write(w, &self.lines)?;
+        write(w, &self.flags)
+    }
+
+    pub(crate) fn read(r: &mut &[u8]) -> bincode::Result<Self> {
+        let strings = Rc::new(Strings::new(read(r)?));
+        let index: Index = read(r)?;
+
+        Ok(Self {
+            strings,
+            switches: switches(&index),
+            index,
+            code: read(r)?,
+            lambdas: read(r)?,
+            sources: read(r)?,
+            lines: read(r)?,
+            flags: read(r)?,
+            warnings: Vec::new(),
+        })
+    }
+
+    // Checks that the code only refers to atoms, registers and lambdas that exist,
+    // that registers are written before they're read and that guards are
+    // built-in predicates, so that a corrupt image can't crash the solver.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let strings = self.strings.len();
+        let lambdas = self.lambdas.len();
+
+        // Built-in predicates are identified by their preloaded string ids
+        if !self
+            .strings
+            .strings()
+            .starts_with(Strings::default().strings())
+        {
+            return Err("preloaded atoms don't match".to_string());
+        }
+
+        let atom = |atom: Atom| !matches!(atom, Atom::String(id) | Atom::Text(id) if id >= strings);
+        let builtin =
+            |ClauseName(name, arity): ClauseName| builtins::PREDICATES.contains(&(name, arity));
+
+        for (ClauseName(functor, arity), clauses) in &self.index {
+            if *functor >= strings {
+                return Err("invalid predicate name".to_string());
+            }
+
+            let name = format!("{}/{}", self.strings.strings()[*functor], arity);
+
+            for (i, clause) in clauses.iter().enumerate() {
+                let registers = clause.registers;
+                let key = match clause.key {
+                    Some(Key::Atom(a)) => atom(a),
+                    Some(Key::Functor(f, _)) => f < strings,
+                    None => true,
+                };
+
+                let code = self.code.get(clause.head..clause.end).unwrap_or(&[]);
+
+                // Registers other than the arguments are each written by an
+                // instruction, which also bounds what the solver allocates
+                let mut valid = key
+                    && clause.head <= clause.body
+                    && clause.body <= clause.end
+                    && clause.end <= self.code.len()
+                    && *arity <= registers
+                    && registers <= arity + code.len()
+                    && clause.guards <= clause.goals;
+
+                if !valid {
+                    return Err(format!("clause {} of {} is invalid", i + 1, name));
+                }
+
+                // Number of arguments of the current structure yet to be unified or set
+                let mut args = 0;
+
+                // Registers keep what earlier clauses left in them until they're
+                // written. The goal each one holds is known for guards to be checked.
+                let mut written = vec![false; registers];
+                written[..*arity].fill(true);
+                let mut goals: Vec<Option<ClauseName>> = vec![None; registers];
+                let mut calls = 0;
+
+                let defined = |reg: Register, written: &[bool]| reg < registers && written[reg];
+                let define = |reg: Register,
+                              goal: Option<ClauseName>,
+                              written: &mut [bool],
+                              goals: &mut [Option<ClauseName>]| {
+                    if reg < registers {
+                        written[reg] = true;
+                        goals[reg] = goal;
+                    }
+                    reg < registers
+                };
+
+                for instruction in code {
+                    valid = valid
+                        && match *instruction {
+                            Instruction::GetVariable(var, arg) => {
+                                defined(arg, &written)
+                                    && define(var, None, &mut written, &mut goals)
+                            }
+                            Instruction::GetValue(var, arg) => {
+                                defined(var, &written) && defined(arg, &written)
+                            }
+                            Instruction::GetConstant(a, reg) => atom(a) && defined(reg, &written),
+                            Instruction::PutConstant(a, reg) => {
+                                let goal = match a {
+                                    Atom::String(name) => Some(ClauseName(name, 0)),
+                                    _ => None,
+                                };
+                                atom(a) && define(reg, goal, &mut written, &mut goals)
+                            }
+                            Instruction::GetStructure(f, arity, reg) => {
+                                args = arity;
+                                f < strings && defined(reg, &written)
+                            }
+                            Instruction::PutStructure(f, arity, reg) => {
+                                args = arity;
+                                f < strings
+                                    && define(
+                                        reg,
+                                        Some(ClauseName(f, arity)),
+                                        &mut written,
+                                        &mut goals,
+                                    )
+                            }
+                            Instruction::PutLambda(id, arity, reg) => {
+                                args = arity;
+                                id < lambdas && define(reg, None, &mut written, &mut goals)
+                            }
+                            Instruction::UnifyVariable(reg) | Instruction::SetVariable(reg) => {
+                                next_arg(&mut args) && define(reg, None, &mut written, &mut goals)
+                            }
+                            Instruction::UnifyValue(reg) | Instruction::SetValue(reg) => {
+                                next_arg(&mut args) && defined(reg, &written)
+                            }
+                            Instruction::UnifyConstant(a) | Instruction::SetConstant(a) => {
+                                next_arg(&mut args) && atom(a)
+                            }
+                            Instruction::UnifyVoid | Instruction::SetVoid => next_arg(&mut args),
+                            Instruction::PutVariable(reg) | Instruction::PutCut(reg) => {
+                                define(reg, None, &mut written, &mut goals)
+                            }
+                            Instruction::Call(reg, source) => {
+                                // Goals are called last first, so guards are the last calls
+                                calls += 1;
+                                let guard =
+                                    calls <= clause.goals && clause.goals - calls < clause.guards;
+                                defined(reg, &written)
+                                    && source < self.sources.len()
+                                    && (!guard || goals[reg].is_some_and(builtin))
+                            }
+                        };
+                }
+
+                if !valid || calls != clause.goals {
+                    return Err(format!("clause {} of {} is invalid", i + 1, name));
+                }
+            }
+        }
+
+        // Sources are only used to locate errors, but must point to a clause
+        for source in &self.sources {
+            if self
+                .index
+                .get(source.predicate)
+                .is_none_or(|(_, clauses)| source.clause >= clauses.len())
+            {
+                return Err("invalid goal source".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    // A listing of the clause index with the results of determinism analysis, for debugging.
+    pub fn dump(&self) -> String {
+        let mut result = String::new();
+        let name = |id: usize| self.strings.strings()[id].as_str();
+
+        for (ClauseName(functor, arity), clauses) in &self.index {
+            writeln!(result, "{}/{}", name(*functor), arity).unwrap();
+
+            for (i, clause) in clauses.iter().enumerate() {
+                let key = match clause.key {
+                    Some(Key::Atom(Atom::String(id))) => name(id).to_string(),
+                    Some(Key::Atom(Atom::Integer(i))) => i.to_string(),
+                    Some(Key::Atom(Atom::Float(f))) => f.to_string(),
+                    Some(Key::Atom(Atom::Text(id))) => crate::atom::quote_string(name(id)),
+                    Some(Key::Functor(f, arity)) => format!("{}/{}", name(f), arity),
+                    None => "-".to_string(),
+                };
+
+                let commit = match clause.commit {
+                    Commit::Never => "no".to_string(),
+                    Commit::Cut => "cut".to_string(),
+                    Commit::Exclusive { args, indexed } => {
+                        let args: Vec<String> = (0..32)
+                            .filter(|arg| args & (1 << arg) != 0)
+                            .map(|arg| (arg + 1).to_string())
+                            .collect();
+
+                        format!(
+                            "exclusive (bound: [{}]{})",
+                            args.join(","),
+                            if indexed { ", indexed" } else { "" }
+                        )
+                    }
+                };
+
+                writeln!(
+                    result,
+                    "  clause {}: key {}, guards {}, commit {}",
+                    i + 1,
+                    key,
+                    clause.guards,
+                    commit
+                )
+                .unwrap();
+
+                for pc in clause.head..clause.end {
+                    writeln!(result, "    {:>4}  {:?}", pc, self.code[pc]).unwrap();
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn predicates(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn clauses(&self) -> usize {
+        self.index.iter().map(|(_, clauses)| clauses.len()).sum()
+    }
+
+    pub fn instructions(&self) -> usize {
+        self.code.len()
+    }
+}
+
+fn switches(index: &Index) -> Vec<Switch> {
+    index
+        .iter()
+        .map(|(_, clauses)| Switch::new(clauses))
+        .collect()
+}
+
+fn next_arg(args: &mut usize) -> bool {
+    match args.checked_sub(1) {
+        Some(remaining) => {
+            *args = remaining;
+            true
+        }
+        None => false,
+    }
+}
+
+fn image_error(reason: String) -> Error {
+    Error::new(
+        ErrorKind::System,
+        format!("Invalid compiled program: {}", reason),
+    )
+}
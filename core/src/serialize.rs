@@ -1,124 +1,230 @@
+use crate::atom::quote;
 use crate::heap::Heap;
+use crate::stringmap::str;
 use crate::{HeapTerm, HeapTermPtr};
 
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 
+struct Printer<'a> {
+    heap: &'a Heap,
+    cycles: HashSet<HeapTermPtr>,
+    names: HashMap<HeapTermPtr, String>,
+    // Cyclic terms that were given a name like `_S1` and still need to be printed
+    substitutions: Vec<(String, HeapTermPtr)>,
+    // Deeper subterms and longer lists are elided as `...`
+    max_depth: usize,
+    depth: usize,
+}
+
 impl Heap {
+    // Cyclic terms are printed as `X = f(X)` when they are bound to one of the given
+    // variables, or otherwise as `_S1` with an additional `_S1 = f(_S1)` binding.
     pub fn serialize(&self, terms: &[(String, usize)]) -> Vec<(String, String)> {
-        let mut stacks: Vec<(String, Vec<HeapTermPtr>)> = terms
-            .iter()
-            .map(|(x, ptr)| (x.clone(), vec![*ptr]))
-            .collect();
+        let roots: Vec<HeapTermPtr> = terms.iter().map(|(_, ptr)| self.get_ptr(*ptr)).collect();
+        let mut printer = Printer::new(self, &roots);
 
-        terms
+        for ((name, _), root) in terms.iter().zip(&roots) {
+            if name != "_" && printer.cycles.contains(root) {
+                printer.names.entry(*root).or_insert_with(|| name.clone());
+            }
+        }
+
+        let mut result: Vec<(String, String)> = terms
             .iter()
-            .enumerate()
-            .map(|(i, (name, term))| {
-                (
-                    name.clone(),
-                    self.serialize_inner(*term, Some(i), &mut stacks, false)
-                        .unwrap(),
-                )
-            })
+            .zip(&roots)
+            .map(|((name, _), root)| (name.clone(), printer.print(*root, true)))
             .filter(|(x, _)| x != "_")
-            .collect()
+            .collect();
+
+        result.extend(printer.print_substitutions());
+
+        result
     }
 
-    fn serialize_inner(
-        &self,
-        mut term: HeapTermPtr,
-        stack: Option<usize>,
-        stacks: &mut Vec<(String, Vec<HeapTermPtr>)>,
-        mut continue_list: bool,
-    ) -> Result<String, std::fmt::Error> {
+    // Cyclic terms are printed as `@(_S1, [_S1 = f(_S1)])`.
+    pub fn serialize_term(&self, term: HeapTermPtr) -> String {
+        let mut printer = Printer::new(self, &[term]);
+        let result = printer.print(term, false);
+
+        let substitutions = printer.print_substitutions();
+        if substitutions.is_empty() {
+            return result;
+        }
+
+        let substitutions: Vec<String> = substitutions
+            .into_iter()
+            .map(|(name, term)| format!("{}={}", name, term))
+            .collect();
+
+        format!("@({},[{}])", result, substitutions.join(","))
+    }
+
+    // Prints at most `depth` levels of nested arguments and list elements, so
+    // cyclic terms need no substitutions.
+    pub fn serialize_bounded(&self, term: HeapTermPtr, depth: usize) -> String {
+        let mut printer = Printer {
+            heap: self,
+            cycles: HashSet::new(),
+            names: HashMap::new(),
+            substitutions: Vec::new(),
+            max_depth: depth,
+            depth: 0,
+        };
+
+        printer.print(term, true)
+    }
+}
+
+impl<'a> Printer<'a> {
+    fn new(heap: &'a Heap, roots: &[HeapTermPtr]) -> Self {
+        Self {
+            heap,
+            cycles: heap.cycles(roots),
+            names: HashMap::new(),
+            substitutions: Vec::new(),
+            max_depth: usize::MAX,
+            depth: 0,
+        }
+    }
+
+    fn print(&mut self, term: HeapTermPtr, top: bool) -> String {
         let mut result = String::new();
+        self.print_inner(term, top, &mut result).unwrap();
+        result
+    }
 
-        loop {
-            match &self.data[term] {
-                HeapTerm::Atom(atom) => {
-                    if continue_list {
-                        if atom.is_nil() {
-                            result.push(']');
-                            return Ok(result);
-                        }
+    fn print_substitutions(&mut self) -> Vec<(String, String)> {
+        let mut result = Vec::new();
 
-                        result.push('|');
-                    }
+        // Printing a substitution can find further cyclic terms
+        let mut i = 0;
+        while i < self.substitutions.len() {
+            let (name, term) = self.substitutions[i].clone();
+            result.push((name, self.print(term, true)));
+            i += 1;
+        }
 
-                    result.push_str(&atom.to_string(&self.string_map));
-                }
-                HeapTerm::Var(ptr, _, _, _) => {
-                    if *ptr == term {
-                        // The variable is unbound
-                        if continue_list {
-                            result.push('|');
+        result
+    }
+
+    // Refers to a cyclic term by name, to stop printing it again.
+    fn name(&mut self, term: HeapTermPtr) -> &str {
+        if !self.names.contains_key(&term) {
+            let name = format!("_S{}", self.substitutions.len() + 1);
+            self.substitutions.push((name.clone(), term));
+            self.names.insert(term, name);
+        }
+
+        &self.names[&term]
+    }
+
+    fn print_inner(
+        &mut self,
+        term: HeapTermPtr,
+        top: bool,
+        result: &mut String,
+    ) -> Result<(), std::fmt::Error> {
+        let heap = self.heap;
+        let term = heap.get_ptr(term);
+
+        if !top && self.cycles.contains(&term) {
+            result.push_str(self.name(term));
+            return Ok(());
+        }
+
+        if self.depth >= self.max_depth {
+            result.push_str("...");
+            return Ok(());
+        }
+
+        self.depth += 1;
+        self.print_term(term, result)?;
+        self.depth -= 1;
+
+        Ok(())
+    }
+
+    fn print_term(
+        &mut self,
+        term: HeapTermPtr,
+        result: &mut String,
+    ) -> Result<(), std::fmt::Error> {
+        let heap = self.heap;
+
+        match heap.term(term) {
+            HeapTerm::Atom(atom) => result.push_str(&atom.to_string(&heap.string_map)),
+            // The variable is unbound
+            HeapTerm::Var(ptr) => write!(result, "_{}", ptr)?,
+            HeapTerm::Compound(str::DOT, 2) => {
+                result.push('[');
+                self.print_inner(term + 1, false, result)?;
+
+                let mut tail = heap.get_ptr(term + 2);
+                let mut length = 1;
+                loop {
+                    match heap.term(tail) {
+                        HeapTerm::Compound(str::DOT, 2) if length >= self.max_depth => {
+                            result.push_str("|...");
+                            break;
                         }
-                        write!(result, "_{}", ptr)?;
-                    } else if let Some(name) = stacks
-                        .iter()
-                        .find(|(_, stack)| stack.contains(ptr))
-                        .map(|(name, _)| name)
-                    {
-                        // We've found a cycle
-                        if continue_list {
-                            result.push('|');
+                        HeapTerm::Compound(str::DOT, 2) if !self.cycles.contains(&tail) => {
+                            length += 1;
+                            result.push(',');
+                            self.print_inner(tail + 1, false, result)?;
+                            tail = heap.get_ptr(tail + 2);
                         }
-                        result.push_str(name);
-                    } else {
-                        if let Some(stack) = stack {
-                            // Keep track of this variable to find future cycles
-                            stacks[stack].1.push(*ptr);
+                        HeapTerm::Atom(atom) if atom.is_nil() => break,
+                        _ => {
+                            result.push('|');
+                            self.print_inner(tail, false, result)?;
+                            break;
                         }
-                        term = *ptr;
-                        continue;
                     }
                 }
-                HeapTerm::Compound(crate::stringmap::str::DOT, 2) => {
-                    let element = self.serialize_inner(term + 1, None, stacks, false)?;
-
-                    if element == "]" {
-                        match continue_list {
-                            true => write!(result, "]")?,
-                            false => write!(result, "[]")?,
-                        };
-                        return Ok(result);
-                    }
 
-                    match continue_list {
-                        true => write!(result, ",{}", element)?,
-                        false => write!(result, "[{}", element)?,
-                    };
+                result.push(']');
+            }
+            // Goals in braces are written the way they're read
+            HeapTerm::Compound(functor, 1) if heap.get_atom(functor) == "{}" => {
+                result.push('{');
+
+                let mut goal = heap.get_ptr(term + 1);
+                let mut length = 0;
+                while let HeapTerm::Compound(comma, 2) = heap.term(goal) {
+                    if heap.get_atom(comma) != ","
+                        || self.cycles.contains(&goal)
+                        || length >= self.max_depth
+                    {
+                        break;
+                    }
 
-                    term += 2;
-                    continue_list = true;
-                    continue;
+                    self.print_inner(goal + 1, false, result)?;
+                    result.push(',');
+                    goal = heap.get_ptr(goal + 2);
+                    length += 1;
                 }
-                HeapTerm::Compound(functor, arity) => {
-                    if continue_list {
-                        result.push('|');
-                    }
 
-                    write!(result, "{}(", self.get_atom(*functor))?;
+                self.print_inner(goal, false, result)?;
+                result.push('}');
+            }
+            HeapTerm::Compound(functor, arity) => {
+                write!(result, "{}(", quote(heap.get_atom(functor)))?;
 
-                    for i in 1..=*arity {
-                        result.push_str(&self.serialize_inner(term + i, None, stacks, false)?);
+                for i in 1..=arity {
+                    self.print_inner(term + i, false, result)?;
 
-                        if i < *arity {
-                            result.push(',');
-                        }
+                    if i < arity {
+                        result.push(',');
                     }
-
-                    result.push(')');
                 }
-                HeapTerm::Cut(_) => result.push('!'),
-                HeapTerm::Lambda(_, _) => result.push_str("<js_function>"),
-            };
 
-            if continue_list {
-                result.push(']');
+                result.push(')');
             }
+            HeapTerm::Cut(_) => result.push('!'),
+            HeapTerm::Lambda(_, _) => result.push_str("<js_function>"),
+        };
 
-            return Ok(result);
-        }
+        Ok(())
     }
 }
@@ -0,0 +1,137 @@
+use crate::encoding::{check_header, header, read, seal, unseal, write};
+use crate::heap::Heap;
+use crate::program::Database;
+use crate::stringmap::StringMap;
+use crate::trace::Tracer;
+use crate::{Error, ErrorKind, Solver};
+
+use std::rc::Rc;
+
+const MAGIC: &[u8; 4] = b"WPLS";
+const VERSION: u32 = 14;
+
+// The profiler, tracer, debugger, verifier and warnings are not part of a snapshot.
+impl Solver {
+    // A snapshot of the solver, followed by a checksum of its contents, as
+    // stored snapshots can be truncated or corrupted.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut bytes = header(MAGIC, VERSION);
+        let start = bytes.len();
+
+        self.write_snapshot(&mut bytes)
+            .expect("Serializing to memory can't fail");
+
+        seal(&mut bytes, start);
+        bytes
+    }
+
+    pub fn restore(bytes: &[u8]) -> Result<Self, Error> {
+        let body =
+            check_header(bytes, MAGIC, VERSION, "solver snapshot").map_err(snapshot_error)?;
+        let mut reader = unseal(body).map_err(snapshot_error)?;
+
+        let solver = Self::read_snapshot(&mut reader).map_err(|e| snapshot_error(e.to_string()))?;
+
+        if !reader.is_empty() {
+            return Err(snapshot_error("unexpected data at the end".to_string()));
+        }
+
+        solver.program.validate().map_err(snapshot_error)?;
+
+        if !solver.heap.has_valid_cells() {
+            return Err(snapshot_error("invalid heap cell".to_string()));
+        }
+
+        if !solver.gc.is_consistent(&solver.heap) {
+            return Err(snapshot_error(
+                "inconsistent garbage collector state".to_string(),
+            ));
+        }
+
+        Ok(solver)
+    }
+
+    fn write_snapshot(&self, w: &mut Vec<u8>) -> bincode::Result<()> {
+        self.program.write(w)?;
+
+        write(w, &self.heap.string_map.local_strings())?;
+        write(w, &self.heap.data)?;
+        write(w, &self.heap.data.capacity())?;
+        write(w, &self.heap.attributes)?;
+        write(w, &self.heap.barrier)?;
+        write(w, &self.lambdas)?;
+        write(w, &self.goals)?;
+        write(w, &self.group)?;
+        write(w, &self.clause)?;
+        write(w, &self.choice_points)?;
+        write(w, &self.choice_point_age)?;
+        write(w, &self.trail)?;
+        write(w, &self.var_map)?;
+        write(w, &self.query_spans)?;
+        write(w, &self.query_lines)?;
+        write(w, &self.flags)?;
+        write(w, &self.input[self.input_offset..])?;
+        write(w, &self.gc)?;
+        write(w, &self.stats)?;
+        write(w, &self.depth)
+    }
+
+    fn read_snapshot(r: &mut &[u8]) -> bincode::Result<Self> {
+        let program = Rc::new(Database::read(r)?);
+        let string_map = StringMap::with_local(program.strings.clone(), read(r)?);
+
+        // The collector is scheduled by the heap's capacity, so it is restored too
+        let mut data: Vec<_> = read(r)?;
+        let capacity: usize = read(r)?;
+        data.try_reserve_exact(capacity.saturating_sub(data.len()))
+            .map_err(|e| bincode::ErrorKind::Custom(e.to_string()))?;
+
+        let heap = Heap {
+            data,
+            attributes: read(r)?,
+            barrier: read(r)?,
+            string_map,
+        };
+
+        let lambdas = read(r)?;
+
+        // Goals are only retained for the debugger
+        let mut goals: crate::goal::Goals = read(r)?;
+        goals.retain = false;
+
+        Ok(Solver {
+            program,
+            registers: Vec::new(),
+            unify_stack: Vec::new(),
+            lambdas,
+            goals,
+            group: read(r)?,
+            clause: read(r)?,
+            choice_points: read(r)?,
+            choice_point_age: read(r)?,
+            heap,
+            trail: read(r)?,
+            var_map: read(r)?,
+            query_spans: read(r)?,
+            query_lines: read(r)?,
+            warnings: Vec::new(),
+            flags: read(r)?,
+            input: read(r)?,
+            input_offset: 0,
+            gc: read(r)?,
+            stats: read(r)?,
+            inference_limit: None,
+            profiler: None,
+            tracer: Tracer::default(),
+            verifier: None,
+            depth: read(r)?,
+
+            #[cfg(test)]
+            interrupt: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        })
+    }
+}
+
+fn snapshot_error(reason: String) -> Error {
+    Error::new(ErrorKind::System, format!("Invalid snapshot: {}", reason))
+}
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct Statistics {
+    pub(crate) inferences: usize,
+    created: f64,
+    cpu_time: f64,
+    #[serde(skip)]
+    resumed: Option<f64>,
+    last_runtime: f64,
+    last_walltime: f64,
+}
+
+impl Statistics {
+    pub fn new() -> Self {
+        let created = now();
+
+        Self {
+            inferences: 0,
+            created,
+            cpu_time: 0.0,
+            resumed: None,
+            last_runtime: 0.0,
+            last_walltime: 0.0,
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.resumed = Some(now());
+    }
+
+    pub fn suspend(&mut self) {
+        if let Some(resumed) = self.resumed.take() {
+            self.cpu_time += now() - resumed;
+        }
+    }
+
+    // Milliseconds spent solving the query so far.
+    pub fn cpu_time(&self) -> f64 {
+        self.cpu_time + self.resumed.map(|resumed| now() - resumed).unwrap_or(0.0)
+    }
+
+    // Milliseconds since the solver was created.
+    pub fn wall_time(&self) -> f64 {
+        now() - self.created
+    }
+
+    // Returns the total runtime and the runtime since the last call, in milliseconds.
+    pub fn runtime(&mut self) -> (f64, f64) {
+        let runtime = self.cpu_time();
+        let since = runtime - self.last_runtime;
+        self.last_runtime = runtime;
+        (runtime, since)
+    }
+
+    // Returns the total wall time and the wall time since the last call, in milliseconds.
+    pub fn walltime(&mut self) -> (f64, f64) {
+        let walltime = self.wall_time();
+        let since = walltime - self.last_walltime;
+        self.last_walltime = walltime;
+        (walltime, since)
+    }
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Current time in milliseconds.
+#[cfg(target_family = "wasm")]
+pub fn now() -> f64 {
+    js_sys::Date::now()
+}
+
+// Current time in milliseconds.
+#[cfg(not(target_family = "wasm"))]
+pub fn now() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0)
+}
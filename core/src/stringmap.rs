@@ -1,6 +1,7 @@
 use crate::StringId;
 
 use std::collections::HashMap;
+use std::rc::Rc;
 
 // Pre-loaded strings with known values
 pub mod str {
@@ -35,14 +36,43 @@ pub mod str {
     pub const CALL: usize = 28;
     pub const NONVAR: usize = 29;
     pub const FREEZE: usize = 30;
+    pub const EXIT: usize = 31;
+    pub const TRACE: usize = 32;
+    pub const NOTRACE: usize = 33;
+    pub const SPY: usize = 34;
+    pub const NOSPY: usize = 35;
+    pub const CYCLIC_TERM: usize = 36;
+    pub const ACYCLIC_TERM: usize = 37;
+    pub const GARBAGE_COLLECT: usize = 38;
+    pub const SET_PROLOG_FLAG: usize = 39;
+    pub const CURRENT_PROLOG_FLAG: usize = 40;
+    pub const TRUE: usize = 41;
+    pub const FAIL: usize = 42;
+    pub const FALSE: usize = 43;
+    pub const STRING: usize = 44;
+    pub const NUMBER_CODES: usize = 45;
+    pub const PHRASE: usize = 46;
+    pub const READ_TERM: usize = 47;
+    pub const TERM_TO_ATOM: usize = 48;
+    pub const TERM_STRING: usize = 49;
 }
 
-pub struct StringMap {
-    map: HashMap<String, usize>,
+// Strings interned by a compiled program, shared by all of its solvers
+pub struct Strings {
+    map: HashMap<String, StringId>,
     reverse: Vec<String>,
 }
 
-impl Default for StringMap {
+// Strings interned while solving a query are kept apart from the shared ones,
+// and can be collected once they're no longer used
+pub struct StringMap {
+    pub(crate) shared: Rc<Strings>,
+    map: HashMap<String, StringId>,
+    reverse: Vec<Option<String>>,
+    free: Vec<StringId>,
+}
+
+impl Default for Strings {
     fn default() -> Self {
         let reverse = vec![
             "!".to_string(),
@@ -76,31 +106,180 @@ impl Default for StringMap {
             "call".to_string(),
             "nonvar".to_string(),
             "freeze".to_string(),
+            "$exit".to_string(),
+            "trace".to_string(),
+            "notrace".to_string(),
+            "spy".to_string(),
+            "nospy".to_string(),
+            "cyclic_term".to_string(),
+            "acyclic_term".to_string(),
+            "garbage_collect".to_string(),
+            "set_prolog_flag".to_string(),
+            "current_prolog_flag".to_string(),
+            "true".to_string(),
+            "fail".to_string(),
+            "false".to_string(),
+            "string".to_string(),
+            "number_codes".to_string(),
+            "phrase".to_string(),
+            "read_term".to_string(),
+            "term_to_atom".to_string(),
+            "term_string".to_string(),
         ];
 
-        let map = reverse
-            .iter()
-            .enumerate()
-            .map(|(a, b)| (b.clone(), a))
-            .collect();
+        Strings::new(reverse)
+    }
+}
+
+impl Strings {
+    pub fn new(reverse: Vec<String>) -> Self {
+        Self {
+            map: index(reverse.iter().map(Some), 0),
+            reverse,
+        }
+    }
 
-        StringMap { map, reverse }
+    pub fn strings(&self) -> &[String] {
+        &self.reverse
+    }
+
+    pub fn len(&self) -> usize {
+        self.reverse.len()
+    }
+}
+
+fn index<'a>(
+    strings: impl Iterator<Item = Option<&'a String>>,
+    offset: usize,
+) -> HashMap<String, StringId> {
+    strings
+        .enumerate()
+        .filter_map(|(i, s)| Some((s?.clone(), i + offset)))
+        .collect()
+}
+
+impl Default for StringMap {
+    fn default() -> Self {
+        Self::new(Rc::new(Strings::default()))
     }
 }
 
 impl StringMap {
+    pub fn new(shared: Rc<Strings>) -> Self {
+        Self {
+            shared,
+            map: HashMap::new(),
+            reverse: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    // Merges the local strings into a new shared table. Only used for programs,
+    // whose strings are never collected.
+    pub fn share(self) -> Rc<Strings> {
+        let mut shared = Rc::try_unwrap(self.shared).unwrap_or_else(|shared| Strings {
+            map: shared.map.clone(),
+            reverse: shared.reverse.clone(),
+        });
+
+        shared.map.extend(self.map);
+        shared.reverse.extend(
+            self.reverse
+                .into_iter()
+                .map(|s| s.expect("Strings are shared before being collected")),
+        );
+
+        Rc::new(shared)
+    }
+
+    // Rebuilds a string map from the strings interned after the shared ones.
+    pub fn with_local(shared: Rc<Strings>, local: Vec<Option<String>>) -> Self {
+        let mut result = Self {
+            map: index(local.iter().map(Option::as_ref), shared.len()),
+            reverse: local,
+            free: Vec::new(),
+            shared,
+        };
+
+        result.update_free();
+        result
+    }
+
+    pub fn local_strings(&self) -> &[Option<String>] {
+        &self.reverse
+    }
+
     pub fn alloc(&mut self, atom: &str) -> StringId {
-        if let Some(ptr) = self.map.get(atom) {
+        if let Some(ptr) = self.shared.map.get(atom) {
+            *ptr
+        } else if let Some(ptr) = self.map.get(atom) {
             *ptr
+        } else if let Some(ptr) = self.free.pop() {
+            self.map.insert(atom.to_string(), ptr);
+            self.reverse[ptr - self.shared.len()] = Some(atom.to_string());
+            ptr
         } else {
-            let ptr = self.reverse.len();
+            let ptr = self.len();
             self.map.insert(atom.to_string(), ptr);
-            self.reverse.push(atom.to_string());
+            self.reverse.push(Some(atom.to_string()));
             ptr
         }
     }
 
     pub fn get(&self, ptr: StringId) -> Option<&str> {
-        self.reverse.get(ptr).map(|s| s.as_str())
+        match ptr.checked_sub(self.shared.reverse.len()) {
+            None => self.shared.reverse.get(ptr),
+            Some(local) => self.reverse.get(local).and_then(Option::as_ref),
+        }
+        .map(|s| s.as_str())
+    }
+
+    // Upper bound of the ids in use
+    pub fn len(&self) -> usize {
+        self.shared.reverse.len() + self.reverse.len()
+    }
+
+    // Number of strings in use
+    pub fn count(&self) -> usize {
+        self.len() - self.free.len()
+    }
+
+    pub fn local_count(&self) -> usize {
+        self.reverse.len() - self.free.len()
+    }
+
+    // Frees the local strings that aren't marked, so that their ids can be reused.
+    // Returns the number of strings that were collected.
+    pub fn collect(&mut self, marked: &[bool]) -> usize {
+        let mut collected = 0;
+
+        for (s, _) in self
+            .reverse
+            .iter_mut()
+            .zip(marked)
+            .filter(|(s, marked)| s.is_some() && !**marked)
+        {
+            self.map.remove(&s.take().unwrap());
+            collected += 1;
+        }
+
+        while let Some(None) = self.reverse.last() {
+            self.reverse.pop();
+        }
+
+        self.update_free();
+
+        collected
+    }
+
+    fn update_free(&mut self) {
+        let offset = self.shared.len();
+
+        // Lowest ids are reused first
+        self.free = (0..self.reverse.len())
+            .rev()
+            .filter(|i| self.reverse[*i].is_none())
+            .map(|i| i + offset)
+            .collect();
     }
 }
@@ -1,5 +1,5 @@
 use crate::tests::SolverFn;
-use crate::{test, Error, Solver};
+use crate::{test, Error, ErrorKind, ErrorLocation, Solver};
 
 test!(cut, |solver: SolverFn| {
     let program = r#"
@@ -146,10 +146,29 @@ test!(insufficient_instantiation, |solver: SolverFn| {
     let mut solver = solver("", "X is Y.");
     if let Err(e) = solver.step() {
         assert_eq!(
-            e,
             Error {
-                location: None,
-                error: "Insufficiently instantiated variable `_4`".into()
+                backtrace: vec![],
+                term: None,
+                ..e
+            },
+            Error {
+                location: Some(Box::new(ErrorLocation {
+                    query: true,
+                    offset: 0,
+                    line: 1,
+                    column: 1,
+                    end: 6,
+                    end_line: 1,
+                    end_column: 7,
+                    utf16_offset: 0,
+                    utf16_end: 6,
+                    predicate: None,
+                    clause: None,
+                })),
+                ..Error::new(
+                    ErrorKind::Instantiation,
+                    "Insufficiently instantiated variable `_4`".into()
+                )
             }
         );
     } else {
@@ -182,6 +201,22 @@ test!(typecheck, |solver: SolverFn| {
     assert_eq!(solver_3.step().unwrap(), None);
 
     assert_eq!(solver_4.step().unwrap(), None);
+
+    // Numbers and strings aren't atoms, but [] is
+    let program = r#"
+        t(a).
+        t('b c').
+        t([]).
+        t(1).
+        t(1.5).
+        t("s").
+        t(f(a)).
+        t(_).
+    "#;
+    let answers: Vec<_> = solver(program, "t(X), atom(X).")
+        .map(|answer| answer.unwrap()[0].1.to_string())
+        .collect();
+    assert_eq!(answers, vec!["a", "'b c'", "[]"]);
 });
 
 test!(call, |solver: SolverFn| {
@@ -233,7 +268,232 @@ test!(dif, |solver: SolverFn| {
         Some(vec![
             ("X".into(), "4".into()),
             ("Y".into(), "3".into()),
-            ("Z".into(), "X".into())
+            ("Z".into(), "4".into())
         ])
     );
 });
+
+test!(statistics, |solver: SolverFn| {
+    let program = r#"
+        count(0).
+        count(N) :- N > 0, N1 is N - 1, count(N1).
+    "#;
+
+    // The query, three calls for each of the ten recursive clauses and statistics/2
+    let mut solver_1 = solver(program, "count(10), statistics(inferences, I).");
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![("I".into(), "32".into())])
+    );
+
+    // Retrying `p(X)` isn't a call, and neither are the markers for exit ports
+    let program_2 = "p(1). p(2). p(3).";
+    let query_2 = "p(X), X >= 3, statistics(inferences, I).";
+    let expected = Some(vec![("X".into(), "3".into()), ("I".into(), "5".into())]);
+    assert_eq!(solver(program_2, query_2).step().unwrap(), expected);
+    let mut profiled = solver(program_2, query_2);
+    profiled.enable_profiler();
+    assert_eq!(profiled.step().unwrap(), expected);
+
+    let mut solver_5 = solver(
+        program_2,
+        "p(X), statistics(choicepoints, C1), statistics(trail, T1), \
+         p(3), statistics(choicepoints, C2), statistics(goals, G), statistics(atoms, A).",
+    );
+    let solution = solver_5.step().unwrap().unwrap();
+    let value = |name: &str| {
+        let (_, value) = solution.iter().find(|(var, _)| var == name).unwrap();
+        value.parse::<usize>().unwrap()
+    };
+    assert_eq!(value("C1"), 1);
+    assert!(value("T1") > 0);
+    assert_eq!(value("C2"), 1);
+    assert!(value("G") > 0);
+    assert!(value("A") > 40);
+
+    let mut solver_6 = solver(
+        "",
+        "garbage_collect, statistics(gc, N), statistics(gc_time, T), \
+         statistics(gc_collected, C), statistics(gc_atoms, A), statistics(gc_trail, R), \
+         statistics(gc_max_pause, P), float(P), statistics(gc_last, [B, F, _, _, _]), B >= F.",
+    );
+    let solution = solver_6.step().unwrap().unwrap();
+    assert_eq!(solution[0], ("N".into(), "1".into()));
+    for (var, value) in &solution[1..5] {
+        assert!(value.parse::<usize>().is_ok(), "{} = {}", var, value);
+    }
+
+    let mut solver_2 = solver(
+        "",
+        "statistics(runtime, [T, S]), statistics(walltime, [W, _]).",
+    );
+    assert!(solver_2.step().unwrap().is_some());
+
+    let mut solver_3 = solver("", "statistics(cputime, T), float(T).");
+    assert!(solver_3.step().unwrap().is_some());
+
+    let mut solver_4 = solver("", "statistics(unknown, T).");
+    assert_eq!(solver_4.step().unwrap(), None);
+});
+
+test!(number_codes, |solver: SolverFn| {
+    let mut solver_1 = solver(
+        "",
+        r#"number_codes(A, " 0x1F"), number_codes(B, "\n-1.5e3"), number_codes(C, [0'1, 0'2])."#,
+    );
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("A".into(), "31".into()),
+            ("B".into(), "-1500.0".into()),
+            ("C".into(), "12".into())
+        ])
+    );
+
+    let mut solver_2 = solver("", "number_codes(1.0e20, C), number_codes(X, C).");
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![
+            ("C".into(), "[49,46,48,101,50,48]".into()),
+            ("X".into(), "1.0e20".into())
+        ])
+    );
+
+    let mut solver_3 = solver("", r#"number_codes(12, "012")."#);
+    assert_eq!(solver_3.step().unwrap(), Some(vec![]));
+
+    let errors = [
+        (r#"number_codes(X, "12a")."#, "Illegal number `12a`"),
+        (r#"number_codes(X, " 12 ")."#, "Illegal number ` 12 `"),
+        (r#"number_codes(X, "12\n")."#, "Illegal number `12\n`"),
+        (r#"number_codes(X, "- 1")."#, "Illegal number `- 1`"),
+        (r#"number_codes(X, " +\t1")."#, "Illegal number ` +\t1`"),
+        (
+            "number_codes(X, Y).",
+            "Insufficiently instantiated variable `_3`",
+        ),
+        ("number_codes(foo, X).", "Expected a number, got `foo`"),
+        (
+            "number_codes(X, [0'1|foo]).",
+            "Expected a list, got `[49|foo]`",
+        ),
+        ("number_codes(X, [-1]).", "Invalid character code `-1`"),
+    ];
+
+    for (query, error) in errors {
+        assert_eq!(solver("", query).step().unwrap_err().error, error);
+    }
+});
+
+test!(term_to_atom, |solver: SolverFn| {
+    let mut solver_1 = solver(
+        "",
+        r#"term_to_atom(f(a, 'b c', [1,2], "s"), A), term_to_atom(foo(X, Y, X), 'foo(1, 2, Z).')."#,
+    );
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("A".into(), r#"'f(a,\'b c\',[1,2],"s")'"#.into()),
+            ("X".into(), "1".into()),
+            ("Y".into(), "2".into())
+        ])
+    );
+
+    let mut solver_2 = solver(
+        "",
+        r#"term_string(1 + x, S), term_string([A|B], "[a|T]"), B = [], term_string(T, "g(1,2)")."#,
+    );
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![
+            ("S".into(), r#""+(1,x)""#.into()),
+            ("A".into(), "a".into()),
+            ("B".into(), "[]".into()),
+            ("T".into(), "g(1,2)".into())
+        ])
+    );
+
+    let errors = [
+        (
+            "term_to_atom(T, 'a b').",
+            "Syntax error in atom: Unexpected token `b`, expected `(`, `.` or an operator",
+        ),
+        (
+            r#"term_string(T, "a b")."#,
+            "Syntax error in string: Unexpected token `b`, expected `(`, `.` or an operator",
+        ),
+        (
+            "term_to_atom(T, A).",
+            "Insufficiently instantiated variable `_3`",
+        ),
+        ("term_to_atom(T, 12).", "Expected an atom, got `12`"),
+        ("term_string(T, 12).", "Expected a string, got `12`"),
+        (
+            "term_string(T, S).",
+            "Insufficiently instantiated variable `_3`",
+        ),
+    ];
+
+    for (query, error) in errors {
+        assert_eq!(solver("", query).step().unwrap_err().error, error);
+    }
+});
+
+test!(read_term, |solver: SolverFn| {
+    let query = "read_term(T, [variable_names(V), singletons(S)]), V = ['X'=1, 'Y'=2, '_Z'=3], \
+                 read_term(user_input, U, []), read_term(E, []).";
+    let mut solver_1 = solver("", query);
+    solver_1.add_input("foo(X, Y, _Z, X).\n% comment\nbar.\n");
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("T".into(), "foo(1,2,3,1)".into()),
+            ("V".into(), "[=('X',1),=('Y',2),=('_Z',3)]".into()),
+            ("S".into(), "[=('Y',2)]".into()),
+            ("U".into(), "bar".into()),
+            ("E".into(), "end_of_file".into())
+        ])
+    );
+
+    // A term with a syntax error is skipped, so the next read continues after it
+    let mut solver_2 = solver("", "read_term(T, []).");
+    solver_2.add_input("a b. c.");
+    assert_eq!(
+        solver_2.step().unwrap_err().error,
+        "Syntax error in read term: Unexpected token `b`, expected `(`, `.` or an operator"
+    );
+    assert_eq!(&solver_2.input[solver_2.input_offset..], " c.");
+
+    // Only the term with the bad token fails, and the terms before it are read
+    let query = "read_term(A, []), read_term(B, []), read_term(C, []), read_term(D, []).";
+    let mut solver_3 = solver("", query);
+    solver_3.add_input("'a. b'. [0'., \"c. d\", =..]. e € f. g.");
+    assert_eq!(
+        solver_3.step().unwrap_err().error,
+        "Syntax error in read term: Invalid token"
+    );
+    assert_eq!(&solver_3.input[solver_3.input_offset..], " g.");
+
+    let mut solver_4 = solver("", "read_term(A, []), read_term(B, []).");
+    solver_4.add_input("'a. b'. /* c. */ [0'., \"c. d\", =..]. e € f. g.");
+    assert_eq!(
+        solver_4.step().unwrap(),
+        Some(vec![
+            ("A".into(), "'a. b'".into()),
+            ("B".into(), "[46,\"c. d\",=..]".into())
+        ])
+    );
+
+    let errors = [
+        ("read_term(T, [foo]).", "Expected a read option, got `foo`"),
+        ("read_term(T, foo).", "Expected a list, got `foo`"),
+        (
+            "read_term(foo, T, []).",
+            "Expected a stream or alias, got `foo`",
+        ),
+    ];
+
+    for (query, error) in errors {
+        assert_eq!(solver("", query).step().unwrap_err().error, error);
+    }
+});
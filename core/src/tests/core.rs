@@ -1,5 +1,5 @@
 use crate::tests::SolverFn;
-use crate::{test, Solver};
+use crate::{ast, test, Program, Solver};
 
 static APP_PROGRAM: &str = r#"
 app([], L2, L2).
@@ -162,6 +162,171 @@ test!(underscore, |solver: SolverFn| {
     assert_eq!(solver.step().unwrap(), None);
 });
 
+test!(quoted_atoms, |solver: SolverFn| {
+    let program = r#"
+        a('hello world').
+        a('it''s').
+        a('\x41\').
+        a('tab\there').
+        a('[]').
+        a('+').
+    "#;
+
+    let answers: Vec<_> = solver(program, "a(X).")
+        .map(|answer| answer.unwrap()[0].1.to_string())
+        .collect();
+    assert_eq!(
+        answers,
+        vec![
+            "'hello world'",
+            r"'it\'s'",
+            "'A'",
+            r"'tab\there'",
+            "[]",
+            "+"
+        ]
+    );
+
+    let mut solver_1 = solver("", r"X = 0'a, Y = 0'\n, Z = 'abc', Z == abc.");
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("X".into(), "97".into()),
+            ("Y".into(), "10".into()),
+            ("Z".into(), "abc".into())
+        ])
+    );
+
+    // Solutions print atoms quoted where they need it, including inside terms
+    let mut solver_2 = solver("", "X = 'Hello', Y = f('a b', [], 'It'), Z = abc.");
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![
+            ("X".into(), "'Hello'".into()),
+            ("Y".into(), "f('a b',[],'It')".into()),
+            ("Z".into(), "abc".into())
+        ])
+    );
+});
+
+test!(number_syntax, |solver: SolverFn| {
+    let query = "A = 1.0e10, B = 1.5E-3, C = 2e3, D = 0x1F, E = 0b1010, F = 0o17, \
+                 G = 16'FF, H = 36'z, I = 1 000 000, J = 1_000_000, K = 0'a.";
+
+    let mut solver = solver("", query);
+    assert_eq!(
+        solver.step().unwrap(),
+        Some(vec![
+            ("A".into(), "10000000000.0".into()),
+            ("B".into(), "0.0015".into()),
+            ("C".into(), "2000.0".into()),
+            ("D".into(), "31".into()),
+            ("E".into(), "10".into()),
+            ("F".into(), "15".into()),
+            ("G".into(), "255".into()),
+            ("H".into(), "35".into()),
+            ("I".into(), "1000000".into()),
+            ("J".into(), "1000000".into()),
+            ("K".into(), "97".into())
+        ])
+    );
+});
+
+test!(unicode_names, |solver: SolverFn| {
+    let program = r#"
+        ville(café).
+        ville(λ).
+        ville(東京).
+        ville('Zürich').
+    "#;
+
+    let answers: Vec<_> = solver(program, "ville(Ñame).")
+        .map(|answer| answer.unwrap()[0].clone())
+        .collect();
+    assert_eq!(
+        answers,
+        vec![
+            ("Ñame".into(), "café".into()),
+            ("Ñame".into(), "λ".into()),
+            ("Ñame".into(), "東京".into()),
+            ("Ñame".into(), "'Zürich'".into())
+        ]
+    );
+
+    let mut solver = solver("", "X = (=..), Y = ;, Z = [@, ->, \\].");
+    assert_eq!(
+        solver.step().unwrap(),
+        Some(vec![
+            ("X".into(), "=..".into()),
+            ("Y".into(), ";".into()),
+            ("Z".into(), r"[@,->,\]".into())
+        ])
+    );
+});
+
+test!(operators_before_signs, |solver: SolverFn| {
+    // Symbol atoms don't swallow an operator written right before a sign
+    let mut solver_1 = solver("", "X=-1, Y is 3*-2, Y>=-6, Z = (\\+a=b), W = '?-'.");
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("X".into(), "-1".into()),
+            ("Y".into(), "-6".into()),
+            ("Z".into(), r"'\\+'(=(a,b))".into()),
+            ("W".into(), "'?-'".into())
+        ])
+    );
+
+    let program = Program::parse(r"a:-\+b.").unwrap();
+    let ast::Clause(_, body, _) = &program.0[0];
+    assert!(matches!(
+        &body[..],
+        [ast::Term::Compound(not, args, _)]
+            if not == r"\+" && matches!(&args[..], [ast::Term::Atom(ast::Atom::String(b), _)] if b == "b")
+    ));
+});
+
+test!(operator_atoms, |solver: SolverFn| {
+    // Operators are atoms where a term ends, as arguments, list elements or terms
+    // on their own
+    let query = "A = f(+), B = [+, -, *], C = f(=), D = f(<, >), E = f(a, *), \
+                 F = [a|-], G = (-), H = ++ , I = < .";
+    let mut solver_1 = solver("", query);
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("A".into(), "f(+)".into()),
+            ("B".into(), "[+,-,*]".into()),
+            ("C".into(), "f(=)".into()),
+            ("D".into(), "f(<,>)".into()),
+            ("E".into(), "f(a,*)".into()),
+            ("F".into(), "[a|-]".into()),
+            ("G".into(), "-".into()),
+            ("H".into(), "'++'".into()),
+            ("I".into(), "<".into())
+        ])
+    );
+
+    // Otherwise they are still operators
+    let mut solver_2 = solver("", "X = f(- 1, - a, 1 - -1), term_to_atom(Y, '*').");
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![
+            ("X".into(), "f(-1,-(a),-(1,-1))".into()),
+            ("Y".into(), "*".into())
+        ])
+    );
+
+    let mut solver_3 = solver("a([<<, >=], f(mod)).", "a(X, Y).");
+    assert_eq!(
+        solver_3.step().unwrap(),
+        Some(vec![
+            ("X".into(), "[<<,>=]".into()),
+            ("Y".into(), "f(mod)".into())
+        ])
+    );
+});
+
 test!(LONG tak, |solver: SolverFn| {
     let program = r#"
         tak(X,Y,Z,A) :-
@@ -198,3 +363,51 @@ fn nullary_predicate() {
     assert_eq!(solver.step().unwrap(), Some(vec![]));
     assert_eq!(solver.step().unwrap(), None);
 }
+
+test!(nested_head, |solver: SolverFn| {
+    let program = "p(f(g(X), [X|T]), X, T).";
+
+    let mut solver_1 = solver(program, "p(A, 1, []).");
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![("A".into(), "f(g(1),[1])".into())])
+    );
+    assert_eq!(solver_1.step().unwrap(), None);
+
+    let mut solver_2 = solver(program, "p(f(g(2), [Y, 3]), Y, T).");
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![("Y".into(), "2".into()), ("T".into(), "[3]".into())])
+    );
+    assert_eq!(solver_2.step().unwrap(), None);
+
+    let mut solver_3 = solver(program, "p(f(g(2), [3]), Y, T).");
+    assert_eq!(solver_3.step().unwrap(), None);
+});
+
+test!(first_argument_indexing, |solver: SolverFn| {
+    let program = r#"
+        colour(red, 1).
+        colour(green, 2).
+        colour(blue, 3).
+        colour(f(X), X).
+    "#;
+
+    let mut solver_1 = solver(program, "colour(green, X).");
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![("X".into(), "2".into())])
+    );
+    assert!(solver_1.choice_points.is_empty());
+    assert_eq!(solver_1.step().unwrap(), None);
+
+    let mut solver_2 = solver(program, "colour(f(4), X).");
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![("X".into(), "4".into())])
+    );
+    assert!(solver_2.choice_points.is_empty());
+
+    let solver_3 = solver(program, "colour(C, X).");
+    assert_eq!(solver_3.count(), 4);
+});
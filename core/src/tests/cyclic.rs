@@ -0,0 +1,93 @@
+use crate::tests::SolverFn;
+use crate::{test, Solver};
+
+test!(cyclic_unify, |solver: SolverFn| {
+    let mut solver_1 = solver("", "X = f(X).");
+    let mut solver_2 = solver("", "X = f(X), Y = f(Y), X = Y.");
+    let mut solver_3 = solver("", "X = f(X, a), Y = f(Y, b), X = Y.");
+    let mut solver_4 = solver("", "X = f(X), Y = f(f(Y)), X = Y.");
+
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![("X".into(), "f(X)".into())])
+    );
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![
+            ("X".into(), "f(X)".into()),
+            ("Y".into(), "f(Y)".into())
+        ])
+    );
+    assert_eq!(solver_3.step().unwrap(), None);
+    assert_eq!(
+        solver_4.step().unwrap(),
+        Some(vec![
+            ("X".into(), "f(X)".into()),
+            ("Y".into(), "f(f(Y))".into())
+        ])
+    );
+});
+
+test!(cyclic_equiv, |solver: SolverFn| {
+    let mut solver_1 = solver("", "X = f(X), Y = f(f(Y)), X == Y.");
+    let mut solver_2 = solver("", "X = f(X, a), Y = f(Y, b), X == Y.");
+    let mut solver_3 = solver("", "X = [1, 2|X], Y = [1, 2, 1, 2|Y], X == Y.");
+
+    assert!(solver_1.step().unwrap().is_some());
+    assert_eq!(solver_2.step().unwrap(), None);
+    assert!(solver_3.step().unwrap().is_some());
+});
+
+test!(cyclic_term, |solver: SolverFn| {
+    let mut solver_1 = solver("", "X = f(Y, X), cyclic_term(X), cyclic_term(f(X)).");
+    let mut solver_2 = solver("", "X = f(Y), acyclic_term(X), acyclic_term(a).");
+    let mut solver_3 = solver("", "X = [a|X], acyclic_term(X).");
+    let mut solver_4 = solver("", "X = g(Y, Y), Y = f(a), cyclic_term(X).");
+
+    assert!(solver_1.step().unwrap().is_some());
+    assert!(solver_2.step().unwrap().is_some());
+    assert_eq!(solver_3.step().unwrap(), None);
+    assert_eq!(solver_4.step().unwrap(), None);
+});
+
+test!(cyclic_print, |solver: SolverFn| {
+    let program = r#"
+        p(g(Y)) :- Y = f(Y).
+        q(L) :- L = [a, b|L].
+    "#;
+
+    let mut solver_1 = solver(program, "p(X).");
+    let mut solver_2 = solver(program, "q(L).");
+    let mut solver_3 = solver(program, "p(X), q(L), Y = h(X, L).");
+
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("X".into(), "g(_S1)".into()),
+            ("_S1".into(), "f(_S1)".into())
+        ])
+    );
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![("L".into(), "[a,b|L]".into())])
+    );
+    assert_eq!(
+        solver_3.step().unwrap(),
+        Some(vec![
+            ("X".into(), "g(_S1)".into()),
+            ("L".into(), "[a,b|L]".into()),
+            ("Y".into(), "h(g(_S1),L)".into()),
+            ("_S1".into(), "f(_S1)".into())
+        ])
+    );
+});
+
+#[test]
+fn cyclic_arithmetic() {
+    let mut solver = Solver::new("", "X = X + 1, Y is X.").unwrap();
+
+    assert_eq!(
+        solver.step().unwrap_err().error,
+        "Expected an acyclic term, got `@(_S1,[_S1=+(_S1,1)])`"
+    );
+}
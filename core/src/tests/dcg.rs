@@ -0,0 +1,186 @@
+use crate::tests::SolverFn;
+use crate::{test, ErrorKind, Program, Solver};
+
+test!(nonterminals, |solver: SolverFn| {
+    let program = r#"
+        greeting --> [hello], name.
+        name --> [world].
+        name --> [prolog].
+    "#;
+
+    let mut solver = solver(program, "phrase(greeting, [hello, X]).");
+    assert_eq!(
+        solver.step().unwrap(),
+        Some(vec![("X".into(), "world".into())])
+    );
+    assert_eq!(
+        solver.step().unwrap(),
+        Some(vec![("X".into(), "prolog".into())])
+    );
+    assert_eq!(solver.step().unwrap(), None);
+    assert!(solver.warnings().is_empty());
+});
+
+test!(goals_and_codes, |solver: SolverFn| {
+    let program = r#"
+        :- set_prolog_flag(double_quotes, codes).
+
+        number(N) --> digits(Ds), { number_codes(N, Ds) }.
+        digits([D|T]) --> digit(D), digits(T).
+        digits([D]) --> digit(D).
+        digit(D) --> [D], { D >= 0'0, D =< 0'9 }.
+
+        sum(S) --> number(A), "+", number(B), !, { S is A + B }.
+    "#;
+
+    let mut solver = solver(program, r#"phrase(sum(S), "12+30")."#);
+    assert_eq!(
+        solver.step().unwrap(),
+        Some(vec![("S".into(), "42".into())])
+    );
+    assert_eq!(solver.step().unwrap(), None);
+});
+
+test!(strings, |solver: SolverFn| {
+    let mut solver = solver(r#"bang --> "ab", [0'!]."#, "phrase(bang, [97, 98, 33]).");
+    assert_eq!(solver.step().unwrap(), Some(vec![]));
+});
+
+test!(pushback, |solver: SolverFn| {
+    let program = r#"
+        peek(X), [X] --> [X].
+    "#;
+
+    let mut solver = solver(program, "phrase(peek(X), [a, b], R).");
+    assert_eq!(
+        solver.step().unwrap(),
+        Some(vec![("X".into(), "a".into()), ("R".into(), "[a,b]".into())])
+    );
+});
+
+test!(call_and_variables, |solver: SolverFn| {
+    let program = r#"
+        twice(G) --> call(G), call(G).
+        pair(A) --> [A, _].
+        anything(G) --> G.
+        x --> [x].
+    "#;
+
+    let mut solver_1 = solver(program, "phrase(twice(x), [x, x]).");
+    assert_eq!(solver_1.step().unwrap(), Some(vec![]));
+
+    let mut solver_2 = solver(program, "phrase(twice(pair(1)), [1, a, 1, b]).");
+    assert_eq!(solver_2.step().unwrap(), Some(vec![]));
+
+    let mut solver_3 = solver(program, "phrase(twice(x), [x, y]).");
+    assert_eq!(solver_3.step().unwrap(), None);
+
+    let mut solver_4 = solver(program, "phrase(anything([a, b]), L, [c]).");
+    assert_eq!(
+        solver_4.step().unwrap(),
+        Some(vec![("L".into(), "[a,b,c]".into())])
+    );
+
+    let mut solver_5 = solver("eq(X, X).", "call(eq(X), 1), call(eq, Y, 2).");
+    assert_eq!(
+        solver_5.step().unwrap(),
+        Some(vec![("X".into(), "1".into()), ("Y".into(), "2".into())])
+    );
+});
+
+test!(braces, |solver: SolverFn| {
+    let mut solver_1 = solver("", "X = {a, b}, Y = {}.");
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("X".into(), "{a,b}".into()),
+            ("Y".into(), "{}".into())
+        ])
+    );
+
+    // Braces hold a single term, the conjunction of their goals
+    let mut solver_2 = solver("", "X = {a, b, c}, X = {Y}, Y = ','(A, B), X = '{}'(Z).");
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![
+            ("X".into(), "{a,b,c}".into()),
+            ("Y".into(), "','(a,','(b,c))".into()),
+            ("A".into(), "a".into()),
+            ("B".into(), "','(b,c)".into()),
+            ("Z".into(), "','(a,','(b,c))".into()),
+        ])
+    );
+});
+
+test!(phrase_bodies, |solver: SolverFn| {
+    // phrase/2,3 translate their bodies like grammar rules
+    let program = r#"
+        a --> [a].
+        b --> [b].
+        either --> ;(a, b).
+        both --> ','(a, b).
+    "#;
+
+    let query = "phrase({X = 1, Y = 2}, []), phrase(!, []), phrase({}, [x], L1), \
+                 phrase(','(a, \"c\"), L2), phrase(both, L3).";
+    let mut solver_1 = solver(program, query);
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("X".into(), "1".into()),
+            ("Y".into(), "2".into()),
+            ("L1".into(), "[x]".into()),
+            ("L2".into(), "[a,99]".into()),
+            ("L3".into(), "[a,b]".into())
+        ])
+    );
+
+    // Each branch of a disjunction in turn, until a cut commits to one
+    for query in ["phrase(;(a, b), L).", "phrase(either, L)."] {
+        let mut solver_2 = solver(program, query);
+        assert_eq!(
+            solver_2.step().unwrap(),
+            Some(vec![("L".into(), "[a]".into())])
+        );
+        assert_eq!(
+            solver_2.step().unwrap(),
+            Some(vec![("L".into(), "[b]".into())])
+        );
+        assert_eq!(solver_2.step().unwrap(), None);
+    }
+
+    let mut solver_3 = solver(program, "phrase(','(;(a, b), !), L).");
+    assert_eq!(
+        solver_3.step().unwrap(),
+        Some(vec![("L".into(), "[a]".into())])
+    );
+    assert_eq!(solver_3.step().unwrap(), None);
+
+    // Variables are named differently once the heap is collected
+    let errors = [
+        ("phrase(G, []).", "Insufficiently instantiated variable"),
+        ("phrase({G}, []).", "Insufficiently instantiated variable"),
+        ("phrase(1, []).", "Expected a callable, got `1`"),
+    ];
+
+    for (query, error) in errors {
+        let e = solver("", query).step().unwrap_err();
+        assert!(e.error.starts_with(error), "{}", e.error);
+    }
+});
+
+#[test]
+fn invalid_rules() {
+    let errors = [
+        ("a --> b, 1.", "Invalid grammar rule body", 10),
+        ("1 --> b.", "Invalid grammar rule head", 1),
+        ("a, b --> c.", "Pushback must be a list", 4),
+    ];
+
+    for (program, error, column) in errors {
+        let e = Program::new(program).err().unwrap();
+        assert_eq!(e.kind, ErrorKind::Syntax);
+        assert_eq!(e.error, error);
+        assert_eq!(e.location.unwrap().column, column);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::tests::SolverFn;
+use crate::{test, Program, Solver};
+
+test!(exclusive_guards, |solver: SolverFn| {
+    let program = r#"
+        max(X, Y, X) :- X >= Y.
+        max(X, Y, Y) :- X < Y.
+        abs(0, 0).
+        abs(N, N) :- N > 0.
+        abs(N, M) :- N < 0, M is N * -1.
+    "#;
+
+    for (query, solution) in [
+        ("max(3, 5, Z).", "5"),
+        ("max(5, 3, Z).", "5"),
+        ("abs(-4, Z).", "4"),
+        ("abs(4, Z).", "4"),
+        ("abs(0, Z).", "0"),
+    ] {
+        let mut solver = solver(program, query);
+        assert_eq!(
+            solver.next().unwrap().unwrap(),
+            vec![("Z".into(), solution.into())]
+        );
+
+        // The solver committed to the clause without leaving a choice point
+        assert!(solver.choice_points.is_empty(), "{}", query);
+        assert!(solver.next().is_none());
+    }
+});
+
+test!(neck_cut, |solver: SolverFn| {
+    let program = r#"
+        sign(X, pos) :- X > 0, !.
+        sign(X, neg) :- X < 0, !.
+        sign(_, zero).
+    "#;
+
+    let mut solver = solver(program, "sign(-2, S).");
+    assert_eq!(
+        solver.next().unwrap().unwrap(),
+        vec![("S".into(), "neg".into())]
+    );
+    assert!(solver.choice_points.is_empty());
+    assert!(solver.next().is_none());
+});
+
+test!(unbound_arguments, |solver: SolverFn| {
+    let program = r#"
+        p(X, 3) :- X > 0.
+        p(Y, -1) :- Y =< 0.
+        q(X, a) :- X > 0.
+        q(X, b) :- X =< 0.
+        q(_, c).
+    "#;
+
+    // The analysis doesn't apply when arguments aren't bound or are aliased
+    let solutions: Vec<_> = solver(program, "p(A, A).").map(|s| s.unwrap()).collect();
+    assert_eq!(
+        solutions,
+        vec![
+            vec![("A".into(), "3".into())],
+            vec![("A".into(), "-1".into())]
+        ]
+    );
+
+    let solutions: Vec<_> = solver(program, "q(1, Y).").map(|s| s.unwrap()).collect();
+    assert_eq!(
+        solutions,
+        vec![
+            vec![("Y".into(), "a".into())],
+            vec![("Y".into(), "c".into())]
+        ]
+    );
+});
+
+#[test]
+fn dump() {
+    let program = Program::new(
+        r#"
+        max(X, Y, X) :- X >= Y.
+        max(X, Y, Y) :- X < Y.
+        len([], 0).
+        len([_|T], N) :- len(T, M), N is M + 1.
+    "#,
+    )
+    .unwrap();
+
+    let dump = program.database().dump();
+    let clauses: Vec<&str> = dump.lines().filter(|l| !l.starts_with("    ")).collect();
+
+    assert_eq!(
+        clauses,
+        vec![
+            "max/3",
+            "  clause 1: key -, guards 1, commit exclusive (bound: [1,2])",
+            "  clause 2: key -, guards 1, commit exclusive (bound: [])",
+            "len/2",
+            "  clause 1: key [], guards 0, commit no",
+            "  clause 2: key ./2, guards 0, commit no",
+        ]
+    );
+}
+
+test!(switch_on_term, |solver: SolverFn| {
+    let program = r#"
+        p(a, 1).
+        p(X, 2).
+        p(b, 3).
+        p(a, 4).
+        p(f(_), 5).
+        p(-0.0, 6).
+    "#;
+
+    for (goal, solutions) in [
+        ("p(a, N)", vec!["1", "2", "4"]),
+        ("p(c, N)", vec!["2"]),
+        ("p(f(x), N)", vec!["2", "5"]),
+        ("p(0.0, N)", vec!["2", "6"]),
+        ("p(_, N)", vec!["1", "2", "3", "4", "5", "6"]),
+    ] {
+        // Only clauses that can match the first argument leave a choice point
+        let query = format!("{}, statistics(choicepoints, C).", goal);
+        let found: Vec<_> = solver(program, &query).map(|s| s.unwrap()).collect();
+        let expected: Vec<_> = solutions
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let last = i + 1 == solutions.len();
+                vec![
+                    ("N".to_string(), n.to_string()),
+                    ("C".to_string(), if last { "0" } else { "1" }.to_string()),
+                ]
+            })
+            .collect();
+        assert_eq!(found, expected, "{}", goal);
+    }
+});
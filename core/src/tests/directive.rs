@@ -0,0 +1,166 @@
+use crate::tests::SolverFn;
+use crate::{test, Program, Solver};
+
+fn warnings(program: &str) -> Vec<(usize, String)> {
+    Program::new(program)
+        .unwrap()
+        .warnings()
+        .iter()
+        .map(|w| (w.location.as_ref().unwrap().line, w.warning.clone()))
+        .collect()
+}
+
+#[test]
+fn directives_run_in_order() {
+    let program = r#"
+        :- a(1).
+        a(1).
+        :- a(1).
+        :- a(2).
+        :- a(X), b(X).
+        b(X) :- c(X).
+    "#;
+
+    assert_eq!(
+        warnings(program),
+        vec![
+            (7, "Unknown procedure `c/1`".to_string()),
+            (2, "Unknown procedure `a/1`".to_string()),
+            (5, "Goal (directive) failed".to_string()),
+            (6, "Unknown procedure `b/1`".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn directive_error_in_clause() {
+    let program = r#"
+        a :- X is foo + 1, b(X).
+        b(_).
+        :- a.
+    "#;
+
+    let program = Program::new(program).unwrap();
+    let warning = &program.warnings()[0];
+    assert_eq!(warning.warning, "Expected a number, got `foo`");
+
+    let location = warning.location.as_ref().unwrap();
+    assert_eq!(location.predicate.as_deref(), Some("a/0"));
+    assert_eq!((location.line, location.query), (2, false));
+}
+
+#[test]
+fn looping_directives() {
+    // Goals that never finish give up rather than hang while loading
+    let program = r#"
+        loop(N) :- loop(N).
+        :- loop(0).
+        :- initialization(spin).
+        spin :- spin.
+    "#;
+
+    assert_eq!(
+        warnings(program),
+        vec![
+            (2, "Gave up after 1000000 inferences".to_string()),
+            (5, "Gave up after 1000000 inferences".to_string()),
+        ]
+    );
+}
+
+test!(clauses_after_directives, |solver: SolverFn| {
+    // Clauses loaded after a directive join the predicates it saw, so the
+    // first clause can't commit any more
+    let program = r#"
+        p(X, a) :- X > 0.
+        :- p(1, a).
+        p(X, b) :- X > 0.
+        :- dynamic q/0.
+        :- p(1, b).
+    "#;
+
+    let answers: Vec<_> = solver(program, "p(1, Y).")
+        .map(|answer| answer.unwrap())
+        .collect();
+    assert_eq!(
+        answers,
+        vec![
+            vec![("Y".into(), "a".into())],
+            vec![("Y".into(), "b".into())]
+        ]
+    );
+
+    let mut solver = solver(program, "q.");
+    assert_eq!(solver.step().unwrap(), None);
+    assert!(solver.warnings().is_empty());
+});
+
+test!(directive_flags, |solver: SolverFn| {
+    let program = r#"
+        :- set_prolog_flag(unknown, fail).
+        a :- b.
+    "#;
+
+    let mut solver = solver(program, "a.");
+    assert_eq!(solver.step().unwrap(), None);
+});
+
+test!(dynamic_prefix, |solver: SolverFn| {
+    let program = r#"
+        :- dynamic a/1, b/2.
+        :- dynamic c/0.
+        d :- a(_).
+        d :- b(_, _).
+        d :- c.
+        d.
+    "#;
+
+    let mut solver = solver(program, "d.");
+    assert_eq!(solver.step().unwrap(), Some(vec![]));
+    assert_eq!(solver.step().unwrap(), None);
+    assert!(solver.warnings().is_empty());
+});
+
+test!(initialization, |solver: SolverFn| {
+    // Goals run after loading, with `main` last, whatever order they're written in
+    let program = r#"
+        :- initialization(set_prolog_flag(unknown, fail), main).
+        :- initialization(set(warning)).
+        :- initialization(set(error), now).
+
+        set(Value) :- set_prolog_flag(unknown, Value).
+    "#;
+
+    let mut solver = solver(program, "current_prolog_flag(unknown, X).");
+    assert_eq!(
+        solver.step().unwrap(),
+        Some(vec![("X".into(), "fail".into())])
+    );
+
+    let warnings: Vec<&str> = solver
+        .warnings()
+        .iter()
+        .map(|w| w.warning.as_str())
+        .collect();
+    assert_eq!(warnings, vec!["Unknown procedure `set/1`"]);
+});
+
+#[test]
+fn initialization_warnings() {
+    let program = r#"
+        :- initialization(main, main).
+        :- initialization(main, sometime).
+        :- initialization(fail).
+
+        main :- missing.
+    "#;
+
+    assert_eq!(
+        warnings(program),
+        vec![
+            (6, "Unknown procedure `missing/0`".to_string()),
+            (3, "Unsupported initialization `sometime`".to_string()),
+            (4, "Goal (initialization) failed".to_string()),
+        ]
+    );
+}
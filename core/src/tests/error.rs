@@ -1,23 +1,32 @@
-use crate::{Error, ErrorLocation, Solver};
+use crate::{Error, ErrorKind, ErrorLocation, Frame, Program, Solver, Term, TraceEvent};
 
 #[test]
 fn invalid_token() {
     let program = r#"
         a(X) :- b(X).
-        b(X) :- @ c(X).
+        b(X) :- € c(X).
     "#;
 
     if let Err(e) = Solver::new(program, "a(3).") {
         assert_eq!(
-            e,
+            Error { term: None, ..e },
             Error {
-                location: Some(ErrorLocation {
+                location: Some(Box::new(ErrorLocation {
                     offset: 39,
                     line: 3,
                     column: 17,
-                    query: false
-                }),
-                error: "Invalid token".into()
+                    end: 42,
+                    end_line: 3,
+                    end_column: 18,
+                    utf16_offset: 39,
+                    utf16_end: 40,
+                    query: false,
+                    predicate: None,
+                    clause: None,
+                })),
+                culprit: Some("€".into()),
+                expected: None,
+                ..Error::new(ErrorKind::Syntax, "Invalid token".into())
             }
         );
     } else {
@@ -25,6 +34,28 @@ fn invalid_token() {
     }
 }
 
+#[test]
+fn unicode_location() {
+    let e = Solver::new("a :- ville(東京, 😀).", "a.").err().unwrap();
+    assert_eq!(
+        e.location,
+        Some(Box::new(ErrorLocation {
+            offset: 19,
+            line: 1,
+            column: 16,
+            end: 23,
+            end_line: 1,
+            end_column: 17,
+            utf16_offset: 15,
+            utf16_end: 17,
+            query: false,
+            predicate: None,
+            clause: None,
+        }))
+    );
+    assert_eq!(e.culprit, Some("😀".into()));
+}
+
 #[test]
 fn unexpected_token() {
     let program = r#"
@@ -34,15 +65,27 @@ fn unexpected_token() {
 
     if let Err(e) = Solver::new(program, "a(3).") {
         assert_eq!(
-            e,
+            Error { term: None, ..e },
             Error {
-                location: Some(ErrorLocation {
+                location: Some(Box::new(ErrorLocation {
                     offset: 45,
                     line: 3,
                     column: 23,
+                    end: 46,
+                    end_line: 3,
+                    end_column: 24,
+                    utf16_offset: 45,
+                    utf16_end: 46,
                     query: false,
-                }),
-                error: "Unexpected token `c`".into()
+                    predicate: None,
+                    clause: None,
+                })),
+                culprit: Some("c".into()),
+                expected: Some("`(`, `,`, `.` or an operator".into()),
+                ..Error::new(
+                    ErrorKind::Syntax,
+                    "Unexpected token `c`, expected `(`, `,`, `.` or an operator".into()
+                )
             }
         );
     } else {
@@ -59,15 +102,27 @@ fn unexpected_eof() {
 
     if let Err(e) = Solver::new(program, "a(3).") {
         assert_eq!(
-            e,
+            Error { term: None, ..e },
             Error {
-                location: Some(ErrorLocation {
+                location: Some(Box::new(ErrorLocation {
                     offset: 35,
                     line: 3,
                     column: 13,
+                    end: 35,
+                    end_line: 3,
+                    end_column: 13,
+                    utf16_offset: 35,
+                    utf16_end: 35,
                     query: false,
-                }),
-                error: "Unexpected end of file, did you forget a '.'?".into()
+                    predicate: None,
+                    clause: None,
+                })),
+                culprit: None,
+                expected: Some("`,`, `-->`, `.`, `:-` or an operator".into()),
+                ..Error::new(
+                    ErrorKind::Syntax,
+                    "Unexpected end of file, did you forget a '.'?".into()
+                )
             }
         );
     } else {
@@ -79,18 +134,365 @@ fn unexpected_eof() {
 fn invalid_query() {
     if let Err(e) = Solver::new("", "a") {
         assert_eq!(
-            e,
+            Error { term: None, ..e },
             Error {
-                location: Some(ErrorLocation {
+                location: Some(Box::new(ErrorLocation {
                     offset: 1,
                     line: 1,
                     column: 2,
-                    query: true
-                }),
-                error: "Unexpected end of file, did you forget a '.'?".to_string()
+                    end: 1,
+                    end_line: 1,
+                    end_column: 2,
+                    utf16_offset: 1,
+                    utf16_end: 1,
+                    query: true,
+                    predicate: None,
+                    clause: None,
+                })),
+                culprit: None,
+                expected: Some("`(`, `,`, `.` or an operator".into()),
+                ..Error::new(
+                    ErrorKind::Syntax,
+                    "Unexpected end of file, did you forget a '.'?".to_string()
+                )
             }
         );
     } else {
         panic!("Expected an error");
     }
 }
+
+#[test]
+fn runtime_error_location() {
+    let program = r#"
+        a(X) :- b(X).
+        b(X) :- X > 0.
+        b(X) :-
+            Y is X + 1,
+            call(Z > Y).
+    "#;
+
+    let mut solver = Solver::new(program, "a(0).").unwrap();
+    let e = solver.next().unwrap().unwrap_err();
+
+    // The unbound `Z` is named by where it is on the heap
+    let var = e
+        .error
+        .strip_prefix("Insufficiently instantiated variable `_")
+        .and_then(|rest| rest.strip_suffix('`'));
+    assert!(var.is_some_and(|var| var.parse::<usize>().is_ok()));
+
+    assert_eq!(
+        Error {
+            error: String::new(),
+            backtrace: vec![],
+            term: None,
+            ..e
+        },
+        Error {
+            location: Some(Box::new(ErrorLocation {
+                offset: 98,
+                line: 6,
+                column: 13,
+                end: 109,
+                end_line: 6,
+                end_column: 24,
+                utf16_offset: 98,
+                utf16_end: 109,
+                query: false,
+                predicate: Some("b/1".into()),
+                clause: Some(2),
+            })),
+            ..Error::new(ErrorKind::Instantiation, String::new())
+        }
+    );
+}
+
+#[test]
+fn term_spans() {
+    use crate::ast::{Span, Term};
+
+    let query = Program::parse_query("foo(X, [a|T], -1 + g(\"ab\")).").unwrap();
+    let Term::Compound(_, args, span) = &query.0[0] else {
+        panic!("Expected a compound term");
+    };
+    assert_eq!(*span, Span(0, 27));
+    assert_eq!(
+        args.iter().map(Term::span).collect::<Vec<_>>(),
+        vec![Span(4, 5), Span(7, 12), Span(14, 26)]
+    );
+
+    // Terms inside operators and list cells have their own spans
+    let [_, Term::Compound(_, list, _), Term::Compound(_, sum, _)] = &args[..] else {
+        panic!("Expected a list and a sum");
+    };
+    assert_eq!(
+        list.iter().map(Term::span).collect::<Vec<_>>(),
+        vec![Span(8, 9), Span(10, 11)]
+    );
+    assert_eq!(
+        sum.iter().map(Term::span).collect::<Vec<_>>(),
+        vec![Span(14, 16), Span(19, 26)]
+    );
+}
+
+#[test]
+fn runtime_error_in_query() {
+    let mut solver = Solver::new("a(1).", "a(X),\n  Y > X.").unwrap();
+    assert_eq!(
+        solver.next().unwrap().err().unwrap().location,
+        Some(Box::new(ErrorLocation {
+            offset: 8,
+            line: 2,
+            column: 3,
+            end: 13,
+            end_line: 2,
+            end_column: 8,
+            utf16_offset: 8,
+            utf16_end: 13,
+            query: true,
+            predicate: None,
+            clause: None,
+        }))
+    );
+}
+
+#[test]
+fn multiple_errors() {
+    let program = r#"
+        a(X) :- b(X) c.
+        b(1).
+        b(X) :- .
+        c(X) :- X > 0
+    "#;
+
+    let errors: Vec<(usize, String)> = Program::parse_all(program)
+        .err()
+        .unwrap()
+        .into_iter()
+        .map(|e| (e.location.unwrap().line, e.error))
+        .collect();
+
+    assert_eq!(
+        errors,
+        vec![
+            (
+                2,
+                "Unexpected token `c`, expected `,`, `.` or an operator".to_string()
+            ),
+            (4, "Unexpected token `.`, expected a term".to_string()),
+            (
+                5,
+                "Unexpected end of file, did you forget a '.'?".to_string()
+            ),
+        ]
+    );
+
+    assert_eq!(Program::new(program).err().unwrap().error, errors[0].1);
+}
+
+static BACKTRACE: &str = r#"
+    p(X) :- q(X, Y), r(Y).
+    q(X, Y) :- s(X, Y), r(Y).
+    q(_, 0).
+    s(X, Y) :- Y is X + Z, r(Z).
+    r(_).
+"#;
+
+// The goal with its variables unnamed, as their names depend on the heap, and
+// the line, predicate and clause it was called from
+type FrameSummary<'a> = (String, usize, Option<&'a str>, Option<usize>);
+
+fn frames(backtrace: &[Frame]) -> Vec<FrameSummary<'_>> {
+    let unnamed = |goal: &str| {
+        let mut text = String::new();
+        for c in goal.chars() {
+            if !(c.is_ascii_digit() && text.ends_with('_')) {
+                text.push(c);
+            }
+        }
+        text
+    };
+
+    backtrace
+        .iter()
+        .map(|frame| {
+            let location = frame.location.as_ref().unwrap();
+            (
+                unnamed(&frame.goal),
+                location.line,
+                location.predicate.as_deref(),
+                location.clause,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn backtrace() {
+    let mut solver = Solver::new(BACKTRACE, "p(1), r(2).").unwrap();
+    let e = solver.next().unwrap().unwrap_err();
+
+    // Each goal is known from the goals after it in the same body, even in
+    // clauses without alternatives
+    assert_eq!(
+        frames(&e.backtrace),
+        vec![
+            ("is(_,+(1,_))".into(), 5, Some("s/2"), Some(1)),
+            ("s(1,_)".into(), 3, Some("q/2"), Some(1)),
+            ("q(1,_)".into(), 2, Some("p/1"), Some(1)),
+            ("p(1)".into(), 1, None, None),
+        ]
+    );
+}
+
+#[test]
+fn backtrace_with_tracing() {
+    let mut solver = Solver::new(BACKTRACE, "p(1), r(2).").unwrap();
+    // Ports keep the goal of every running clause for its exit event
+    solver.set_tracer(|_: &TraceEvent| {});
+    let e = solver.next().unwrap().unwrap_err();
+
+    assert_eq!(
+        frames(&e.backtrace),
+        vec![
+            ("is(_,+(1,_))".into(), 5, Some("s/2"), Some(1)),
+            ("s(1,_)".into(), 3, Some("q/2"), Some(1)),
+            ("q(1,_)".into(), 2, Some("p/1"), Some(1)),
+            ("p(1)".into(), 1, None, None),
+        ]
+    );
+}
+
+#[test]
+fn backtrace_depth() {
+    let mut solver = Solver::new("p(X) :- X > 0.", "p(f(g(h(i(j))))).").unwrap();
+    let e = solver.next().unwrap().unwrap_err();
+    assert_eq!(e.backtrace[0].goal, ">(f(g(h(...))),0)");
+
+    let mut solver = Solver::new("", "X = [1, 2, 3, 4, 5, 6], Y is X.").unwrap();
+    let e = solver.next().unwrap().unwrap_err();
+    assert_eq!(e.backtrace[0].goal, "is(_32,[1,2,3,4|...])");
+}
+
+#[test]
+fn error_terms() {
+    let cases = [
+        (
+            "X is foo + 1.",
+            ErrorKind::Type,
+            "error(type_error(evaluable,foo/0),is/2)",
+        ),
+        (
+            "X is 1 // 0.",
+            ErrorKind::Evaluation,
+            "error(evaluation_error(zero_divisor),is/2)",
+        ),
+        (
+            "X > 1.",
+            ErrorKind::Instantiation,
+            "error(instantiation_error,>/2)",
+        ),
+        (
+            "spy(1).",
+            ErrorKind::Type,
+            "error(type_error(predicate_indicator,1),spy/1)",
+        ),
+        (
+            "set_prolog_flag(colour, red).",
+            ErrorKind::Domain,
+            "error(domain_error(prolog_flag,colour),set_prolog_flag/2)",
+        ),
+        (
+            "missing(1).",
+            ErrorKind::Existence,
+            "error(existence_error(procedure,missing/1),missing/1)",
+        ),
+        (
+            "call(1).",
+            ErrorKind::Type,
+            "error(type_error(callable,1),[])",
+        ),
+        (
+            "X = 1.5, X.",
+            ErrorKind::Type,
+            "error(type_error(callable,1.5),[])",
+        ),
+        (
+            "call(X).",
+            ErrorKind::Instantiation,
+            "error(instantiation_error,[])",
+        ),
+        (
+            "trace, X = 2, X.",
+            ErrorKind::Type,
+            "error(type_error(callable,2),[])",
+        ),
+    ];
+
+    for (query, kind, text) in cases {
+        let e = Solver::new("", query).unwrap().next().unwrap().unwrap_err();
+        assert_eq!(e.kind, kind, "{}", query);
+        assert_eq!(e.term.unwrap().text, text, "{}", query);
+    }
+}
+
+#[test]
+fn error_culprit() {
+    let mut solver = Solver::new("", "X is foo(1, 2, 3).").unwrap();
+    let e = solver.next().unwrap().unwrap_err();
+
+    assert_eq!(e.culprit.as_deref(), Some("foo/3"));
+    assert_eq!(e.expected.as_deref(), Some("evaluable"));
+    assert_eq!(
+        e.term.unwrap().term,
+        Term::compound(
+            "error",
+            vec![
+                Term::compound(
+                    "type_error",
+                    vec![Term::atom("evaluable"), Term::indicator("foo", 3)]
+                ),
+                Term::indicator("is", 2),
+            ]
+        )
+    );
+}
+
+#[test]
+fn syntax_error_term() {
+    let e = Program::new("a :- b c.").err().unwrap();
+    assert_eq!(e.kind, ErrorKind::Syntax);
+    assert_eq!(
+        e.term.unwrap().text,
+        "error(syntax_error('Unexpected token `c`, expected `(`, `,`, `.` or an operator'),1:8)"
+    );
+}
+
+#[test]
+fn undefined_escape() {
+    let e = Program::new("a('x').\nb('\\q').").err().unwrap();
+    assert_eq!(e.kind, ErrorKind::Syntax);
+    assert_eq!(e.error, "Undefined escape sequence");
+
+    let location = e.location.unwrap();
+    assert_eq!((location.line, location.column), (2, 3));
+    assert_eq!((location.end_line, location.end_column), (2, 7));
+}
+
+#[test]
+fn malformed_numbers() {
+    let errors = [
+        ("a(0b102).", "Invalid digit for the radix", 3),
+        ("a(99999999999999999999).", "Integer overflow", 3),
+        ("a(1.0e999).", "Float overflow", 3),
+        ("a(8'9).", "Invalid digit for the radix", 3),
+    ];
+
+    for (program, error, column) in errors {
+        let e = Program::new(program).err().unwrap();
+        assert_eq!(e.kind, ErrorKind::Syntax);
+        assert_eq!(e.error, error);
+        assert_eq!(e.location.unwrap().column, column);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::tests::SolverFn;
+use crate::{test, Solver};
+
+test!(unknown_procedure, |solver: SolverFn| {
+    let program = r#"
+        a(X) :- b(X).
+        b(1).
+        b(X) :- c(X, 2).
+    "#;
+
+    let mut solver = solver(program, "a(X).");
+    assert_eq!(solver.step().unwrap(), Some(vec![("X".into(), "1".into())]));
+
+    let error = solver.step().unwrap_err();
+    assert_eq!(error.error, "Unknown procedure `c/2`");
+
+    let location = error.location.unwrap();
+    assert_eq!(location.predicate.as_deref(), Some("b/1"));
+    assert_eq!((location.line, location.clause), (4, Some(2)));
+});
+
+test!(unknown_flag, |solver: SolverFn| {
+    let program = "a(1).";
+
+    let mut solver_1 = solver(program, "set_prolog_flag(unknown, fail), a(X), b(X).");
+    assert_eq!(solver_1.step().unwrap(), None);
+
+    // Calls the lint can't see are warned about when they run
+    let mut solver_2 = solver(
+        program,
+        "set_prolog_flag(unknown, warning), a(X), G = b(X), call(G).",
+    );
+    assert_eq!(solver_2.step().unwrap(), None);
+
+    let warnings: Vec<&str> = solver_2
+        .warnings()
+        .iter()
+        .map(|w| w.warning.as_str())
+        .collect();
+    assert_eq!(warnings, vec!["Unknown procedure `b/1`"]);
+    assert!(solver_2.warnings()[0].location.as_ref().unwrap().query);
+
+    let mut solver_3 = solver(
+        program,
+        "current_prolog_flag(unknown, A), set_prolog_flag(unknown, fail), \
+         current_prolog_flag(unknown, B).",
+    );
+    assert_eq!(
+        solver_3.step().unwrap(),
+        Some(vec![
+            ("A".into(), "error".into()),
+            ("B".into(), "fail".into())
+        ])
+    );
+
+    let mut solver_4 = solver(program, "set_prolog_flag(unknown, maybe).");
+    assert_eq!(
+        solver_4.step().unwrap_err().error,
+        "Invalid value `maybe` for Prolog flag `unknown`"
+    );
+
+    let mut solver_5 = solver(program, "set_prolog_flag(unheard_of, fail).");
+    assert_eq!(
+        solver_5.step().unwrap_err().error,
+        "Unknown Prolog flag `unheard_of`"
+    );
+});
+
+test!(double_quotes, |solver: SolverFn| {
+    let program = r#"
+        a("ab").
+        :- set_prolog_flag(double_quotes, codes).
+        b("ab").
+        :- set_prolog_flag(double_quotes, chars).
+        c("ab").
+        :- set_prolog_flag(double_quotes, atom).
+        d("ab").
+    "#;
+
+    let mut solver_1 = solver(program, "a(A), string(A), b(B), c(C), d(D), atom(D).");
+    assert_eq!(
+        solver_1.step().unwrap(),
+        Some(vec![
+            ("A".into(), r#""ab""#.into()),
+            ("B".into(), "[97,98]".into()),
+            ("C".into(), "[a,b]".into()),
+            ("D".into(), "ab".into())
+        ])
+    );
+    assert!(solver_1.warnings().is_empty());
+
+    // Queries read with the flags the program ends with
+    let mut solver_2 = solver(
+        program,
+        r#"X = "ab", d(X), current_prolog_flag(double_quotes, F)."#,
+    );
+    assert_eq!(
+        solver_2.step().unwrap(),
+        Some(vec![("X".into(), "ab".into()), ("F".into(), "atom".into())])
+    );
+
+    // Setting the flag in a query doesn't change how the query was read
+    let mut solver_3 = solver(
+        "",
+        r#"set_prolog_flag(double_quotes, codes), current_prolog_flag(double_quotes, F), X = "a"."#,
+    );
+    assert_eq!(
+        solver_3.step().unwrap(),
+        Some(vec![
+            ("F".into(), "codes".into()),
+            ("X".into(), r#""a""#.into())
+        ])
+    );
+});
+
+test!(dynamic, |solver: SolverFn| {
+    let program = r#"
+        :- dynamic(counter/1).
+        :- dynamic([seen/2, visited/0]).
+
+        a :- counter(_).
+        a :- seen(_, _).
+        a :- visited.
+        a.
+    "#;
+
+    let mut solver = solver(program, "a.");
+    assert_eq!(solver.step().unwrap(), Some(vec![]));
+    assert_eq!(solver.step().unwrap(), None);
+});
+
+#[test]
+fn dynamic_warnings() {
+    let solver = Solver::new(":- dynamic(a/1).\n:- foo(bar).\nb :- a(_).", "b.").unwrap();
+
+    let warnings: Vec<&str> = solver
+        .warnings()
+        .iter()
+        .map(|w| w.warning.as_str())
+        .collect();
+    assert_eq!(warnings, vec!["Unknown procedure `foo/1`"]);
+}
@@ -1,6 +1,8 @@
 use crate::gc::GarbageCollector;
-use crate::Solver;
+use crate::{GCConfig, GCRecord, Solver};
 
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::Duration;
 
 fn test_constant_memory(program: &str, query: &str) {
@@ -42,6 +44,31 @@ fn run_for(solver: &mut Solver, time: Duration) {
     solver.next();
 }
 
+// Incremental collections keep precisely what full ones would, so the memory
+// used after each one stays the same
+fn test_constant_memory_incremental(program: &str, query: &str) {
+    let records = Rc::new(RefCell::new(Vec::new()));
+    let sink = records.clone();
+
+    let mut solver = Solver::new(program, query).unwrap();
+    solver.configure_gc(GCConfig {
+        mark_budget: Some(64),
+        ..GCConfig::default()
+    });
+    solver.set_gc_sink(move |record: &GCRecord| sink.borrow_mut().push(record.cells_after));
+
+    run_for(&mut solver, Duration::from_secs(2));
+
+    let records = records.borrow();
+    assert!(records.len() > 20);
+    let (first, last) = records.split_at(records.len() / 2);
+    assert!(
+        last.iter().max() <= first.iter().max(),
+        "Memory usage increased: {:?}",
+        records
+    );
+}
+
 // Wielemaker and Neumerkel, Precise Garbage Collection in Prolog (2008)
 #[test]
 #[ignore = "Long in debug"]
@@ -97,3 +124,172 @@ fn precise_gc_4() {
         "run.",
     );
 }
+
+#[test]
+#[ignore = "Long in debug"]
+fn precise_gc_incremental() {
+    test_constant_memory_incremental(
+        r#"
+            run :- run(_).
+            run(X) :- freeze(X, dummy(X)), X = 1, run(T).
+            dummy(_).
+        "#,
+        "run.",
+    );
+    test_constant_memory_incremental(
+        r#"
+            run :- run(_).
+            run(X) :- f(X).
+            run(X) :- X == [].
+            f([f|X]) :- f(X).
+        "#,
+        "run.",
+    );
+}
+
+#[test]
+fn atom_gc() {
+    let mut solver = Solver::new_with_gc("p(a).", "p(Foo), Bar = local_atom.").unwrap();
+    let shared = solver.heap.string_map.shared.len();
+
+    assert_eq!(
+        solver.next().unwrap().unwrap(),
+        vec![
+            ("Foo".into(), "a".into()),
+            ("Bar".into(), "local_atom".into())
+        ]
+    );
+
+    let unused = solver.heap.string_map.alloc("unused");
+    assert_eq!(solver.heap.string_map.local_count(), 4);
+
+    GarbageCollector::run(&mut solver);
+    GarbageCollector::collect_atoms(&mut solver);
+
+    // Only the atom that is still on the heap survives, the variable names don't
+    let string_map = &mut solver.heap.string_map;
+    assert_eq!(string_map.local_count(), 1);
+    assert_eq!(string_map.get(unused), None);
+    assert_eq!(string_map.get(crate::stringmap::str::IS), Some("is"));
+    assert_eq!(string_map.shared.len(), shared);
+    assert_eq!(solver.gc.collected_atoms(), 3);
+
+    // Freed ids are reused
+    let reused = string_map.alloc("reused");
+    assert!(reused < unused);
+    assert_eq!(string_map.get(reused), Some("reused"));
+    assert_eq!(string_map.alloc("local_atom"), shared + 2);
+
+    let snapshot = Solver::restore(&solver.snapshot()).unwrap();
+    assert_eq!(snapshot.heap.string_map.get(reused), Some("reused"));
+    assert_eq!(snapshot.heap.string_map.local_count(), 2);
+}
+
+#[test]
+fn atom_gc_threshold() {
+    let mut solver = Solver::new_with_gc("", "pending.").unwrap();
+
+    for i in 0..2000 {
+        solver.heap.string_map.alloc(&format!("atom_{}", i));
+    }
+
+    // The query's `pending` is still to be run
+    GarbageCollector::run(&mut solver);
+    assert_eq!(solver.heap.string_map.local_count(), 1);
+    assert_eq!(solver.gc.collected_atoms(), 2000);
+}
+
+static GARBAGE: &str = r#"
+    garbage(0) :- !.
+    garbage(N) :- _ = f(N, N), M is N - 1, garbage(M).
+"#;
+
+#[test]
+fn garbage_collect() {
+    let mut solver = Solver::new(
+        GARBAGE,
+        "garbage(100), garbage_collect, statistics(gc, N), statistics(gc_last, [B, A, _, C, _]).",
+    )
+    .unwrap();
+
+    let solution = solver.next().unwrap().unwrap();
+    assert_eq!(solution[0], ("N".into(), "1".into()));
+    assert_eq!(solution[3], ("C".into(), "0".into()));
+
+    let record = *solver.last_gc().unwrap();
+    assert!(record.full);
+    assert!(record.cells_after < record.cells_before);
+    assert_eq!(solution[1].1, record.cells_before.to_string());
+    assert_eq!(solution[2].1, record.cells_after.to_string());
+}
+
+#[test]
+fn gc_config() {
+    let mut solver = Solver::new(GARBAGE, "garbage(1000).").unwrap();
+    assert!(solver.last_gc().is_none());
+
+    let config = GCConfig {
+        heap_size_threshold: 64,
+        heap_pressure_threshold: 0.0,
+        cooldown: 4,
+        mark_budget: None,
+    };
+    solver.configure_gc(config);
+    assert_eq!(solver.gc_config(), config);
+
+    let records = Rc::new(RefCell::new(Vec::new()));
+    let sink = records.clone();
+    solver.set_gc_sink(move |record: &GCRecord| sink.borrow_mut().push(*record));
+
+    assert!(solver.next().unwrap().is_ok());
+
+    let records = records.borrow();
+    assert_eq!(records.len(), solver.gc.runs());
+    assert!(records.len() > 10);
+    assert!(records
+        .iter()
+        .all(|r| !r.full && r.cells_after <= r.cells_before));
+    assert_eq!(
+        records
+            .iter()
+            .map(|r| r.cells_before - r.cells_after)
+            .sum::<usize>(),
+        solver.gc.collected()
+    );
+    assert_eq!(solver.last_gc(), records.last());
+}
+
+#[test]
+fn incremental_gc() {
+    let mut solver = Solver::new(GARBAGE, "garbage(2000), statistics(gc_max_pause, P).").unwrap();
+    solver.configure_gc(GCConfig {
+        heap_size_threshold: 256,
+        heap_pressure_threshold: 0.0,
+        cooldown: 0,
+        mark_budget: Some(16),
+    });
+
+    let records = Rc::new(RefCell::new(Vec::new()));
+    let sink = records.clone();
+    solver.set_gc_sink(move |record: &GCRecord| sink.borrow_mut().push(*record));
+
+    assert!(solver.next().unwrap().is_ok());
+
+    let records = records.borrow();
+    assert!(records.len() > 10);
+    assert!(records
+        .iter()
+        .all(|r| r.slices > 1 && r.max_pause <= r.time));
+    assert!(solver.heap.len() < 1024);
+    assert_eq!(
+        solver.gc.max_pause(),
+        records.iter().map(|r| r.max_pause).fold(0.0, f64::max)
+    );
+
+    // A forced collection doesn't wait for marking to finish
+    let mut solver = crate::tests::incremental_gc(GARBAGE, "garbage(100), garbage_collect.");
+    assert!(solver.next().unwrap().is_ok());
+    solver.next();
+    assert!(solver.last_gc().unwrap().full);
+    assert_eq!(solver.last_gc().unwrap().slices, 1);
+}
@@ -0,0 +1,54 @@
+use crate::cell::Cell;
+use crate::gc::GarbageCollector;
+use crate::tests::SolverFn;
+use crate::{test, HeapTerm, Solver};
+
+#[test]
+fn cell_size() {
+    assert_eq!(std::mem::size_of::<Cell>(), 8);
+}
+
+test!(boxed_numbers, |solver: SolverFn| {
+    let program = "p(f(1.5, 9223372036854775807, -576460752303423489, 576460752303423487)).";
+    let query = "p(f(A, B, C, D)), X is A * 2, Y is D + 1, p(Z).";
+
+    let mut solver = solver(program, query);
+    assert_eq!(
+        solver.next().unwrap().unwrap(),
+        vec![
+            ("A".into(), "1.5".into()),
+            ("B".into(), "9223372036854775807".into()),
+            ("C".into(), "-576460752303423489".into()),
+            ("D".into(), "576460752303423487".into()),
+            ("X".into(), "3.0".into()),
+            ("Y".into(), "576460752303423488".into()),
+            (
+                "Z".into(),
+                "f(1.5,9223372036854775807,-576460752303423489,576460752303423487)".into()
+            ),
+        ]
+    );
+});
+
+#[test]
+fn boxed_numbers_gc() {
+    let mut solver = Solver::new_with_gc(
+        "p(X, Y) :- X is 0.5 + 0.25, Y is 1 << 62.",
+        "p(X, Y), freeze(Z, Z = 1).",
+    )
+    .unwrap();
+
+    assert!(solver.next().unwrap().is_ok());
+    GarbageCollector::run(&mut solver);
+
+    assert!(solver.heap.has_valid_cells());
+    assert_eq!(solver.heap.attributes.len(), 1);
+    assert!(matches!(
+        solver.heap.get(solver.var_map[2].1),
+        HeapTerm::Var(_)
+    ));
+
+    let solution = solver.heap.serialize(&solver.var_map);
+    assert_eq!(solution[0], ("X".into(), "0.75".into()));
+    assert_eq!(solution[1], ("Y".into(), "4611686018427387904".into()));
+}
@@ -1,13 +1,39 @@
 mod builtins;
 mod core;
+mod cyclic;
+mod dcg;
+mod determinism;
+mod directive;
 mod error;
+mod flags;
 mod gc;
+mod heap;
 mod lco;
+mod profile;
+mod program;
+mod snapshot;
+mod trace;
+mod verify;
+mod warnings;
 
-use crate::Solver;
+use crate::{GCConfig, Solver};
 
 pub type SolverFn = fn(program: &str, query: &str) -> Solver;
 
+// Collects incrementally in small slices as often as possible, verifying the heap
+// after every collection
+pub fn incremental_gc(program: &str, query: &str) -> Solver {
+    let mut solver = Solver::new(program, query).unwrap();
+    solver.configure_gc(GCConfig {
+        heap_size_threshold: 0,
+        heap_pressure_threshold: 0.0,
+        cooldown: 0,
+        mark_budget: Some(4),
+    });
+    solver.enable_verifier(None);
+    solver
+}
+
 #[macro_export]
 macro_rules! test {
     ($name:ident, $fn:expr) => {
@@ -15,6 +41,7 @@ macro_rules! test {
         fn $name() {
             $fn(|program, query| Solver::new(program, query).unwrap());
             $fn(|program, query| Solver::new_with_gc(program, query).unwrap());
+            $fn($crate::tests::incremental_gc);
         }
     };
 
@@ -24,6 +51,7 @@ macro_rules! test {
         fn $name() {
             $fn(|program, query| Solver::new(program, query).unwrap());
             $fn(|program, query| Solver::new_with_gc(program, query).unwrap());
+            $fn($crate::tests::incremental_gc);
         }
     };
 }
@@ -0,0 +1,43 @@
+use crate::tests::SolverFn;
+use crate::{test, ProfileEntry, Solver};
+
+test!(profile, |solver: SolverFn| {
+    let program = r#"
+        a(1).
+        a(2).
+        a(3).
+        b(X) :- a(X), X > 1.
+    "#;
+
+    let mut solver = solver(program, "b(X).");
+    solver.enable_profiler();
+
+    assert_eq!(solver.step().unwrap(), Some(vec![("X".into(), "2".into())]));
+    assert_eq!(solver.step().unwrap(), Some(vec![("X".into(), "3".into())]));
+    assert_eq!(solver.step().unwrap(), None);
+
+    let profile = solver.profile().unwrap();
+    let entry = |name: &str| -> ProfileEntry {
+        profile
+            .iter()
+            .find(|entry| entry.predicate == name)
+            .unwrap()
+            .clone()
+    };
+
+    let a = entry("a/1");
+    assert_eq!((a.calls, a.redos, a.exits, a.fails), (1, 2, 3, 0));
+
+    let b = entry("b/1");
+    assert_eq!((b.calls, b.redos, b.exits, b.fails), (1, 0, 2, 0));
+
+    let gt = entry(">/2");
+    assert_eq!((gt.calls, gt.exits, gt.fails), (3, 2, 1));
+});
+
+#[test]
+fn profile_disabled() {
+    let mut solver = Solver::new("a.", "a.").unwrap();
+    assert!(solver.next().is_some());
+    assert!(solver.profile().is_none());
+}
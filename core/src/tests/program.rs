@@ -0,0 +1,208 @@
+use crate::{Program, Solver};
+
+use std::rc::Rc;
+
+#[test]
+fn program_queries() {
+    let program = Program::new(
+        r#"
+        parent(tom, bob).
+        parent(bob, ann).
+        parent(bob, pat).
+        grandparent(X, Z) :- parent(X, Y), parent(Y, Z).
+    "#,
+    )
+    .unwrap();
+
+    let mut a = program.query("grandparent(tom, X).").unwrap();
+    let mut b = program.query_with_gc("parent(bob, X).").unwrap();
+
+    // Solvers for the same program can be interleaved
+    assert_eq!(a.step().unwrap(), Some(vec![("X".into(), "ann".into())]));
+    assert_eq!(b.step().unwrap(), Some(vec![("X".into(), "ann".into())]));
+    assert_eq!(b.step().unwrap(), Some(vec![("X".into(), "pat".into())]));
+    assert_eq!(a.step().unwrap(), Some(vec![("X".into(), "pat".into())]));
+    assert_eq!(a.step().unwrap(), None);
+    assert_eq!(b.step().unwrap(), None);
+
+    let solutions: Vec<_> = program
+        .query("parent(X, Y).")
+        .unwrap()
+        .map(|s| s.unwrap())
+        .collect();
+    assert_eq!(solutions.len(), 3);
+
+    assert_eq!(program.database().predicates(), 2);
+    assert_eq!(program.database().clauses(), 4);
+}
+
+#[test]
+fn program_shared() {
+    let program = Program::new("a(x).").unwrap();
+
+    let a = program.query("a(new_atom).").unwrap();
+    let b = program.query("a(x).").unwrap();
+
+    // Code and the program's atoms are shared, atoms created by a query are not
+    assert!(Rc::ptr_eq(&a.program, &b.program));
+    assert!(Rc::ptr_eq(
+        &a.heap.string_map.shared,
+        &b.heap.string_map.shared
+    ));
+    assert_eq!(a.heap.string_map.len(), b.heap.string_map.len() + 1);
+}
+
+#[test]
+fn program_errors() {
+    assert!(Program::new("a(.").is_err());
+
+    let program = Program::new("a.").unwrap();
+    let error = program.query("a(").err().unwrap();
+    assert!(error.location.unwrap().query);
+
+    // Parse errors in the program are reported before the query
+    let error = Solver::new("a(.", "b(").err().unwrap();
+    assert!(!error.location.unwrap().query);
+}
+
+#[test]
+fn program_image() {
+    let program = Program::new(
+        r#"
+        len([], 0).
+        len([_|T], N) :- len(T, M), N is M + 1.
+        greet(F) :- F = hello(world, "quoted").
+    "#,
+    )
+    .unwrap();
+
+    let image = program.to_bytes();
+    let loaded = Program::from_bytes(&image).unwrap();
+    assert_eq!(loaded.to_bytes(), image);
+
+    let mut solver = loaded.query("len([a, b, c], N), greet(F).").unwrap();
+    assert_eq!(
+        solver.step().unwrap(),
+        Some(vec![
+            ("N".into(), "3".into()),
+            ("F".into(), "hello(world,\"quoted\")".into())
+        ])
+    );
+    assert_eq!(solver.step().unwrap(), None);
+}
+
+#[test]
+fn program_image_invalid() {
+    let image = Program::new("a(X) :- b(X, f(X)).").unwrap().to_bytes();
+    let error = |bytes: &[u8]| Program::from_bytes(bytes).err().unwrap().error;
+
+    assert_eq!(
+        error(b"a."),
+        "Invalid compiled program: not a compiled program"
+    );
+    assert_eq!(
+        error(&Solver::new("a.", "a.").unwrap().snapshot()),
+        "Invalid compiled program: not a compiled program"
+    );
+
+    let mut version = image.clone();
+    version[4] = 0;
+    assert_eq!(
+        error(&version),
+        "Invalid compiled program: unsupported version 0 (expected 7)"
+    );
+
+    let mut corrupt = image.clone();
+    corrupt[image.len() / 2] ^= 1;
+    assert_eq!(
+        error(&corrupt),
+        "Invalid compiled program: checksum mismatch"
+    );
+
+    assert!(Program::from_bytes(&image[..image.len() - 1]).is_err());
+
+    // Truncated or with a corrupt length, but with a valid checksum
+    let body = &image[..image.len() - 8];
+    let mut truncated = body[..body.len() / 2].to_vec();
+    crate::encoding::seal(&mut truncated, 8);
+    assert!(Program::from_bytes(&truncated).is_err());
+
+    for i in [8, 20, 40] {
+        let mut length = body.to_vec();
+        length[i] ^= 0x40;
+        crate::encoding::seal(&mut length, 8);
+        assert!(Program::from_bytes(&length).is_err());
+    }
+}
+
+#[test]
+fn program_image_validation() {
+    use crate::vm::Instruction;
+    use crate::Atom;
+
+    let program = Program::new("a(X) :- b(X, f(X)).").unwrap();
+    let mut database = crate::program::Database::read(&mut &program.to_bytes()[8..]).unwrap();
+    assert_eq!(database.validate(), Ok(()));
+
+    database.code[0] = Instruction::GetConstant(Atom::String(usize::MAX), 0);
+    assert_eq!(
+        database.validate(),
+        Err("clause 1 of a/1 is invalid".into())
+    );
+
+    database.code[0] = Instruction::UnifyVoid;
+    assert_eq!(
+        database.validate(),
+        Err("clause 1 of a/1 is invalid".into())
+    );
+}
+
+#[test]
+fn program_image_dataflow() {
+    use crate::encoding::checksum;
+    use crate::program::Database;
+    use crate::vm::Instruction;
+
+    let program = Program::new("a(X) :- X > 1, b(X, f(X)).").unwrap();
+    let image = program.to_bytes();
+
+    // Rebuilds the image with the code changed, so that its checksum is valid
+    let craft = |change: fn(&mut Database)| {
+        let mut database = Database::read(&mut &image[8..]).unwrap();
+        change(&mut database);
+
+        let mut crafted = image[..8].to_vec();
+        database.write(&mut crafted).unwrap();
+        crafted.extend(checksum(&crafted[8..]).to_le_bytes());
+        Program::from_bytes(&crafted).err().map(|e| e.error)
+    };
+
+    assert_eq!(craft(|_| {}), None);
+
+    let invalid = Some("Invalid compiled program: clause 1 of a/1 is invalid".to_string());
+
+    // Reads a register no instruction has written
+    assert_eq!(
+        craft(|database| database.code[0] = Instruction::GetVariable(1, 1)),
+        invalid
+    );
+
+    // Calls a register no instruction has written, as `f(X)` is put in the
+    // register of `b(X, f(X))`
+    assert_eq!(
+        craft(|database| database.code[6] = Instruction::PutStructure(52, 2, 3)),
+        invalid
+    );
+
+    // The guard `X > 1` replaced by a call of b/2
+    assert_eq!(
+        craft(|database| database.code[1] = Instruction::PutStructure(52, 2, 2)),
+        invalid
+    );
+
+    // A call fewer than the clause has goals
+    assert_eq!(
+        craft(|database| database.code[9] = Instruction::SetVoid),
+        invalid
+    );
+}
@@ -0,0 +1,77 @@
+use crate::tests::SolverFn;
+use crate::{test, Solver};
+
+test!(snapshot, |solver: SolverFn| {
+    let program = r#"
+        take([H|T], H, T).
+        take([H|T], R, [H|S]) :- take(T, R, S).
+        perm([], []).
+        perm(L, [H|R]) :- take(L, H, T), perm(T, R).
+    "#;
+
+    let mut solver = solver(program, "perm([a, b, c, d], P), freeze(X, X = P).");
+    let first = solver.step().unwrap();
+    assert!(first.is_some());
+
+    let snapshot = solver.snapshot();
+    let remaining: Vec<_> = solver.map(|s| s.unwrap()).collect();
+    assert_eq!(remaining.len(), 23);
+
+    // Restoring twice gives the same solutions
+    for _ in 0..2 {
+        let restored = Solver::restore(&snapshot).unwrap();
+        assert_eq!(restored.map(|s| s.unwrap()).collect::<Vec<_>>(), remaining);
+    }
+});
+
+test!(LONG snapshot_gc, |solver: SolverFn| {
+    let program = r#"
+        count(N, N).
+        count(N, M) :- N1 is N + 1, count(N1, M).
+    "#;
+
+    let mut solver = solver(program, "count(0, X), X > 20000.");
+    assert_eq!(solver.step().unwrap(), Some(vec![("X".into(), "20001".into())]));
+
+    let mut restored = Solver::restore(&solver.snapshot()).unwrap();
+    for expected in ["20002", "20003"] {
+        let solution = Some(vec![("X".into(), expected.into())]);
+        assert_eq!(solver.step().unwrap(), solution);
+        assert_eq!(restored.step().unwrap(), solution);
+    }
+});
+
+#[test]
+fn snapshot_invalid() {
+    let snapshot = Solver::new("a(1). a(2).", "a(X).").unwrap().snapshot();
+
+    assert!(Solver::restore(&snapshot).is_ok());
+    assert!(Solver::restore(b"").is_err());
+    assert!(Solver::restore(&snapshot[..snapshot.len() - 1]).is_err());
+
+    let mut extended = snapshot.clone();
+    extended.push(0);
+    assert!(Solver::restore(&extended).is_err());
+
+    let mut corrupt = snapshot.clone();
+    corrupt[snapshot.len() / 2] ^= 1;
+    assert_eq!(
+        Solver::restore(&corrupt).err().unwrap().error,
+        "Invalid snapshot: checksum mismatch"
+    );
+
+    // A corrupt length with a valid checksum is an error, not an allocation
+    for i in [15, 23, 40] {
+        let mut length = snapshot[..snapshot.len() - 8].to_vec();
+        length[i] = 0x40;
+        crate::encoding::seal(&mut length, 8);
+        assert!(Solver::restore(&length).is_err());
+    }
+
+    let mut version = snapshot;
+    version[4] += 1;
+    assert_eq!(
+        Solver::restore(&version).err().unwrap().error,
+        "Invalid snapshot: unsupported version 15 (expected 14)"
+    );
+}
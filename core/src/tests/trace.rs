@@ -0,0 +1,177 @@
+use crate::{DebugCommand, DebugStep, Port, Solver, TraceEvent};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+static PROGRAM: &str = r#"
+    a(1).
+    a(2).
+    b(X) :- a(X), X > 1.
+"#;
+
+fn collect(solver: &mut Solver) -> Rc<RefCell<Vec<TraceEvent>>> {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let sink = events.clone();
+    solver.set_tracer(move |event: &TraceEvent| sink.borrow_mut().push(event.clone()));
+    events
+}
+
+fn ports(events: &[TraceEvent]) -> Vec<(Port, String, usize)> {
+    events
+        .iter()
+        .map(|event| (event.port, event.goal.clone(), event.depth))
+        .collect()
+}
+
+#[test]
+fn trace() {
+    let mut solver = Solver::new(PROGRAM, "b(X).").unwrap();
+    let events = collect(&mut solver);
+    solver.set_tracing(true);
+
+    assert_eq!(solver.step().unwrap(), Some(vec![("X".into(), "2".into())]));
+
+    assert_eq!(
+        ports(&events.borrow()),
+        vec![
+            (Port::Call, "b(_2)".into(), 0),
+            (Port::Call, "a(_2)".into(), 1),
+            (Port::Exit, "a(1)".into(), 1),
+            (Port::Call, ">(1,1)".into(), 1),
+            (Port::Fail, ">(1,1)".into(), 1),
+            (Port::Redo, "a(_2)".into(), 1),
+            (Port::Exit, "a(2)".into(), 1),
+            (Port::Call, ">(2,1)".into(), 1),
+            (Port::Exit, ">(2,1)".into(), 1),
+            (Port::Exit, "b(2)".into(), 0),
+        ]
+    );
+
+    let events = events.borrow();
+    assert_eq!(events[2].clause, Some(1));
+    assert_eq!(events[2].choice_point, 1);
+    assert_eq!(events[5].clause, Some(2));
+    assert_eq!(events[6].clause, Some(2));
+}
+
+#[test]
+fn trace_builtins() {
+    let mut solver = Solver::new(PROGRAM, "a(1), trace, a(2), notrace, a(1).").unwrap();
+    let events = collect(&mut solver);
+
+    assert_eq!(solver.step().unwrap(), Some(vec![]));
+    assert_eq!(
+        ports(&events.borrow()),
+        vec![
+            (Port::Exit, "trace".into(), 0),
+            (Port::Call, "a(2)".into(), 0),
+            (Port::Exit, "a(2)".into(), 0),
+            (Port::Call, "notrace".into(), 0),
+        ]
+    );
+}
+
+#[test]
+fn spy() {
+    let mut solver = Solver::new(PROGRAM, "spy(a/1), b(X).").unwrap();
+    let events = collect(&mut solver);
+
+    assert!(solver.step().unwrap().is_some());
+    assert!(events
+        .borrow()
+        .iter()
+        .all(|event| event.goal.starts_with("a(")));
+    assert_eq!(events.borrow().len(), 4);
+
+    let mut solver = Solver::new(PROGRAM, "spy(a), nospy(a), b(X).").unwrap();
+    let events = collect(&mut solver);
+    assert!(solver.step().unwrap().is_some());
+    assert_eq!(events.borrow().len(), 0);
+
+    let mut solver = Solver::new(PROGRAM, "spy(X).").unwrap();
+    assert!(solver.step().is_err());
+}
+
+fn step(solver: &mut Solver, command: DebugCommand) -> (Port, String, usize) {
+    match solver.debug_step(command).unwrap() {
+        DebugStep::Event(event) => (event.port, event.goal, event.depth),
+        step => panic!("Expected an event, got {:?}", step),
+    }
+}
+
+#[test]
+fn debug() {
+    let mut solver = Solver::new(PROGRAM, "b(X).").unwrap();
+    solver.debug();
+
+    assert_eq!(
+        step(&mut solver, DebugCommand::Creep),
+        (Port::Call, "b(_2)".into(), 0)
+    );
+    assert_eq!(
+        step(&mut solver, DebugCommand::Creep),
+        (Port::Call, "a(_2)".into(), 1)
+    );
+    assert_eq!(
+        step(&mut solver, DebugCommand::Creep),
+        (Port::Exit, "a(1)".into(), 1)
+    );
+    assert_eq!(
+        step(&mut solver, DebugCommand::Retry),
+        (Port::Call, "a(_2)".into(), 1)
+    );
+    assert_eq!(
+        step(&mut solver, DebugCommand::Skip),
+        (Port::Exit, "a(1)".into(), 1)
+    );
+    assert_eq!(
+        step(&mut solver, DebugCommand::Creep),
+        (Port::Call, ">(1,1)".into(), 1)
+    );
+    assert_eq!(
+        step(&mut solver, DebugCommand::Creep),
+        (Port::Fail, ">(1,1)".into(), 1)
+    );
+    assert_eq!(
+        step(&mut solver, DebugCommand::Creep),
+        (Port::Redo, "a(_2)".into(), 1)
+    );
+    solver.spy("b", Some(1));
+    assert_eq!(
+        step(&mut solver, DebugCommand::Leap),
+        (Port::Exit, "b(2)".into(), 0)
+    );
+    assert_eq!(
+        solver.debug_step(DebugCommand::Creep).unwrap(),
+        DebugStep::Solution(vec![("X".into(), "2".into())])
+    );
+    assert_eq!(
+        solver.debug_step(DebugCommand::Creep).unwrap(),
+        DebugStep::Done
+    );
+}
+
+#[test]
+fn paused_isnt_done() {
+    let mut solver = Solver::new(PROGRAM, "b(X).").unwrap();
+    solver.debug();
+
+    assert_eq!(
+        step(&mut solver, DebugCommand::Creep),
+        (Port::Call, "b(_2)".into(), 0)
+    );
+    // The solver runs on to the next port and pauses there, rather than ending
+    let error = solver.next().unwrap().unwrap_err();
+    assert_eq!(
+        error.error,
+        "Paused in the debugger, continue with `debug_step`"
+    );
+    assert_eq!(
+        step(&mut solver, DebugCommand::Creep),
+        (Port::Call, "a(_2)".into(), 1)
+    );
+    assert_eq!(
+        solver.debug_step(DebugCommand::Leap).unwrap(),
+        DebugStep::Solution(vec![("X".into(), "2".into())])
+    );
+}
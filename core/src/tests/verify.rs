@@ -0,0 +1,89 @@
+use crate::cell::Cell;
+use crate::gc::GarbageCollector;
+use crate::stringmap::str;
+use crate::{HeapTerm, Solver};
+
+static PROGRAM: &str = r#"
+    count(N, N).
+    count(N, M) :- N1 is N + 1, count(N1, M).
+"#;
+
+#[test]
+fn verify() {
+    let mut solver = Solver::new_with_gc(PROGRAM, "count(0, X), X > 2000.").unwrap();
+    solver.enable_verifier(Some(100));
+    assert_eq!(
+        solver.next().unwrap().unwrap(),
+        vec![("X".into(), "2001".into())]
+    );
+    assert!(solver.gc.runs() > 0);
+
+    GarbageCollector::run(&mut solver);
+    assert_eq!(solver.verify(), Ok(()));
+}
+
+#[test]
+fn verify_corrupted() {
+    let mut solver = Solver::new(PROGRAM, "count(0, X), X > 10.").unwrap();
+    solver.next().unwrap().unwrap();
+    let len = solver.heap.len();
+
+    let mut corrupted = Solver::restore(&solver.snapshot()).unwrap();
+    corrupted.trail.vars.push(len + 5);
+    assert_eq!(
+        corrupted.verify(),
+        Err(format!(
+            "trail entry {} refers to cell {}, past the end of the heap ({} cells)",
+            corrupted.trail.vars.len() - 1,
+            len + 5,
+            len
+        ))
+    );
+
+    let mut corrupted = Solver::restore(&solver.snapshot()).unwrap();
+    let goal = corrupted.goals.current.unwrap();
+    corrupted.goals.goals[goal].0 = len;
+    assert_eq!(
+        corrupted.verify(),
+        Err(format!(
+            "goal {} refers to cell {}, past the end of the heap ({} cells)",
+            goal, len, len
+        ))
+    );
+
+    // Variables are only ever bound to older ones, whether or not it was trailed
+    let mut corrupted = Solver::restore(&solver.snapshot()).unwrap();
+    let a = corrupted.heap.alloc_new_var();
+    let b = corrupted.heap.alloc_new_var();
+    corrupted.heap.data[a] = Cell::var(b);
+    assert_eq!(
+        corrupted.verify(),
+        Err(format!("variable {} is bound to newer variable {}", a, b))
+    );
+
+    // Arguments refer to newer cells, but following them can't loop either
+    let mut corrupted = Solver::restore(&solver.snapshot()).unwrap();
+    let f = corrupted.heap.alloc(HeapTerm::Compound(str::EQ, 1));
+    corrupted.heap.alloc_new_var();
+    let b = corrupted.heap.alloc_new_var();
+    corrupted.heap.data[f + 1] = Cell::var(b);
+    assert_eq!(corrupted.verify(), Ok(()));
+    corrupted.heap.data[b] = Cell::var(f + 1);
+    assert_eq!(
+        corrupted.verify(),
+        Err(format!("the bindings from cell {} form a cycle", f + 1))
+    );
+
+    // The verifier reports failures as errors from the solver
+    let mut corrupted = Solver::restore(&solver.snapshot()).unwrap();
+    corrupted.enable_verifier(Some(1));
+    corrupted.var_map[0].1 = len + 1;
+    assert_eq!(
+        corrupted.next().unwrap().unwrap_err().error,
+        format!(
+            "Heap verification failed at step 1: variable `X` refers to cell {}, past the end of the heap ({} cells)",
+            len + 1,
+            len
+        )
+    );
+}
@@ -0,0 +1,117 @@
+use crate::{Program, Solver};
+
+fn warnings(program: &str) -> Vec<(usize, String)> {
+    Program::new(program)
+        .unwrap()
+        .warnings()
+        .iter()
+        .map(|w| (w.location.as_ref().unwrap().line, w.warning.clone()))
+        .collect()
+}
+
+#[test]
+fn singletons() {
+    let program = r#"
+        a(X, Y) :- b(X, Z).
+        b(_, _Ignored).
+        c(X) :- <{ (X, Y) => console.log(Y) }>.
+    "#;
+
+    assert_eq!(
+        warnings(program),
+        vec![
+            (2, "Singleton variable `Y`".to_string()),
+            (2, "Singleton variable `Z`".to_string()),
+        ]
+    );
+
+    // Each warning points at the variable
+    let columns: Vec<_> = Program::new(program)
+        .unwrap()
+        .warnings()
+        .iter()
+        .map(|w| w.location.as_ref().unwrap().column)
+        .collect();
+    assert_eq!(columns, vec![14, 25]);
+}
+
+#[test]
+fn discontiguous() {
+    let program = r#"
+        a(1).
+        b(1).
+        a(2).
+        b(2).
+        a(3).
+    "#;
+
+    assert_eq!(
+        warnings(program),
+        vec![
+            (4, "Clauses of `a/1` are not together".to_string()),
+            (5, "Clauses of `b/1` are not together".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn unknown_predicates() {
+    let program = r#"
+        a(X) :- b(X), c(X, 1), X > 0, call(d).
+        c(X) :- d(X, X).
+        d(_, _).
+    "#;
+
+    assert_eq!(
+        warnings(program),
+        vec![
+            (2, "Unknown procedure `b/1`".to_string()),
+            (
+                2,
+                "Unknown procedure `c/2`, did you mean `c/1`?".to_string()
+            ),
+        ]
+    );
+
+    let program = Program::new(program).unwrap();
+    let location = &program.warnings()[1].location.as_ref().unwrap();
+    assert_eq!(location.predicate.as_deref(), Some("a/1"));
+    assert_eq!(location.clause, Some(1));
+    assert_eq!((location.column, location.end_column), (23, 30));
+}
+
+#[test]
+fn builtin_redefined() {
+    assert_eq!(
+        warnings("var(_).\nis(X, X)."),
+        vec![
+            (
+                1,
+                "`var/1` is a built-in predicate, so its clauses are never called".to_string()
+            ),
+            (
+                2,
+                "`is/2` is a built-in predicate, so its clauses are never called".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn query_warnings() {
+    let solver = Solver::new("a(1).\nb :- c.", "a(X), a(X, Y).").unwrap();
+    let warnings: Vec<&str> = solver
+        .warnings()
+        .iter()
+        .map(|w| w.warning.as_str())
+        .collect();
+
+    assert_eq!(
+        warnings,
+        vec![
+            "Unknown procedure `c/0`",
+            "Unknown procedure `a/2`, did you mean `a/1`?"
+        ]
+    );
+    assert!(solver.warnings()[1].location.as_ref().unwrap().query);
+}
@@ -0,0 +1,320 @@
+use crate::profile::Port;
+use crate::{ChoicePoint, ClauseName, Error, HeapTermPtr, Solution, Solver, Stop, StringId};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceEvent {
+    pub port: Port,
+    pub goal: String,
+    pub depth: usize,
+    pub clause: Option<usize>,
+    pub choice_point: usize,
+}
+
+pub trait TraceSink {
+    fn event(&mut self, event: &TraceEvent);
+}
+
+impl<F: FnMut(&TraceEvent)> TraceSink for F {
+    fn event(&mut self, event: &TraceEvent) {
+        self(event)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugCommand {
+    Creep,
+    Skip,
+    Leap,
+    Retry,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DebugStep {
+    Event(TraceEvent),
+    Solution(Solution),
+    Done,
+}
+
+#[derive(Default)]
+pub struct Tracer {
+    sink: Option<Box<dyn TraceSink>>,
+    tracing: bool,
+    spy_points: Vec<(StringId, Option<usize>)>,
+    debugger: Option<Debugger>,
+}
+
+struct Debugger {
+    mode: Mode,
+    state: State,
+    frames: Vec<Frame>,
+    last: Option<(Port, usize, HeapTermPtr)>,
+    solution: Option<Solution>,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Creep,
+    Skip(usize),
+    Leap,
+}
+
+enum State {
+    Running,
+    Paused(TraceEvent),
+    Resuming,
+}
+
+// The state of the solver at a call port, used to retry the goal.
+#[derive(Clone, Copy)]
+struct Frame {
+    depth: usize,
+    goal: HeapTermPtr,
+    choice_point: ChoicePoint,
+    choice_points: usize,
+}
+
+impl Tracer {
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.sink.is_some() || self.debugger.is_some()
+    }
+
+    #[inline]
+    pub fn is_debugging(&self) -> bool {
+        self.debugger.is_some()
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        matches!(
+            self.debugger,
+            Some(Debugger {
+                state: State::Paused(_),
+                ..
+            })
+        )
+    }
+
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.tracing = tracing;
+    }
+
+    pub fn spy(&mut self, functor: StringId, arity: Option<usize>) {
+        if !self.spy_points.contains(&(functor, arity)) {
+            self.spy_points.push((functor, arity));
+        }
+    }
+
+    pub fn nospy(&mut self, functor: StringId, arity: Option<usize>) {
+        self.spy_points
+            .retain(|(f, a)| *f != functor || (arity.is_some() && *a != arity));
+    }
+
+    pub fn spy_points(&self) -> impl Iterator<Item = StringId> + '_ {
+        self.spy_points.iter().map(|(functor, _)| *functor)
+    }
+
+    fn is_spied(&self, ClauseName(functor, arity): ClauseName) -> bool {
+        self.spy_points
+            .iter()
+            .any(|(f, a)| *f == functor && a.map(|a| a == arity).unwrap_or(true))
+    }
+
+    // Returns false if this is the call port we paused at, which has already been reported.
+    #[inline]
+    pub fn resume(&mut self, port: Port) -> bool {
+        match &mut self.debugger {
+            Some(debugger) if matches!(debugger.state, State::Resuming) => {
+                debugger.state = State::Running;
+                !matches!(port, Port::Call | Port::Redo)
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Debugger {
+    fn new() -> Self {
+        Self {
+            mode: Mode::Creep,
+            state: State::Running,
+            frames: Vec::new(),
+            last: None,
+            solution: None,
+        }
+    }
+
+    fn should_stop(&self, depth: usize, spied: bool) -> bool {
+        match self.mode {
+            Mode::Creep => true,
+            Mode::Skip(skip_depth) => depth <= skip_depth,
+            Mode::Leap => spied,
+        }
+    }
+}
+
+impl Solver {
+    pub fn set_tracer(&mut self, sink: impl TraceSink + 'static) {
+        self.tracer.sink = Some(Box::new(sink));
+    }
+
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.tracer.set_tracing(tracing);
+    }
+
+    pub fn spy(&mut self, name: &str, arity: Option<usize>) {
+        let functor = self.heap.string_map.alloc(name);
+        self.tracer.spy(functor, arity);
+    }
+
+    pub fn nospy(&mut self, name: &str, arity: Option<usize>) {
+        let functor = self.heap.string_map.alloc(name);
+        self.tracer.nospy(functor, arity);
+    }
+
+    // Puts the solver into step-by-step debug mode.
+    // Garbage collection is suspended while debugging so that goals can be retried.
+    pub fn debug(&mut self) {
+        if self.tracer.debugger.is_none() {
+            self.tracer.debugger = Some(Debugger::new());
+            self.goals.retain = true;
+        }
+    }
+
+    pub fn debug_step(&mut self, command: DebugCommand) -> Result<DebugStep, Error> {
+        self.debug();
+
+        let debugger = self.tracer.debugger.as_mut().unwrap();
+
+        if let Some(solution) = debugger.solution.take() {
+            return Ok(DebugStep::Solution(solution));
+        }
+
+        debugger.mode = match (command, debugger.last) {
+            (DebugCommand::Skip, Some((Port::Call | Port::Redo, depth, _))) => Mode::Skip(depth),
+            (DebugCommand::Leap, _) => Mode::Leap,
+            _ => Mode::Creep,
+        };
+
+        if command == DebugCommand::Retry {
+            self.retry();
+        }
+
+        let result = self.resume();
+        let debugger = self.tracer.debugger.as_mut().unwrap();
+        let paused = match std::mem::replace(&mut debugger.state, State::Running) {
+            State::Paused(event) => {
+                // Don't report the call port again when execution resumes
+                if matches!(event.port, Port::Call | Port::Redo) {
+                    debugger.state = State::Resuming;
+                }
+                Some(event)
+            }
+            _ => None,
+        };
+
+        match (result, paused) {
+            (Stop::Solution(solution), Some(event)) => {
+                debugger.solution = Some(solution);
+                Ok(DebugStep::Event(event))
+            }
+            (Stop::Solution(solution), None) => Ok(DebugStep::Solution(solution)),
+            (Stop::Error(e), _) => Err(e),
+            (Stop::Paused | Stop::Done, Some(event)) => Ok(DebugStep::Event(event)),
+            (Stop::Paused | Stop::Done, None) => Ok(DebugStep::Done),
+        }
+    }
+
+    // Restores the state at the call port of the goal the debugger last stopped at.
+    fn retry(&mut self) {
+        let debugger = self.tracer.debugger.as_mut().unwrap();
+
+        let Some((_, depth, goal)) = debugger.last else {
+            return;
+        };
+
+        let Some(i) = debugger
+            .frames
+            .iter()
+            .rposition(|frame| frame.depth == depth && frame.goal == goal)
+        else {
+            return;
+        };
+
+        let frame = debugger.frames[i];
+        debugger.frames.truncate(i);
+        debugger.state = State::Running;
+
+        self.choice_points.truncate(frame.choice_points);
+        self.undo(frame.choice_point);
+        self.reset_choice_point_age();
+    }
+
+    pub(crate) fn trace(
+        &mut self,
+        port: Port,
+        goal: HeapTermPtr,
+        name: ClauseName,
+        clause: Option<usize>,
+    ) {
+        let spied = self.tracer.is_spied(name);
+        let deliver = self.tracer.sink.is_some() && (self.tracer.tracing || spied);
+
+        let stop = if let Some(debugger) = &mut self.tracer.debugger {
+            if port == Port::Call {
+                while debugger
+                    .frames
+                    .last()
+                    .map(|frame| frame.depth >= self.depth)
+                    .unwrap_or(false)
+                {
+                    debugger.frames.pop();
+                }
+
+                debugger.frames.push(Frame {
+                    depth: self.depth,
+                    goal,
+                    choice_point: ChoicePoint {
+                        group: self.group,
+                        clause: 0,
+                        depth: self.depth,
+                        trail_checkpoint: self.trail.checkpoint(),
+                        heap_checkpoint: self.heap.checkpoint(),
+                        goals_checkpoint: self.goals.checkpoint(),
+                    },
+                    choice_points: self.choice_points.len(),
+                });
+            }
+
+            debugger.should_stop(self.depth, spied)
+        } else {
+            false
+        };
+
+        if !deliver && !stop {
+            return;
+        }
+
+        let event = TraceEvent {
+            port,
+            goal: self.heap.serialize_term(goal),
+            depth: self.depth,
+            clause: clause.map(|clause| clause + 1),
+            choice_point: self.choice_points.len(),
+        };
+
+        if deliver {
+            if let Some(sink) = &mut self.tracer.sink {
+                sink.event(&event);
+            }
+        }
+
+        if stop {
+            let debugger = self.tracer.debugger.as_mut().unwrap();
+            debugger.last = Some((port, self.depth, goal));
+            debugger.state = State::Paused(event);
+        }
+    }
+}
@@ -1,12 +1,14 @@
 use crate::heap::Heap;
 use crate::HeapTermPtr;
 
-#[derive(Default, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Trail {
     pub(crate) vars: Vec<HeapTermPtr>,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Checkpoint(pub(crate) usize);
 
 impl Trail {
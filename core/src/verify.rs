@@ -0,0 +1,296 @@
+use crate::goal::Caller;
+use crate::{Error, ErrorKind, HeapTerm, Solver};
+
+// Checks the invariants the garbage collector relies on after it has rewritten
+// every pointer, so that a bad rewrite is reported where it happened rather
+// than as a wrong answer much later.
+pub struct Verifier {
+    // Also verify every this many steps, as well as after every collection
+    every: Option<usize>,
+    steps: usize,
+}
+
+impl Solver {
+    pub fn enable_verifier(&mut self, every: Option<usize>) {
+        self.verifier = Some(Verifier { every, steps: 0 });
+    }
+
+    #[inline]
+    pub(crate) fn verify_step(&mut self, collected: bool) -> Result<(), Error> {
+        let Some(verifier) = &mut self.verifier else {
+            return Ok(());
+        };
+
+        verifier.steps += 1;
+        let when = if collected {
+            format!("after garbage collection {}", self.gc.runs())
+        } else if verifier
+            .every
+            .is_some_and(|every| verifier.steps % every.max(1) == 0)
+        {
+            format!("at step {}", verifier.steps)
+        } else {
+            return Ok(());
+        };
+
+        self.verify().map_err(|e| {
+            Error::new(
+                ErrorKind::System,
+                format!("Heap verification failed {}: {}", when, e),
+            )
+        })
+    }
+
+    // Walks the heap, goals, trail, var map and choice points, and describes the
+    // first pointer that is out of place
+    pub fn verify(&self) -> Result<(), String> {
+        let heap = &self.heap;
+        let len = heap.data.len();
+
+        let mut trailed = vec![false; len];
+        for (i, &var) in self.trail.vars.iter().enumerate() {
+            if var >= len {
+                return Err(format!(
+                    "trail entry {} refers to cell {}, past the end of the heap ({} cells)",
+                    i, var, len
+                ));
+            }
+            if !heap.data[var].is_var() {
+                return Err(format!(
+                    "trail entry {} refers to cell {}, which is not a variable: {:?}",
+                    i, var, heap.data[var]
+                ));
+            }
+            trailed[var] = true;
+        }
+
+        // The cell after the last argument of the structures seen so far
+        let mut arguments = 0;
+
+        let mut i = 0;
+        while i < len {
+            let cell = heap.data[i];
+            if !cell.is_valid() {
+                return Err(format!("cell {} is not the start of a term: {:?}", i, cell));
+            }
+
+            if cell.is_boxed() {
+                if !heap.data.get(i + 1).is_some_and(|raw| raw.is_raw()) {
+                    return Err(format!("boxed cell {} is missing its low bits", i));
+                }
+                i += 2;
+                continue;
+            }
+
+            match heap.term(i) {
+                HeapTerm::Var(ptr) if ptr >= len => {
+                    return Err(format!(
+                        "cell {} points to cell {}, past the end of the heap ({} cells)",
+                        i, ptr, len
+                    ));
+                }
+                // Variables are bound downwards, so bindings always terminate.
+                // Arguments of a structure also refer to subterms built after
+                // it, so only the ones that were bound can be checked.
+                HeapTerm::Var(ptr)
+                    if ptr > i
+                        && heap.data[ptr].is_var()
+                        && (i >= arguments || cell.is_shunted() || trailed[i]) =>
+                {
+                    return Err(format!("variable {} is bound to newer variable {}", i, ptr));
+                }
+                HeapTerm::Compound(_, arity) | HeapTerm::Lambda(_, arity) if i + arity >= len => {
+                    return Err(format!(
+                        "structure at cell {} with arity {} extends past the end of the heap ({} cells)",
+                        i, arity, len
+                    ));
+                }
+                HeapTerm::Compound(_, arity) | HeapTerm::Lambda(_, arity) => {
+                    arguments = i + arity + 1
+                }
+                _ => {}
+            }
+
+            if cell.is_attributed() && !heap.attributes.contains_key(&i) {
+                return Err(format!("attributed variable {} has no attribute", i));
+            }
+
+            i += 1;
+        }
+
+        self.verify_bindings()?;
+
+        for (&var, &goal) in &heap.attributes {
+            if var >= len || !heap.data[var].is_attributed() {
+                return Err(format!(
+                    "attribute of cell {} has no attributed variable",
+                    var
+                ));
+            }
+            if goal >= len {
+                return Err(format!(
+                    "attribute of variable {} refers to cell {}, past the end of the heap ({} cells)",
+                    var, goal, len
+                ));
+            }
+        }
+
+        for (name, ptr) in &self.var_map {
+            if *ptr >= len {
+                return Err(format!(
+                    "variable `{}` refers to cell {}, past the end of the heap ({} cells)",
+                    name, ptr, len
+                ));
+            }
+        }
+
+        self.verify_choice_points()?;
+        self.verify_goals()
+    }
+
+    // Following bindings from any cell must end at an unbound variable or a value
+    fn verify_bindings(&self) -> Result<(), String> {
+        const UNVISITED: u8 = 0;
+        const VISITING: u8 = 1;
+        const DONE: u8 = 2;
+
+        let data = &self.heap.data;
+        let mut state = vec![UNVISITED; data.len()];
+        let mut chain = Vec::new();
+
+        for start in 0..data.len() {
+            let mut ptr = start;
+            while state[ptr] == UNVISITED && data[ptr].is_var() && data[ptr].ptr() != ptr {
+                state[ptr] = VISITING;
+                chain.push(ptr);
+                ptr = data[ptr].ptr();
+            }
+
+            if state[ptr] == VISITING {
+                return Err(format!("the bindings from cell {} form a cycle", start));
+            }
+
+            for ptr in chain.drain(..) {
+                state[ptr] = DONE;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn verify_choice_points(&self) -> Result<(), String> {
+        let mut heap_top = 0;
+        let mut trail_top = 0;
+
+        for (i, cp) in self.choice_points.iter().enumerate() {
+            let heap_checkpoint = cp.heap_checkpoint.0;
+            let trail_checkpoint = cp.trail_checkpoint.0;
+
+            if heap_checkpoint < heap_top || heap_checkpoint > self.heap.data.len() {
+                return Err(format!(
+                    "choice point {} starts at cell {}, outside cells {}..{}",
+                    i,
+                    heap_checkpoint,
+                    heap_top,
+                    self.heap.data.len()
+                ));
+            }
+
+            if trail_checkpoint < trail_top || trail_checkpoint > self.trail.vars.len() {
+                return Err(format!(
+                    "choice point {} starts at trail entry {}, outside entries {}..{}",
+                    i,
+                    trail_checkpoint,
+                    trail_top,
+                    self.trail.vars.len()
+                ));
+            }
+
+            // Bindings trailed before a choice point are of variables that existed before it
+            for (j, &var) in self.trail.vars[trail_top..trail_checkpoint]
+                .iter()
+                .enumerate()
+            {
+                if var >= heap_checkpoint {
+                    return Err(format!(
+                        "trail entry {} refers to cell {}, which is newer than choice point {} (cell {})",
+                        trail_top + j,
+                        var,
+                        i,
+                        heap_checkpoint
+                    ));
+                }
+            }
+
+            let goals = &self.goals.goals;
+            if cp.goals_checkpoint.1 > goals.len()
+                || cp
+                    .goals_checkpoint
+                    .0
+                    .is_some_and(|goal| goal >= goals.len())
+            {
+                return Err(format!(
+                    "choice point {} refers to goal {:?}, past the end of the goal stack ({} goals)",
+                    i,
+                    cp.goals_checkpoint.0,
+                    goals.len()
+                ));
+            }
+
+            heap_top = heap_checkpoint;
+            trail_top = trail_checkpoint;
+        }
+
+        Ok(())
+    }
+
+    // The current goals, and the ones each choice point would restore
+    fn verify_goals(&self) -> Result<(), String> {
+        let goals = &self.goals.goals;
+        let mut visited = vec![false; goals.len()];
+
+        let starts = std::iter::once(self.goals.current)
+            .chain(self.choice_points.iter().map(|cp| cp.goals_checkpoint.0));
+
+        for start in starts {
+            let mut next = start;
+            while let Some(ptr) = next {
+                if ptr >= goals.len() {
+                    return Err(format!(
+                        "goal {} is past the end of the goal stack ({} goals)",
+                        ptr,
+                        goals.len()
+                    ));
+                }
+                if visited[ptr] {
+                    break;
+                }
+                visited[ptr] = true;
+
+                if goals[ptr].term() >= self.heap.data.len() {
+                    return Err(format!(
+                        "goal {} refers to cell {}, past the end of the heap ({} cells)",
+                        ptr,
+                        goals[ptr].term(),
+                        self.heap.data.len()
+                    ));
+                }
+
+                if let Some(Caller(term, _)) = goals[ptr].caller() {
+                    if term >= self.heap.data.len() {
+                        return Err(format!(
+                            "caller of goal {} refers to cell {}, past the end of the heap ({} cells)",
+                            ptr,
+                            term,
+                            self.heap.data.len()
+                        ));
+                    }
+                }
+
+                next = goals[ptr].prev_ptr();
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,354 @@
+use crate::ast::Span;
+use crate::goal::{Caller, Origin};
+use crate::{Atom, ChoicePointIdx, HeapTerm, HeapTermPtr, LambdaId, Solver, StringId};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub type Register = usize;
+
+// Registers `0..arity` hold the arguments of the goal being resolved,
+// followed by one register for each clause variable and then temporaries.
+//
+// Only head unification works on registers. Body goals are still built as
+// terms and pushed on the goal stack by `Call`, since the collector, snapshots,
+// the tracer and backtraces all work on goal terms. So there are no
+// environments and no `execute`/`proceed`: the goal stack plays their part,
+// and a determinate goal's frame is dropped when it's popped.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Instruction {
+    // Head unification
+    GetVariable(Register, Register), // var, arg
+    GetValue(Register, Register),    // var, arg
+    GetConstant(Atom, Register),
+    GetStructure(StringId, usize, Register),
+    UnifyVariable(Register),
+    UnifyValue(Register),
+    UnifyConstant(Atom),
+    UnifyVoid,
+
+    // Body construction
+    PutVariable(Register),
+    PutConstant(Atom, Register),
+    PutStructure(StringId, usize, Register),
+    PutLambda(LambdaId, usize, Register),
+    PutCut(Register),
+    SetVariable(Register),
+    SetValue(Register),
+    SetConstant(Atom),
+    SetVoid,
+    Call(Register, usize), // goal, source
+}
+
+// The principal functor or constant of a clause's first argument
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Key {
+    Atom(Atom),
+    Functor(StringId, usize),
+}
+
+// Whether a clause can commit once its guards succeed, without a choice point
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Commit {
+    Never,
+    // The guards are followed by a cut
+    Cut,
+    // No later clause can succeed as well, provided the arguments in the mask are
+    // bound when the goal is called (and its first argument, if indexed)
+    Exclusive { args: u32, indexed: bool },
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CompiledClause {
+    pub(crate) head: usize,
+    pub(crate) body: usize,
+    pub(crate) end: usize,
+    pub(crate) goals: usize,
+    pub(crate) registers: usize,
+    pub(crate) key: Option<Key>,
+    pub(crate) guards: usize,
+    pub(crate) commit: Commit,
+}
+
+// Where a body goal was written, and the clause it belongs to
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GoalSource {
+    pub(crate) span: Span,
+    pub(crate) predicate: usize,
+    pub(crate) clause: usize,
+}
+
+// switch_on_term: the clauses of a predicate that can match each first
+// argument, in order, so that the others are never tried
+#[derive(Clone, Default)]
+pub struct Switch {
+    all: Vec<usize>,
+    keys: HashMap<(u8, u64, usize), Vec<usize>>,
+    // Clauses whose first argument is a variable, which match any key
+    vars: Vec<usize>,
+}
+
+impl Switch {
+    pub fn new(clauses: &[CompiledClause]) -> Self {
+        let mut switch = Switch {
+            all: (0..clauses.len()).collect(),
+            ..Default::default()
+        };
+
+        for (i, clause) in clauses.iter().enumerate() {
+            match clause.key {
+                Some(key) => switch
+                    .keys
+                    .entry(hash(key))
+                    .or_insert_with(|| switch.vars.clone())
+                    .push(i),
+                None => {
+                    switch.vars.push(i);
+                    switch.keys.values_mut().for_each(|clauses| clauses.push(i));
+                }
+            }
+        }
+
+        switch
+    }
+
+    // The clauses that can match a goal whose first argument has this key,
+    // or all of them if it is unbound
+    #[inline]
+    pub fn clauses(&self, key: Option<Key>) -> &[usize] {
+        match key {
+            Some(key) => self.keys.get(&hash(key)).unwrap_or(&self.vars),
+            None => &self.all,
+        }
+    }
+}
+
+// Keys that are equal have the same hash. Zeros are equal whatever their sign.
+fn hash(key: Key) -> (u8, u64, usize) {
+    match key {
+        Key::Atom(Atom::String(id)) => (0, id as u64, 0),
+        Key::Atom(Atom::Integer(i)) => (1, i as u64, 0),
+        Key::Atom(Atom::Float(f)) => (2, (f + 0.0).to_bits(), 0),
+        Key::Atom(Atom::Text(id)) => (3, id as u64, 0),
+        Key::Functor(functor, arity) => (4, functor as u64, arity),
+    }
+}
+
+impl Solver {
+    // Finds the indexing key of the goal's first argument, if it is bound.
+    pub(crate) fn first_arg_key(&self, goal: HeapTermPtr) -> Option<Key> {
+        let goal = self.heap.get_ptr(goal);
+
+        match self.heap.term(goal) {
+            HeapTerm::Compound(_, arity) if arity > 0 => match self.heap.get(goal + 1) {
+                HeapTerm::Atom(atom) => Some(Key::Atom(atom)),
+                HeapTerm::Compound(functor, arity) => Some(Key::Functor(functor, arity)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub(crate) fn can_commit(
+        &self,
+        goal: HeapTermPtr,
+        key: Option<Key>,
+        clause: CompiledClause,
+    ) -> bool {
+        match clause.commit {
+            Commit::Never => false,
+            Commit::Cut => true,
+            Commit::Exclusive { args, indexed } => {
+                let goal = self.heap.get_ptr(goal);
+
+                (!indexed || key.is_some())
+                    && (0..32).filter(|arg| args & (1 << arg) != 0).all(|arg| {
+                        matches!(
+                            self.heap.get(self.heap.get_ptr(goal + 1 + arg)),
+                            HeapTerm::Atom(_)
+                        )
+                    })
+            }
+        }
+    }
+
+    pub(crate) fn unify_head(&mut self, goal: HeapTermPtr, clause: CompiledClause) -> bool {
+        if self.registers.len() < clause.registers {
+            self.registers.resize(clause.registers, 0);
+        }
+
+        let goal = self.heap.get_ptr(goal);
+        if let HeapTerm::Compound(_, arity) = self.heap.term(goal) {
+            for i in 0..arity {
+                self.registers[i] = goal + 1 + i;
+            }
+        }
+
+        self.execute(clause.head, clause.body, 0, None)
+    }
+
+    // Pushes the goals of the body, called by the goal the clause was selected for
+    pub(crate) fn build_body(
+        &mut self,
+        clause: CompiledClause,
+        choice_point_idx: ChoicePointIdx,
+        caller: Caller,
+    ) {
+        self.execute(clause.body, clause.end, choice_point_idx, Some(caller));
+    }
+
+    fn execute(
+        &mut self,
+        start: usize,
+        end: usize,
+        choice_point_idx: ChoicePointIdx,
+        caller: Option<Caller>,
+    ) -> bool {
+        // Next argument of the current structure, and whether it is being built or read
+        let mut s: HeapTermPtr = 0;
+        let mut write = false;
+
+        for pc in start..end {
+            let instruction = self.program.code[pc];
+            match instruction {
+                // Variables refer to the dereferenced argument so that the goal's
+                // own cells don't stay reachable from the body
+                Instruction::GetVariable(var, arg) => {
+                    self.registers[var] = self.heap.get_ptr(self.registers[arg])
+                }
+                Instruction::GetValue(var, arg) => {
+                    if !self.unify(self.registers[var], self.registers[arg]) {
+                        return false;
+                    }
+                }
+                Instruction::GetConstant(atom, arg) => {
+                    if !self.unify_constant(self.registers[arg], atom) {
+                        return false;
+                    }
+                }
+                Instruction::GetStructure(functor, arity, arg) => {
+                    let ptr = self.heap.get_ptr(self.registers[arg]);
+
+                    match self.heap.term(ptr) {
+                        HeapTerm::Compound(f, a) if f == functor && a == arity => {
+                            s = ptr + 1;
+                            write = false;
+                        }
+                        HeapTerm::Var(_) => {
+                            let structure =
+                                self.alloc_structure(HeapTerm::Compound(functor, arity));
+                            self.unify_var(ptr, structure);
+                            s = structure + 1;
+                            write = true;
+                        }
+                        _ => return false,
+                    }
+                }
+                Instruction::UnifyVariable(var) => {
+                    if write {
+                        self.registers[var] = self.heap.alloc_new_var();
+                        self.heap.set(s, HeapTerm::Var(self.registers[var]));
+                    } else {
+                        self.registers[var] = self.heap.get_ptr(s);
+                    }
+                    s += 1;
+                }
+                Instruction::UnifyValue(var) => {
+                    if write {
+                        self.heap.set(s, HeapTerm::Var(self.registers[var]));
+                    } else if !self.unify(self.registers[var], s) {
+                        return false;
+                    }
+                    s += 1;
+                }
+                Instruction::UnifyConstant(atom) => {
+                    if write {
+                        self.heap.set(s, HeapTerm::Atom(atom));
+                    } else if !self.unify_constant(s, atom) {
+                        return false;
+                    }
+                    s += 1;
+                }
+                Instruction::UnifyVoid => {
+                    if write {
+                        let var = self.heap.alloc_new_var();
+                        self.heap.set(s, HeapTerm::Var(var));
+                    }
+                    s += 1;
+                }
+                Instruction::PutVariable(reg) => self.registers[reg] = self.heap.alloc_new_var(),
+                Instruction::PutConstant(atom, reg) => {
+                    self.registers[reg] = self.heap.alloc(HeapTerm::Atom(atom))
+                }
+                Instruction::PutStructure(functor, arity, reg) => {
+                    self.registers[reg] = self.alloc_structure(HeapTerm::Compound(functor, arity));
+                    s = self.registers[reg] + 1;
+                    write = true;
+                }
+                Instruction::PutLambda(id, arity, reg) => {
+                    self.registers[reg] = self.alloc_structure(HeapTerm::Lambda(id, arity));
+                    s = self.registers[reg] + 1;
+                    write = true;
+                }
+                Instruction::PutCut(reg) => {
+                    self.registers[reg] = self.heap.alloc(HeapTerm::Cut(choice_point_idx))
+                }
+                Instruction::SetVariable(var) => {
+                    self.registers[var] = self.heap.alloc_new_var();
+                    self.heap.set(s, HeapTerm::Var(self.registers[var]));
+                    s += 1;
+                }
+                Instruction::SetValue(var) => {
+                    self.heap.set(s, HeapTerm::Var(self.registers[var]));
+                    s += 1;
+                }
+                Instruction::SetConstant(atom) => {
+                    self.heap.set(s, HeapTerm::Atom(atom));
+                    s += 1;
+                }
+                Instruction::SetVoid => {
+                    let var = self.heap.alloc_new_var();
+                    self.heap.set(s, HeapTerm::Var(var));
+                    s += 1;
+                }
+                Instruction::Call(reg, source) => self.goals.push(
+                    self.registers[reg],
+                    Some(Origin::Program(source as u32)),
+                    caller,
+                ),
+            }
+        }
+
+        true
+    }
+
+    #[inline]
+    fn unify_constant(&mut self, ptr: HeapTermPtr, atom: Atom) -> bool {
+        let ptr = self.heap.get_ptr(ptr);
+
+        match self.heap.term(ptr) {
+            HeapTerm::Atom(other) => other == atom,
+            HeapTerm::Var(_) => {
+                let constant = self.heap.alloc(HeapTerm::Atom(atom));
+                self.unify_var(ptr, constant)
+            }
+            _ => false,
+        }
+    }
+
+    // Allocates a structure with space for its arguments, which must then be filled in.
+    #[inline]
+    fn alloc_structure(&mut self, term: HeapTerm) -> HeapTermPtr {
+        let arity = match term {
+            HeapTerm::Compound(_, arity) | HeapTerm::Lambda(_, arity) => arity,
+            _ => 0,
+        };
+
+        let result = self.heap.alloc(term);
+        for _ in 0..arity {
+            self.heap.alloc_new_var();
+        }
+
+        result
+    }
+}
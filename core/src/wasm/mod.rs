@@ -15,7 +15,10 @@ pub mod inline_js {
 use serde::{ser::SerializeStruct, Serialize};
 use wasm_bindgen::prelude::*;
 
-use crate::{Atom, Error, Heap, HeapTerm, HeapTermPtr};
+use crate::atom::{quote, quote_string};
+use crate::{
+    Atom, DebugCommand, DebugStep, Error, ErrorKind, Heap, HeapTerm, HeapTermPtr, TraceEvent,
+};
 
 #[wasm_bindgen(start)]
 pub fn init() {
@@ -51,11 +54,61 @@ extern "C" {
     fn dummy();
 }
 
+#[derive(Debug, PartialEq)]
 pub enum Term {
     String(String),
     Number(f64),
     Variable(HeapTermPtr),
     Compound(String, Vec<Term>),
+    Text(String),
+}
+
+#[wasm_bindgen]
+pub struct Program(crate::Program);
+
+#[wasm_bindgen]
+impl Program {
+    #[wasm_bindgen]
+    pub fn load(program: &str) -> Result<Program, JsValue> {
+        Ok(Program(crate::Program::new(program)?))
+    }
+
+    // All syntax errors in the program, which is empty if it parses
+    #[wasm_bindgen]
+    pub fn check(program: &str) -> JsValue {
+        let errors = crate::Program::parse_all(program).err().unwrap_or_default();
+        serde_wasm_bindgen::to_value(&errors).unwrap()
+    }
+
+    #[wasm_bindgen]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, JsValue> {
+        Ok(Program(crate::Program::from_bytes(bytes)?))
+    }
+
+    #[wasm_bindgen]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    #[wasm_bindgen]
+    pub fn warnings(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(self.0.warnings()).unwrap()
+    }
+
+    #[wasm_bindgen]
+    pub fn dump(&self) -> String {
+        self.0.database().dump()
+    }
+
+    #[wasm_bindgen]
+    pub fn query(&self, query: &str) -> Result<Solver, JsValue> {
+        Ok(Solver(self.0.query(query)?))
+    }
+
+    #[wasm_bindgen]
+    pub fn query_with_gc(&self, query: &str) -> Result<Solver, JsValue> {
+        Ok(Solver(self.0.query_with_gc(query)?))
+    }
 }
 
 #[wasm_bindgen]
@@ -73,6 +126,16 @@ impl Solver {
         Ok(Solver(crate::Solver::new_with_gc(program, query)?))
     }
 
+    #[wasm_bindgen]
+    pub fn restore(snapshot: &[u8]) -> Result<Solver, JsValue> {
+        Ok(Solver(crate::Solver::restore(snapshot)?))
+    }
+
+    #[wasm_bindgen]
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.0.snapshot()
+    }
+
     #[wasm_bindgen]
     pub fn next(&mut self) -> Result<Option<js_sys::Map>, Error> {
         self.0.step().map(|o| o.map(solution_to_js))
@@ -82,23 +145,172 @@ impl Solver {
     pub fn all(&mut self) -> Result<js_sys::Array, Error> {
         self.0.by_ref().map(|s| s.map(solution_to_js)).collect()
     }
+
+    #[wasm_bindgen]
+    pub fn configure_gc(
+        &mut self,
+        heap_size_threshold: Option<usize>,
+        heap_pressure_threshold: Option<f64>,
+        cooldown: Option<usize>,
+        mark_budget: Option<usize>,
+    ) {
+        let default = crate::GCConfig::default();
+        self.0.configure_gc(crate::GCConfig {
+            heap_size_threshold: heap_size_threshold.unwrap_or(default.heap_size_threshold),
+            heap_pressure_threshold: heap_pressure_threshold
+                .unwrap_or(default.heap_pressure_threshold),
+            cooldown: cooldown.unwrap_or(default.cooldown),
+            mark_budget,
+        });
+    }
+
+    #[wasm_bindgen]
+    pub fn add_input(&mut self, text: &str) {
+        self.0.add_input(text);
+    }
+
+    #[wasm_bindgen]
+    pub fn warnings(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.0.warnings()).unwrap()
+    }
+
+    #[wasm_bindgen]
+    pub fn last_gc(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.0.last_gc()).unwrap()
+    }
+
+    #[wasm_bindgen]
+    pub fn enable_verifier(&mut self, every: Option<usize>) {
+        self.0.enable_verifier(every);
+    }
+
+    #[wasm_bindgen]
+    pub fn enable_profiler(&mut self) {
+        self.0.enable_profiler();
+    }
+
+    #[wasm_bindgen]
+    pub fn profile(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.0.profile()).unwrap()
+    }
+
+    #[wasm_bindgen]
+    pub fn set_tracer(&mut self, tracer: js_sys::Function) {
+        self.0.set_tracer(move |event: &TraceEvent| {
+            let event = serde_wasm_bindgen::to_value(event).unwrap();
+            let _ = tracer.call1(&JsValue::NULL, &event);
+        });
+    }
+
+    #[wasm_bindgen]
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.0.set_tracing(tracing);
+    }
+
+    #[wasm_bindgen]
+    pub fn spy(&mut self, name: &str, arity: Option<usize>) {
+        self.0.spy(name, arity);
+    }
+
+    #[wasm_bindgen]
+    pub fn nospy(&mut self, name: &str, arity: Option<usize>) {
+        self.0.nospy(name, arity);
+    }
+
+    #[wasm_bindgen]
+    pub fn debug(&mut self) {
+        self.0.debug();
+    }
+
+    #[wasm_bindgen]
+    pub fn debug_step(&mut self, command: &str) -> Result<JsValue, Error> {
+        let command = match command {
+            "creep" => DebugCommand::Creep,
+            "skip" => DebugCommand::Skip,
+            "leap" => DebugCommand::Leap,
+            "retry" => DebugCommand::Retry,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Domain,
+                    format!("Unknown debugger command `{}`", command),
+                ))
+            }
+        };
+
+        Ok(match self.0.debug_step(command)? {
+            DebugStep::Event(event) => serde_wasm_bindgen::to_value(&event).unwrap(),
+            DebugStep::Solution(solution) => {
+                let result = js_sys::Object::new();
+                js_sys::Reflect::set(&result, &"solution".into(), &solution_to_js(solution))
+                    .unwrap();
+                result.into()
+            }
+            DebugStep::Done => JsValue::UNDEFINED,
+        })
+    }
 }
 
 impl Term {
     pub fn from_heap(heap: &Heap, ptr: HeapTermPtr) -> Self {
         let ptr = heap.get_ptr(ptr);
         match heap.get(ptr) {
-            HeapTerm::Atom(Atom::String(id)) => Term::String(heap.get_atom(*id).to_string()),
-            HeapTerm::Atom(Atom::Integer(i)) => Term::Number(*i as f64),
-            HeapTerm::Atom(Atom::Float(f)) => Term::Number(*f),
-            HeapTerm::Var(ptr, _, _, _) => Term::Variable(*ptr),
+            HeapTerm::Atom(Atom::String(id)) => Term::String(heap.get_atom(id).to_string()),
+            HeapTerm::Atom(Atom::Integer(i)) => Term::Number(i as f64),
+            HeapTerm::Atom(Atom::Float(f)) => Term::Number(f),
+            HeapTerm::Atom(Atom::Text(id)) => Term::Text(heap.get_atom(id).to_string()),
+            HeapTerm::Var(ptr) => Term::Variable(ptr),
             HeapTerm::Compound(functor, arity) => Term::Compound(
-                heap.get_atom(*functor).to_string(),
-                (1..=*arity)
+                heap.get_atom(functor).to_string(),
+                (1..=arity)
                     .map(|i| Term::from_heap(heap, ptr + i))
                     .collect::<Vec<_>>(),
             ),
-            _ => panic!("Invalid term"),
+            HeapTerm::Cut(_) => Term::String("!".into()),
+            HeapTerm::Lambda(_, _) => Term::String("<js_function>".into()),
+        }
+    }
+
+    pub fn atom(name: &str) -> Self {
+        Term::String(name.into())
+    }
+
+    pub fn compound(functor: &str, args: Vec<Term>) -> Self {
+        Term::Compound(functor.into(), args)
+    }
+
+    pub fn indicator(name: &str, arity: usize) -> Self {
+        Term::compound("/", vec![Term::atom(name), Term::Number(arity as f64)])
+    }
+
+    fn is_atomic(&self) -> bool {
+        !matches!(self, Term::Compound(_, _))
+    }
+}
+
+// Atoms are quoted where they need to be to read back, and `/` and `:`
+// between atomic terms are written as operators, as in `foo/2`
+impl std::fmt::Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Term::String(s) => f.write_str(&quote(s)),
+            Term::Number(n) => write!(f, "{}", n),
+            Term::Variable(ptr) => write!(f, "_{}", ptr),
+            Term::Text(s) => f.write_str(&quote_string(s)),
+            Term::Compound(functor, args) => match args.as_slice() {
+                [a, b] if (functor == "/" || functor == ":") && a.is_atomic() && b.is_atomic() => {
+                    write!(f, "{}{}{}", a, functor, b)
+                }
+                _ => {
+                    write!(f, "{}(", quote(functor))?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            f.write_str(",")?;
+                        }
+                        write!(f, "{}", arg)?;
+                    }
+                    f.write_str(")")
+                }
+            },
         }
     }
 }
@@ -113,6 +325,11 @@ impl Serialize for Term {
                 obj.serialize_field("variable", ptr)?;
                 obj.end()
             }
+            Term::Text(s) => {
+                let mut obj = serializer.serialize_struct("string", 1)?;
+                obj.serialize_field("string", s)?;
+                obj.end()
+            }
             Term::Compound(functor, args) => {
                 let mut obj = serializer.serialize_struct("compound", 2)?;
                 obj.serialize_field("functor", functor)?;
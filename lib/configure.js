@@ -0,0 +1,13 @@
+/**
+ * Enables garbage collection and the profiler on a new solver.
+ * @param {import("./wasm/webpl.js").Solver} solver
+ * @param {boolean | { heapSizeThreshold?: number, heapPressureThreshold?: number, cooldown?: number, markBudget?: number }} gc
+ * @param {boolean} profile
+ */
+export function configure(solver, gc, profile) {
+  if (gc) {
+    let { heapSizeThreshold, heapPressureThreshold, cooldown, markBudget } = gc === true ? {} : gc;
+    solver.configure_gc(heapSizeThreshold, heapPressureThreshold, cooldown, markBudget);
+  }
+  if (profile) solver.enable_profiler();
+}
@@ -1,15 +1,85 @@
 declare module "webpl" {
+  export class Program {
+    /**
+     * Compiles a program so that it can be queried any number of times.
+     * 
+     * @param src The program source, or a compiled image created by `toBytes` or `webpl compile`.
+     * @returns A Promise that resolves to the compiled program.
+     */
+    static load(src: string | Uint8Array): Promise<Program>;
+
+    /**
+     * Finds every syntax error in a program, recovering at the end of each clause.
+     * 
+     * @param src The program source.
+     * @returns A Promise resolving to the errors, which is empty if the program parses.
+     */
+    static check(src: string): Promise<Error[]>;
+
+    /**
+     * Gets the compiled image of this program, which loads faster than its source.
+     * 
+     * @returns A Promise resolving to the compiled image.
+     */
+    toBytes(): Promise<Uint8Array>;
+
+    /**
+     * Gets the likely mistakes found when compiling this program from source, such as
+     * singleton variables or calls to unknown predicates. Compiled images have none.
+     * 
+     * @returns A Promise resolving to the warnings.
+     */
+    warnings(): Promise<Warning[]>;
+
+    /**
+     * Sets up a solver for the given query against this program.
+     * Only one solver is active at a time.
+     * 
+     * @param query The query to run.
+     * @param gc Whether to enable garbage collection, or when to run it.
+     * @param profile Whether to enable the profiler.
+     * @returns A Promise that resolves to a Solver instance.
+     */
+    query(query: string, gc?: boolean | GCConfig, profile?: boolean): Promise<Solver>;
+  }
+
   export class Solver {
     /**
      * Sets up the solver with the given program and query.
-     * Optionally uses garbage collection.
+     * Optionally uses garbage collection and the profiler.
      * 
      * @param program The program source.
      * @param query The query to run.
-     * @param gc Whether to enable garbage collection.
+     * @param gc Whether to enable garbage collection, or when to run it.
+     * @param profile Whether to enable the profiler.
+     * @returns A Promise that resolves to a Solver instance.
+     */
+    static solve(program: string, query: string, gc?: boolean | GCConfig, profile?: boolean): Promise<Solver>;
+
+    /**
+     * Restores a solver from a snapshot, which continues with the remaining solutions.
+     * The profiler, tracer and debugger are not restored.
+     * 
+     * @param snapshot A snapshot created by `snapshot`.
      * @returns A Promise that resolves to a Solver instance.
      */
-    static solve(program: string, query: string, gc?: boolean): Promise<Solver>;
+    static restore(snapshot: Uint8Array): Promise<Solver>;
+
+    /**
+     * Serializes the full state of the solver between calls to `next`.
+     * 
+     * @returns A Promise resolving to the snapshot.
+     */
+    snapshot(): Promise<Uint8Array>;
+
+    /**
+     * Appends text to the input that `read_term/2` reads from, one term at a time.
+     * Once the input runs out, `read_term/2` reads `end_of_file`.
+     * 
+     * @param text The text to append.
+     * @returns A Promise that resolves once the text is added.
+     */
+    addInput(text: string): Promise<void>;
 
     /**
      * Gets the next result from the solver.
@@ -24,6 +94,171 @@ declare module "webpl" {
      * @returns A Promise resolving to all results.
      */
     all(): Promise<Map<string, string>[]>;
+
+    /**
+     * Gets the profiler report, if the profiler is enabled.
+     * 
+     * @returns A Promise resolving to the per-predicate port counts and times.
+     */
+    profile(): Promise<ProfileEntry[] | undefined>;
+
+    /**
+     * Gets the warnings about the program, followed by those about the query.
+     * 
+     * @returns A Promise resolving to the warnings.
+     */
+    warnings(): Promise<Warning[]>;
+
+    /**
+     * Gets what the most recent garbage collection did.
+     * 
+     * @returns A Promise resolving to the collection's record, or undefined if there wasn't one.
+     */
+    lastGC(): Promise<GCRecord | undefined>;
+
+    /**
+     * Sends the port events of traced and spied goals to a callback, as they happen.
+     * 
+     * @param callback Called with each event.
+     */
+    setTracer(callback: (event: TraceEvent) => void): Promise<void>;
+
+    /**
+     * Traces every goal, as `trace/0` does, or only spied ones.
+     * 
+     * @param tracing Whether to trace every goal.
+     */
+    setTracing(tracing: boolean): Promise<void>;
+
+    /**
+     * Sets a spy point, as `spy/1` does.
+     * 
+     * @param name The name of the predicate.
+     * @param arity The arity of the predicate, or any arity if omitted.
+     */
+    spy(name: string, arity?: number): Promise<void>;
+
+    /**
+     * Removes a spy point set by `spy`.
+     * 
+     * @param name The name of the predicate.
+     * @param arity The arity of the predicate, or every arity if omitted.
+     */
+    nospy(name: string, arity?: number): Promise<void>;
+
+    /**
+     * Runs the solver step-by-step in debug mode. While the debugger is paused,
+     * `next` and `all` reject with a `system` error instead of ending.
+     * 
+     * @param command How far to advance before stopping again.
+     * @returns A Promise resolving to the next port event, the next solution, or undefined when done.
+     */
+    debug(command?: DebugCommand): Promise<TraceEvent | { solution: Map<string, string> } | undefined>;
+  }
+
+  export interface Error {
+    kind:
+      | "syntax"
+      | "instantiation"
+      | "type"
+      | "domain"
+      | "existence"
+      | "evaluation"
+      | "representation"
+      | "resource"
+      | "javascript"
+      | "system";
+    error: string;
+    location?: {
+      query: boolean;
+      offset: number;
+      line: number;
+      column: number;
+      end: number;
+      end_line: number;
+      end_column: number;
+      /** The offsets in UTF-16 code units, for indexing into JavaScript strings. */
+      utf16_offset: number;
+      utf16_end: number;
+      /** For runtime errors, the predicate indicator of the clause containing the goal. */
+      predicate?: string;
+      clause?: number;
+    };
+    /** For runtime errors, the goals that were running, innermost first. */
+    backtrace: Frame[];
+    /** The term that caused the error, such as `foo/0` in `X is foo`. */
+    culprit?: string;
+    /** The type or domain the culprit should have been in, such as `evaluable`. */
+    expected?: string;
+    /** The error as ISO Prolog would throw it, such as `error(type_error(evaluable,foo/0),is/2)`. */
+    term?: {
+      text: string;
+      term: Term;
+    };
+  }
+
+  /**
+   * A Prolog term: atoms are strings and numbers are numbers. Strings read
+   * from double quotes are `{ string }`.
+   */
+  export type Term =
+    | string
+    | number
+    | { string: string }
+    | { variable: number }
+    | { functor: string; args: Term[] };
+
+  export interface Frame {
+    /** The goal, with deeply nested arguments elided. */
+    goal: string;
+    /** Where the goal was called. */
+    location?: Error["location"];
+  }
+
+  export interface Warning {
+    warning: string;
+    location?: Error["location"];
+  }
+
+  export type DebugCommand = "creep" | "skip" | "leap" | "retry";
+
+  export interface TraceEvent {
+    port: "call" | "redo" | "exit" | "fail" | "exception";
+    goal: string;
+    depth: number;
+    clause?: number;
+    choice_point: number;
+  }
+
+  export interface GCConfig {
+    /** Minimum number of heap cells before collecting (default 1024). */
+    heapSizeThreshold?: number;
+    /** Minimum ratio of used to allocated heap memory before collecting (default 0.9). */
+    heapPressureThreshold?: number;
+    /** Minimum number of steps between collections (default 16). */
+    cooldown?: number;
+    /** Marks at most this many cells per step, spreading each collection over several steps. */
+    markBudget?: number;
+  }
+
+  export interface GCRecord {
+    full: boolean;
+    cells_before: number;
+    cells_after: number;
+    trail_removed: number;
+    choice_points_scanned: number;
+    slices: number;
+    time: number;
+    max_pause: number;
+  }
+
+  export interface ProfileEntry {
+    predicate: string;
+    calls: number;
+    redos: number;
+    exits: number;
+    fails: number;
+    time: number;
   }
 
   /**
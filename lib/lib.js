@@ -1,4 +1,5 @@
-import initWasm, { Solver as SolverWasm } from "./wasm/webpl.js";
+import initWasm, { Program as ProgramWasm, Solver as SolverWasm } from "./wasm/webpl.js";
+import { configure } from "./configure.js";
 
 /**
  * The worker that will run everything in the background.
@@ -14,9 +15,82 @@ let solver;
 
 let useWorker;
 
+let nextProgram = 0;
+
 let pending = new Map();
 let nextId = 0;
 
+/**
+ * The callback for trace events, which the worker sends as they happen.
+ * @type {((event: object) => void) | undefined}
+ */
+let tracer;
+
+export class Program {
+  /**
+   * @param {number | ProgramWasm} program The program's id in the worker, or the program itself.
+   */
+  constructor(program) {
+    this.program = program;
+  }
+
+  /**
+   * Compiles a program that can be queried any number of times.
+   * The program can also be a compiled image, e.g. from `webpl compile`.
+   * @param {string | Uint8Array} src
+   * @returns {Promise<Program>}
+   */
+  static async load(src) {
+    if (useWorker) {
+      let id = nextProgram++;
+      await post("load", { id, src });
+      return new Program(id);
+    } else {
+      return new Program(src instanceof Uint8Array ? ProgramWasm.from_bytes(src) : ProgramWasm.load(src));
+    }
+  }
+
+  /**
+   * Finds every syntax error in a program, rather than only the first.
+   * @param {string} src
+   * @returns {Promise<object[]>}
+   */
+  static check(src) {
+    return useWorker ? post("check", { src }) : Promise.resolve(ProgramWasm.check(src));
+  }
+
+  /**
+   * Gets the compiled image of this program, which loads faster than its source.
+   * @returns {Promise<Uint8Array>}
+   */
+  toBytes() {
+    return useWorker ? post("to_bytes", { program: this.program }) : Promise.resolve(this.program.to_bytes());
+  }
+
+  /**
+   * Gets the warnings found when compiling this program from source.
+   * @returns {Promise<object[]>}
+   */
+  warnings() {
+    return useWorker ? post("program_warnings", { program: this.program }) : Promise.resolve(this.program.warnings());
+  }
+
+  /**
+   * Sets up a solver for the given query against this program.
+   * @param {string} query
+   * @returns {Promise<Solver>}
+   */
+  async query(query, gc = false, profile = false) {
+    if (useWorker) {
+      await post("query", { program: this.program, query, gc, profile });
+    } else {
+      solver = this.program.query(query);
+      configure(solver, gc, profile);
+    }
+    return new Solver();
+  }
+}
+
 export class Solver {
   /**
    * Sets up the solver.
@@ -24,16 +98,47 @@ export class Solver {
    * @param {string} query
    * @returns {Promise<Solver>}
    */x
-  static async solve(program, query, gc = false) {
+  static async solve(program, query, gc = false, profile = false) {
     if (useWorker) {
-      await post("solve", { program, query, gc });
+      await post("solve", { program, query, gc, profile });
       return new Solver();
     } else {
-      solver = gc ? SolverWasm.new_with_gc(program, query) : new SolverWasm(program, query);
+      solver = new SolverWasm(program, query);
+      configure(solver, gc, profile);
       return new Solver();
     }
   }
 
+  /**
+   * Restores a solver from a snapshot, to continue where it left off.
+   * @param {Uint8Array} snapshot
+   * @returns {Promise<Solver>}
+   */
+  static async restore(snapshot) {
+    if (useWorker) {
+      await post("restore", { snapshot });
+    } else {
+      solver = SolverWasm.restore(snapshot);
+    }
+    return new Solver();
+  }
+
+  /**
+   * Serializes the state of the solver, e.g. to store it or move it to another worker.
+   * @returns {Promise<Uint8Array>}
+   */
+  snapshot() {
+    return useWorker ? post("snapshot") : Promise.resolve(solver.snapshot());
+  }
+
+  /**
+   * Appends text to the input that read_term/2 reads from.
+   * @param {string} text
+   */
+  addInput(text) {
+    return useWorker ? post("add_input", { text }) : Promise.resolve(solver.add_input(text));
+  }
+
   next() {
     return useWorker ? post("next") : Promise.resolve(solver.next());
   }
@@ -41,6 +146,65 @@ export class Solver {
   all() {
     return useWorker ? post("all") : Promise.resolve(solver.all());
   }
+
+  profile() {
+    return useWorker ? post("profile") : Promise.resolve(solver.profile());
+  }
+
+  warnings() {
+    return useWorker ? post("warnings") : Promise.resolve(solver.warnings());
+  }
+
+  lastGC() {
+    return useWorker ? post("last_gc") : Promise.resolve(solver.last_gc());
+  }
+
+  /**
+   * Sends the port events of traced and spied goals to a callback.
+   * @param {(event: object) => void} callback
+   */
+  setTracer(callback) {
+    if (useWorker) {
+      tracer = callback;
+      return post("set_tracer");
+    } else {
+      return Promise.resolve(solver.set_tracer(callback));
+    }
+  }
+
+  /**
+   * Traces every goal, as trace/0 does, or only spied ones.
+   * @param {boolean} tracing
+   */
+  setTracing(tracing) {
+    return useWorker ? post("set_tracing", { tracing }) : Promise.resolve(solver.set_tracing(tracing));
+  }
+
+  /**
+   * Sets a spy point on a predicate, with any arity if none is given.
+   * @param {string} name
+   * @param {number} [arity]
+   */
+  spy(name, arity) {
+    return useWorker ? post("spy", { name, arity }) : Promise.resolve(solver.spy(name, arity));
+  }
+
+  /**
+   * Removes a spy point set by `spy`.
+   * @param {string} name
+   * @param {number} [arity]
+   */
+  nospy(name, arity) {
+    return useWorker ? post("nospy", { name, arity }) : Promise.resolve(solver.nospy(name, arity));
+  }
+
+  /**
+   * Advances the debugger to the next port event or solution.
+   * @param {"creep" | "skip" | "leap" | "retry"} command
+   */
+  debug(command = "creep") {
+    return useWorker ? post("debug", { command }) : Promise.resolve(solver.debug_step(command));
+  }
 }
 
 /**
@@ -57,10 +221,15 @@ function post(fn, data) {
 }
 
 /**
- * Handles responses from the worker.
- * @param {{ id: number, ok: boolean, data: any }}
+ * Handles responses and trace events from the worker.
+ * @param {{ id: number, ok: boolean, data: any, event?: object }}
  */
-function recv({ id, ok, data }) {
+function recv({ id, ok, data, event }) {
+  if (event) {
+    tracer?.(event);
+    return;
+  }
+
   let { res, rej } = pending.get(id);
   if (ok) res(data);
   else rej(data);
@@ -1,10 +1,17 @@
-import init, { Solver } from "./wasm/webpl.js";
+import init, { Program, Solver } from "./wasm/webpl.js";
+import { configure } from "./configure.js";
 
 /**
  * @type {Solver}
  */
 let solver;
 
+/**
+ * Compiled programs, by id.
+ * @type {Map<number, Program>}
+ */
+let programs = new Map();
+
 function ok(id, data = undefined) {
   postMessage({ id, ok: true, data });
 }
@@ -19,8 +26,39 @@ onmessage = async e => {
         ok(id);
         break;
       case "solve":
-        solver = data.gc ? Solver.new_with_gc(data.program, data.query)
-          : new Solver(data.program, data.query);
+        solver = new Solver(data.program, data.query);
+        configure(solver, data.gc, data.profile);
+        ok(id);
+        break;
+      case "load":
+        programs.set(data.id, data.src instanceof Uint8Array ? Program.from_bytes(data.src) : Program.load(data.src));
+        ok(id);
+        break;
+      case "check":
+        ok(id, Program.check(data.src));
+        break;
+      case "program_warnings":
+        ok(id, programs.get(data.program).warnings());
+        break;
+      case "to_bytes":
+        ok(id, programs.get(data.program).to_bytes());
+        break;
+      case "query": {
+        let program = programs.get(data.program);
+        solver = program.query(data.query);
+        configure(solver, data.gc, data.profile);
+        ok(id);
+        break;
+      }
+      case "restore":
+        solver = Solver.restore(data.snapshot);
+        ok(id);
+        break;
+      case "snapshot":
+        ok(id, solver.snapshot());
+        break;
+      case "add_input":
+        solver.add_input(data.text);
         ok(id);
         break;
       case "next":
@@ -29,6 +67,34 @@ onmessage = async e => {
       case "all":
         ok(id, solver.all());
         break;
+      case "profile":
+        ok(id, solver.profile());
+        break;
+      case "warnings":
+        ok(id, solver.warnings());
+        break;
+      case "last_gc":
+        ok(id, solver.last_gc());
+        break;
+      case "set_tracer":
+        solver.set_tracer(event => postMessage({ event }));
+        ok(id);
+        break;
+      case "set_tracing":
+        solver.set_tracing(data.tracing);
+        ok(id);
+        break;
+      case "spy":
+        solver.spy(data.name, data.arity);
+        ok(id);
+        break;
+      case "nospy":
+        solver.nospy(data.name, data.arity);
+        ok(id);
+        break;
+      case "debug":
+        ok(id, solver.debug_step(data.command));
+        break;
     }
   } catch (e) {
     postMessage({ id, ok: false, data: e });
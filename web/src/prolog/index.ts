@@ -1,13 +1,25 @@
 export type Solution = Map<string, string>;
 
 export type Error = {
+  kind?: string;
   error: string;
   location?: {
     offset: number;
     line: number;
     column: number;
+    end: number;
+    end_line: number;
+    end_column: number;
+    utf16_offset: number;
+    utf16_end: number;
     query: boolean;
-  }
+    predicate?: string | null;
+    clause?: number | null;
+  };
+  backtrace?: {
+    goal: string;
+    location?: Error["location"] | null;
+  }[];
 };
 
 export default abstract class Prolog {
@@ -31,9 +31,19 @@ export default class WebPL extends Prolog {
 
   public handleError(e: Error): string {
     if (e.location) {
-      return `Error in ${e.location!.query ? "query" : "program"} (${e.location!.line}:${e.location!.column}): ${e.error}`;
+      const message = `Error in ${this.where(e.location)}: ${e.error}`;
+      const frames = (e.backtrace ?? [])
+        .slice(1)
+        .map(frame => `  ${frame.goal} at ${frame.location ? this.where(frame.location) : "?"}`);
+      return [message, ...frames].join("\n");
     } else {
       return e.error;
     }
   }
+
+  private where(location: NonNullable<Error["location"]>): string {
+    const { query, line, column, predicate, clause } = location;
+    const where = predicate ? `clause ${clause} of ${predicate}` : query ? "query" : "program";
+    return `${where} (${line}:${column})`;
+  }
 }
\ No newline at end of file